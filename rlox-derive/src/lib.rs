@@ -0,0 +1,76 @@
+//! `#[derive(LoxBind)]` for rlox, re-exported by `rlox` with the `derive`
+//! feature. See `rlox::bind::LoxBind` for the natives it defines.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+#[proc_macro_derive(LoxBind)]
+pub fn derive_lox_bind(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "LoxBind can't be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "LoxBind needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "LoxBind can only be derived for structs",
+            ))
+        }
+    };
+    let field_names: Vec<_> = fields.iter().filter_map(|f| f.ident.as_ref()).collect();
+    let arity = field_names.len();
+    let indices = 0..arity;
+    let constructor = LitStr::new(&name.to_string(), Span::call_site());
+    let accessors = field_names.iter().map(|field| {
+        let getter = LitStr::new(&format!("{name}_{field}"), Span::call_site());
+        let setter = LitStr::new(&format!("{name}_set_{field}"), Span::call_site());
+        let field = format_ident!("{}", field);
+        quote! {
+            interpreter.define_native(#getter, 1, |_, arguments| {
+                let object = ::rlox::bind::object::<#name>(&arguments[0])?;
+                let value = ::std::clone::Clone::clone(&object.borrow().#field);
+                Ok(::rlox::bind::IntoValue::into_value(value))
+            });
+            interpreter.define_native(#setter, 2, |_, arguments| {
+                let object = ::rlox::bind::object::<#name>(&arguments[0])?;
+                object.borrow_mut().#field = ::rlox::bind::FromValue::from_value(&arguments[1])?;
+                Ok(arguments[1].clone())
+            });
+        }
+    });
+    Ok(quote! {
+        impl ::rlox::bind::LoxBind for #name {
+            fn bind(interpreter: &mut ::rlox::interpreter::Interpreter) {
+                interpreter.define_native(#constructor, #arity, |_, arguments| {
+                    let object = #name {
+                        #(#field_names: ::rlox::bind::FromValue::from_value(&arguments[#indices])?,)*
+                    };
+                    Ok(::rlox::bind::LoxBind::into_object(object))
+                });
+                #(#accessors)*
+            }
+        }
+    })
+}
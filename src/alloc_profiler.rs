@@ -0,0 +1,108 @@
+//! `--profile-allocations`: counts what each line of a script allocates.
+
+use rlox::hook::Hook;
+use rlox::interpreter::{Interpreter, RuntimeError};
+use rlox::parser::Stmt;
+use rlox::stats::{self, AllocationKind, Allocations};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Lines listed in the report, the most allocating first.
+const REPORT_LINES: usize = 20;
+
+/// What a run allocated. The allocations between two statements starting
+/// are charged to the line of the first, calls included.
+#[derive(Debug)]
+struct AllocationProfile {
+    line: Option<usize>,
+    since: Allocations,
+    lines: HashMap<usize, Allocations>,
+}
+
+impl AllocationProfile {
+    /// Charges the allocations since the last statement started. Those
+    /// before the first statement, made while parsing, aren't charged.
+    fn charge(&mut self) {
+        let now = stats::allocations();
+        let allocated = now.since(&self.since);
+        self.since = now;
+        if let Some(line) = self.line {
+            self.lines.entry(line).or_default().add(&allocated);
+        }
+    }
+}
+
+/// The hook counting allocations. Clones share the counts, so one can be
+/// given to the interpreter and the other report on the run.
+#[derive(Debug, Clone)]
+pub struct AllocationProfiler(Rc<RefCell<AllocationProfile>>);
+
+impl AllocationProfiler {
+    pub fn new() -> Self {
+        AllocationProfiler(Rc::new(RefCell::new(AllocationProfile {
+            line: None,
+            since: stats::allocations(),
+            lines: HashMap::new(),
+        })))
+    }
+
+    /// Charges what the last statement allocated, once the script is done.
+    pub fn finish(&self) {
+        let mut profile = self.0.borrow_mut();
+        profile.charge();
+        profile.line = None;
+    }
+
+    /// Allocations of each kind, then the lines allocating the most.
+    pub fn report(&self, origin: &str) -> String {
+        let profile = self.0.borrow();
+        let mut totals = Allocations::default();
+        for allocations in profile.lines.values() {
+            totals.add(allocations);
+        }
+        let mut report = String::new();
+        let _ = writeln!(report, "{:<24} {:>10}", "kind", "allocations");
+        for kind in AllocationKind::ALL {
+            let _ = writeln!(report, "{:<24} {:>10}", kind.name(), totals.get(kind));
+        }
+        let mut lines: Vec<_> = profile
+            .lines
+            .iter()
+            .filter(|(_, allocations)| allocations.total() > 0)
+            .collect();
+        lines.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(b.0)));
+        if lines.is_empty() {
+            return report;
+        }
+        let _ = write!(report, "\n{:<24} {:>10}", "line", "total");
+        for kind in AllocationKind::ALL {
+            let _ = write!(report, " {:>8}", kind.name());
+        }
+        report.push('\n');
+        for (line, allocations) in lines.into_iter().take(REPORT_LINES) {
+            let location = format!("{origin}:{line}");
+            let _ = write!(report, "{location:<24} {:>10}", allocations.total());
+            for kind in AllocationKind::ALL {
+                let _ = write!(report, " {:>8}", allocations.get(kind));
+            }
+            report.push('\n');
+        }
+        report
+    }
+}
+
+impl Hook for AllocationProfiler {
+    fn before_statement(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        let mut profile = self.0.borrow_mut();
+        profile.charge();
+        profile.line = Some(line);
+        Ok(())
+    }
+}
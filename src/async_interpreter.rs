@@ -0,0 +1,203 @@
+use crate::interpreter::{Interpreter, NativeFunction, RuntimeError};
+use crate::parser::{Expr, Stmt, Value};
+use crate::scanner::TokenType;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Future returned by an async native.
+pub type NativeFuture = Pin<Box<dyn Future<Output = Result<Value, RuntimeError>>>>;
+
+/// Signature of async Rust functions exposed to scripts. They take their
+/// arguments by value because the interpreter stays borrowed by the running
+/// script while they are awaited.
+pub type AsyncNativeFn = dyn Fn(Vec<Value>) -> NativeFuture;
+
+impl Interpreter {
+    /// Like `define_native`, for functions returning a future, such as
+    /// timers or network requests. Scripts calling them have to be run with
+    /// [`Interpreter::interpret_async`].
+    pub fn define_async_native<F, Fut>(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: F,
+    ) where
+        F: Fn(Vec<Value>) -> Fut + 'static,
+        Fut: Future<Output = Result<Value, RuntimeError>> + 'static,
+    {
+        let name = name.into();
+        let function: Box<AsyncNativeFn> =
+            Box::new(move |arguments| Box::pin(function(arguments)) as NativeFuture);
+        let native = NativeFunction::new_async(name.clone(), arity, function);
+        self.define_global(name, Value::NativeFunction(Rc::new(native)));
+    }
+
+    /// Runs the program like `interpret`, suspending while async natives are
+    /// awaited. Every call of one in top-level code is awaited, scripts
+    /// don't mark them. Functions declared in Lox run their body
+    /// synchronously, so async natives can't be called from them. The
+    /// interpreter isn't `Send`, so under tokio run this on a `LocalSet` or
+    /// with `block_on`.
+    pub async fn interpret_async(&mut self, statments: Vec<Stmt>) -> Result<(), RuntimeError> {
+        for stmt in statments.iter() {
+            self.execute_async(stmt).await?;
+            // Statements that weren't resolved can return from the top.
+            if self.take_return().is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute_async(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.observe(stmt)?;
+        let result = self.execute_stmt_async(stmt).await;
+        self.observe_error(result)
+    }
+
+    async fn execute_stmt_async(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        match stmt {
+            Stmt::Expression(e) => {
+                self.evaluate_async(e).await?;
+            }
+            Stmt::Print(e) => {
+                let value = self.evaluate_async(e).await?;
+                self.print_value(&value)?;
+            }
+            Stmt::Var { name, initializer } => {
+                match initializer {
+                    Some(expr) => {
+                        let value = self.evaluate_async(expr).await?;
+                        self.define_variable(name, value);
+                    }
+                    None => self.define_uninitialized(name),
+                }
+            }
+            Stmt::Block(statments) => {
+                self.begin_scope();
+                let mut result = Ok(());
+                for stmt in statments {
+                    result = Box::pin(self.execute_async(stmt)).await;
+                    if result.is_err() || self.is_returning() {
+                        break;
+                    }
+                }
+                self.end_scope();
+                result?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate_async(condition).await?.is_truthy() {
+                    Box::pin(self.execute_async(then_branch)).await?;
+                } else if let Some(else_branch) = else_branch {
+                    Box::pin(self.execute_async(else_branch)).await?;
+                }
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate_async(condition).await?.is_truthy() {
+                    Box::pin(self.execute_async(body)).await?;
+                    if self.is_returning() {
+                        break;
+                    }
+                }
+            }
+            Stmt::Import(name) => self.import_module(name)?,
+            Stmt::Function(declaration) => self.define_function(declaration),
+            Stmt::Class { name, methods } => self.define_class(name, methods),
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate_async(value).await?,
+                    None => Value::Nil,
+                };
+                self.set_return(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `evaluate`, awaiting async natives.
+    pub async fn evaluate_async(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let result = self.evaluate_expr_async(expr).await;
+        let value = self.observe_error(result)?;
+        self.with_hook(|hook, interpreter| hook.after_expression(interpreter, expr, &value));
+        Ok(value)
+    }
+
+    async fn evaluate_expr_async(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = Box::pin(self.evaluate_async(left)).await?;
+                let right = Box::pin(self.evaluate_async(right)).await?;
+                self.binary_operation(operator, left, right)
+            }
+            Expr::Unary { operator, right } => {
+                let right = Box::pin(self.evaluate_async(right)).await?;
+                self.unary_operation(operator, right)
+            }
+            Expr::Grouping(e) => Box::pin(self.evaluate_async(e)).await,
+            Expr::Literal { value, token } => self.literal(value, token),
+            Expr::Variable { name, distance } => self.look_up_variable(name, distance),
+            Expr::Assign {
+                name,
+                value,
+                distance,
+            } => {
+                let value = Box::pin(self.evaluate_async(value)).await?;
+                self.assign_variable(name, distance, value.clone())?;
+                Ok(value)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = Box::pin(self.evaluate_async(left)).await?;
+                match operator.token_type {
+                    TokenType::And if !left.is_truthy() => Ok(left),
+                    TokenType::Or if left.is_truthy() => Ok(left),
+                    _ => Box::pin(self.evaluate_async(right)).await,
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = Box::pin(self.evaluate_async(callee)).await?;
+                let mut values = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    values.push(Box::pin(self.evaluate_async(argument)).await?);
+                }
+                let function = Interpreter::check_call(&callee, paren, values.len())?;
+                let Some(function) = function.as_async() else {
+                    return self.call_value(callee, paren, values);
+                };
+                function(values).await.map_err(|e| e.or_at(paren))
+            }
+            Expr::Get { object, name } => {
+                let object = Box::pin(self.evaluate_async(object)).await?;
+                Interpreter::get_property(&object, name)
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = Box::pin(self.evaluate_async(object)).await?;
+                let instance = Interpreter::instance_to_set(&object, name)?;
+                let value = Box::pin(self.evaluate_async(value)).await?;
+                instance.set(name, value.clone());
+                Ok(value)
+            }
+            Expr::This { keyword, distance } => self.look_up_variable(keyword, distance),
+        }
+    }
+}
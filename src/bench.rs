@@ -0,0 +1,67 @@
+//! `rlox bench <file>`: runs a script repeatedly and reports how long the
+//! runs took.
+
+use rlox::{RunResult, Runner};
+use std::fmt::Write;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Runs `code` `warmup` times untimed, then `iterations` times timed, each
+/// time in a fresh runner from `new_runner` with its output thrown away.
+/// Setting up the runner isn't timed. Stops with the result of the first
+/// run that fails.
+pub fn run_bench(
+    code: &str,
+    mut new_runner: impl FnMut() -> Runner,
+    iterations: usize,
+    warmup: usize,
+) -> Result<Vec<Duration>, RunResult> {
+    let mut times = Vec::with_capacity(iterations);
+    for run in 0..warmup + iterations {
+        let mut runner = new_runner();
+        runner.interpreter_mut().set_output(io::sink());
+        let start = Instant::now();
+        let result = runner.run(code);
+        let elapsed = start.elapsed();
+        if !result.success() {
+            return Err(result);
+        }
+        if run >= warmup {
+            times.push(elapsed);
+        }
+    }
+    Ok(times)
+}
+
+/// Min, median, mean and standard deviation of the run times.
+pub fn report(origin: &str, times: &[Duration], warmup: usize) -> String {
+    let mut report = format!(
+        "{origin}: {} runs after {warmup} warmup runs\n",
+        times.len()
+    );
+    if times.is_empty() {
+        return report;
+    }
+    let mut sorted = times.to_vec();
+    sorted.sort();
+    let middle = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2
+    } else {
+        sorted[middle]
+    };
+    let seconds: Vec<f64> = times.iter().map(Duration::as_secs_f64).collect();
+    let mean = seconds.iter().sum::<f64>() / seconds.len() as f64;
+    let variance = seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / seconds.len() as f64;
+    let rows = [
+        ("min", sorted[0]),
+        ("median", median),
+        ("mean", Duration::from_secs_f64(mean)),
+        ("stddev", Duration::from_secs_f64(variance.sqrt())),
+        ("max", sorted[sorted.len() - 1]),
+    ];
+    for (name, time) in rows {
+        let _ = writeln!(report, "  {name:<8} {:>12}", format!("{time:.2?}"));
+    }
+    report
+}
@@ -0,0 +1,204 @@
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Bytes, Foreign, Key, List, Map, Value};
+use std::any::Any;
+use std::cell::RefCell;
+
+/// A Rust type exposed to scripts, usually through `#[derive(LoxBind)]`.
+///
+/// For `struct Point { x: f64, y: f64 }` the derive defines the natives
+/// `Point(x, y)`, which makes a new object, `Point_x(point)` and
+/// `Point_y(point)`, which read a field, and `Point_set_x(point, value)` and
+/// `Point_set_y(point, value)`, which write one. Field types must implement
+/// [`FromValue`] and [`IntoValue`].
+pub trait LoxBind: Sized + 'static {
+    /// Defines the natives for this type in `interpreter`.
+    fn bind(interpreter: &mut Interpreter);
+
+    /// Wraps a value so scripts can pass it to the bound natives.
+    fn into_object(self) -> Value {
+        Value::Foreign(Foreign::named(
+            RefCell::new(self),
+            std::any::type_name::<Self>(),
+        ))
+    }
+}
+
+/// Borrows the Rust object inside a value made by [`LoxBind::into_object`].
+pub fn object<T: Any>(value: &Value) -> Result<&RefCell<T>, RuntimeError> {
+    value.downcast_ref().ok_or_else(|| {
+        RuntimeError::new(format!(
+            "Expected {} but got {}.",
+            std::any::type_name::<T>()
+                .rsplit("::")
+                .next()
+                .unwrap_or("object"),
+            value.type_name()
+        ))
+    })
+}
+
+/// Converts a script value into a Rust one, for native arguments.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError>;
+}
+
+/// Converts a Rust value into a script one, for native results.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => Ok(crate::decimal::to_f64(d)),
+            other => Err(expected("number", other)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            other => Err(expected("boolean", other)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            other => Err(expected("string", other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl FromValue for List {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(list) => Ok(list.clone()),
+            other => Err(expected("list", other)),
+        }
+    }
+}
+
+impl FromValue for Map {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Map(map) => Ok(map.clone()),
+            other => Err(expected("map", other)),
+        }
+    }
+}
+
+impl FromValue for Bytes {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Bytes(bytes) => Ok(bytes.clone()),
+            other => Err(expected("bytes", other)),
+        }
+    }
+}
+
+impl FromValue for Key {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(Key::Nil),
+            Value::Boolean(b) => Ok(Key::Boolean(*b)),
+            Value::Number(n) => {
+                Key::number(*n).ok_or_else(|| RuntimeError::new("NaN can't be a map key."))
+            }
+            Value::String(s) => Ok(Key::String(s.to_string())),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => Key::from_value(&Value::Number(crate::decimal::to_f64(d))),
+            other => Err(RuntimeError::new(format!(
+                "Only nil, booleans, numbers and strings can be map keys, not {}.",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(list) => list.borrow().iter().map(T::from_value).collect(),
+            other => Err(expected("list", other)),
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for Bytes {
+    fn into_value(self) -> Value {
+        Value::Bytes(self)
+    }
+}
+
+impl IntoValue for Key {
+    fn into_value(self) -> Value {
+        self.into()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Nil, IntoValue::into_value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(List::new(
+            self.into_iter().map(IntoValue::into_value).collect(),
+        ))
+    }
+}
+
+fn expected(type_name: &str, value: &Value) -> RuntimeError {
+    RuntimeError::new(format!(
+        "Expected {type_name} but got {}.",
+        value.type_name()
+    ))
+}
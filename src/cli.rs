@@ -0,0 +1,458 @@
+use crate::config::{apply_config_file, PROJECT_CONFIG_FILE};
+use rlox::dialect::Dialect;
+use rlox::diagnostic::{Diagnostic, DiagnosticFormat, DiagnosticRenderer, Warning, WarningConfig};
+use rlox::interpreter::Capability;
+use rlox::messages::Catalog;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::thread;
+
+pub const USAGE: &str = "\
+Usage: rlox [options] [file]
+       rlox <command> [options] [args]
+
+Commands:
+  run <file>      Run a Lox script, or the main.lox of a directory
+  repl            Start an interactive session (default without a file)
+  replay <file>   Run the inputs of a session recorded with :record
+  check <file>    Scan, parse and resolve a script without running it
+  lint <file>     Check a script with every warning enabled
+  fmt <file>      Print a script reformatted
+  ast <file>      Print the syntax tree of a script
+  tokens <file>   Print the tokens of a script
+  debug <file>    Run a Lox script under the debugger
+  test <dir>      Run every .lox file in a directory against its // expect comments
+  bench <file>    Run a Lox script repeatedly and report its run times
+  kernel <file>   Serve a Jupyter notebook, given its connection file
+  lsp             Serve the Language Server Protocol over stdin and stdout
+
+Options:
+  --config=<path>         Read options from a config file (default ./rlox.toml)
+  --time                  Report how long each phase took
+  --profile               Report the time spent in each function and line
+  --profile=<path>        Write the profile as folded stacks, for flamegraphs
+  --profile-allocations   Report the strings, lists, maps and scopes each line allocated
+  --trace                 Print each statement as it runs and the value it computed
+  --trace=expressions     Also print the value of every expression evaluated
+  --dump-on-error         Show the variables and calls where a runtime error was raised
+  --keep-going            After a runtime error, go on with the next top-level statement
+  --iterations <n>        Timed runs of bench (default 10)
+  --warmup <n>            Untimed runs of bench before the timed ones (default 3)
+  --suite                 Make test run the Crafting Interpreters test suite in jlox mode
+  --jobs <n>              Test files run at once by test (default: one per CPU)
+  --chapter <n>           With --suite, only run the tests of chapters up to n
+  --skip <path>           With --suite, skip a test file or directory of the suite
+  --stack-size <size>     Run on a thread with this much stack, like 64M, and let
+                          calls nest deeper before overflowing it
+  --no-rc                 Don't run ~/.rloxrc when the REPL starts
+  --no-prelude            Start without the Lox prelude definitions
+  --allow=<capability>    Let scripts use natives that need a capability: env, exec
+  --plugin=<path>         Load natives from a plugin shared library (repeatable)
+  --no-color              Never color diagnostics
+  --diagnostics=<format>  Diagnostic format: human (default), json or fancy
+  --max-errors <n>        Stop reporting after n errors, 0 for no limit (default 20)
+  --dialect=[no-]<name>   Change how the language behaves: ieee-division,
+                          string-comparison, strict-equality,
+                          uninitialized-error, checked-arithmetic, decimal
+                          for exact decimal numbers, or jlox to match the
+                          reference implementation
+  -Wall                   Enable all warnings
+  -W<name>, -Wno-<name>   Enable or disable a warning
+  --Werror                Treat warnings as errors
+  -h, --help              Print this help
+
+Environment:
+  RLOX_PATH               Directories where import looks for name.lox when it
+                          isn't next to the importing file";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Run(String),
+    Repl,
+    Replay(String),
+    Check(String),
+    Lint(String),
+    Fmt(String),
+    Ast(String),
+    Tokens(String),
+    Debug(String),
+    Test(String),
+    Bench(String),
+    Kernel(String),
+    Lsp,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color when stderr is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// What `--profile` produces after a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileOutput {
+    /// A table of functions and lines on stderr.
+    Report,
+    /// Folded stacks written to a file.
+    FoldedStacks(String),
+}
+
+/// What `--trace` prints as a script runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trace {
+    Statements,
+    Expressions,
+}
+
+#[derive(Debug)]
+pub struct CliOptions {
+    pub command: Command,
+    pub time: bool,
+    pub profile: Option<ProfileOutput>,
+    pub profile_allocations: bool,
+    pub trace: Option<Trace>,
+    pub dump_on_error: bool,
+    pub keep_going: bool,
+    pub iterations: usize,
+    pub warmup: usize,
+    pub suite: bool,
+    pub chapter: Option<usize>,
+    /// Test files run at once, one per CPU when `None`.
+    pub jobs: Option<usize>,
+    pub skips: Vec<String>,
+    pub no_rc: bool,
+    pub no_prelude: bool,
+    pub plugins: Vec<String>,
+    pub capabilities: Vec<Capability>,
+    pub color: ColorChoice,
+    pub diagnostics: DiagnosticFormat,
+    pub max_errors: usize,
+    pub warnings: WarningConfig,
+    pub dialect: Dialect,
+    pub messages: Catalog,
+    /// Stack of the thread commands run on, in bytes, the main thread's
+    /// when `None`.
+    pub stack_size: Option<usize>,
+}
+
+const DEFAULT_MAX_ERRORS: usize = 20;
+const DEFAULT_ITERATIONS: usize = 10;
+const DEFAULT_WARMUP: usize = 3;
+
+impl Default for CliOptions {
+    fn default() -> Self {
+        CliOptions {
+            command: Command::Repl,
+            time: false,
+            profile: None,
+            profile_allocations: false,
+            trace: None,
+            dump_on_error: false,
+            keep_going: false,
+            iterations: DEFAULT_ITERATIONS,
+            warmup: DEFAULT_WARMUP,
+            suite: false,
+            chapter: None,
+            jobs: None,
+            skips: Vec::new(),
+            no_rc: false,
+            no_prelude: false,
+            plugins: Vec::new(),
+            capabilities: Vec::new(),
+            color: ColorChoice::default(),
+            diagnostics: DiagnosticFormat::default(),
+            max_errors: DEFAULT_MAX_ERRORS,
+            warnings: WarningConfig::default(),
+            dialect: Dialect::default(),
+            messages: Catalog::new(),
+            stack_size: None,
+        }
+    }
+}
+
+impl CliOptions {
+    /// Parses the arguments after the program name. A first positional
+    /// argument that isn't a command is the script to run, so plain
+    /// `rlox file.lox` keeps working. Settings from the config file are
+    /// loaded first so flags can override them.
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<CliOptions, String> {
+        let args: Vec<String> = args.collect();
+        let mut options = CliOptions::default();
+        let config = args
+            .iter()
+            .rev()
+            .find_map(|arg| arg.strip_prefix("--config="));
+        match config {
+            Some(path) => apply_config_file(Path::new(path), &mut options)?,
+            None if Path::new(PROJECT_CONFIG_FILE).is_file() => {
+                apply_config_file(Path::new(PROJECT_CONFIG_FILE), &mut options)?
+            }
+            None => {}
+        }
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => options.command = Command::Help,
+                "--time" => options.time = true,
+                "--profile" => options.profile = Some(ProfileOutput::Report),
+                flag if flag.starts_with("--profile=") => {
+                    let path = flag["--profile=".len()..].to_string();
+                    options.profile = Some(ProfileOutput::FoldedStacks(path));
+                }
+                "--profile-allocations" => options.profile_allocations = true,
+                "--trace" => options.trace = Some(Trace::Statements),
+                "--trace=expressions" => options.trace = Some(Trace::Expressions),
+                "--dump-on-error" => options.dump_on_error = true,
+                "--keep-going" => options.keep_going = true,
+                "--iterations" | "--warmup" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    let count = parse_count(&arg, &value)?;
+                    if arg == "--iterations" {
+                        options.iterations = count;
+                    } else {
+                        options.warmup = count;
+                    }
+                }
+                flag if flag.starts_with("--iterations=") => {
+                    options.iterations = parse_count("--iterations", &flag["--iterations=".len()..])?
+                }
+                flag if flag.starts_with("--warmup=") => {
+                    options.warmup = parse_count("--warmup", &flag["--warmup=".len()..])?
+                }
+                "--stack-size" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    options.stack_size = Some(parse_stack_size(&value)?);
+                }
+                flag if flag.starts_with("--stack-size=") => {
+                    options.stack_size = Some(parse_stack_size(&flag["--stack-size=".len()..])?)
+                }
+                "--suite" => options.suite = true,
+                "--chapter" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    options.chapter = Some(parse_count(&arg, &value)?);
+                }
+                flag if flag.starts_with("--chapter=") => {
+                    options.chapter = Some(parse_count("--chapter", &flag["--chapter=".len()..])?)
+                }
+                "--jobs" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    options.jobs = Some(parse_count(&arg, &value)?);
+                }
+                flag if flag.starts_with("--jobs=") => {
+                    options.jobs = Some(parse_count("--jobs", &flag["--jobs=".len()..])?)
+                }
+                "--skip" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    options.skips.push(path);
+                }
+                flag if flag.starts_with("--skip=") => {
+                    options.skips.push(flag["--skip=".len()..].to_string())
+                }
+                "--no-rc" => options.no_rc = true,
+                "--no-prelude" => options.no_prelude = true,
+                "--no-color" => options.color = ColorChoice::Never,
+                // Passed by LSP clients, stdio being the only transport.
+                "--stdio" => {}
+                flag if flag.starts_with("--config=") => {}
+                flag if flag.starts_with("--allow=") => {
+                    let name = &flag["--allow=".len()..];
+                    let capability = Capability::from_name(name)
+                        .ok_or_else(|| format!("Unknown capability {name:?}"))?;
+                    options.capabilities.push(capability);
+                }
+                flag if flag.starts_with("--plugin=") => {
+                    options.plugins.push(flag["--plugin=".len()..].to_string())
+                }
+                "--diagnostics=human" => options.diagnostics = DiagnosticFormat::Human,
+                "--diagnostics=json" => options.diagnostics = DiagnosticFormat::Json,
+                #[cfg(feature = "fancy")]
+                "--diagnostics=fancy" => options.diagnostics = DiagnosticFormat::Fancy,
+                #[cfg(not(feature = "fancy"))]
+                "--diagnostics=fancy" => {
+                    return Err("This rlox was built without the fancy feature.".to_string())
+                }
+                "--max-errors" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {arg}"))?;
+                    options.max_errors = parse_error_limit(&value)?;
+                }
+                flag if flag.starts_with("--max-errors=") => {
+                    options.max_errors = parse_error_limit(&flag["--max-errors=".len()..])?
+                }
+                flag if flag.starts_with("--dialect=") => {
+                    let name = &flag["--dialect=".len()..];
+                    let (name, enabled) = match name.strip_prefix("no-") {
+                        Some(name) => (name, false),
+                        None => (name, true),
+                    };
+                    if !options.dialect.set(name, enabled) {
+                        return Err(format!(
+                            "Unknown dialect option {name:?} in {flag}, expected one of {}",
+                            Dialect::OPTIONS.join(", ")
+                        ));
+                    }
+                }
+                "--Werror" => options.warnings.warnings_as_errors = true,
+                "-Wall" => options.warnings.enable_all(),
+                flag if flag.starts_with("-W") => options.parse_warning_flag(flag)?,
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("Unknown option {flag}"))
+                }
+                _ => positional.push(arg),
+            }
+        }
+        if options.command == Command::Help {
+            return Ok(options);
+        }
+        let mut positional = positional.into_iter();
+        let command = positional.next();
+        let argument = positional.next();
+        if let Some(extra) = positional.next() {
+            return Err(format!("Unexpected argument {extra:?}"));
+        }
+        let required = |name: &str| {
+            argument
+                .clone()
+                .ok_or_else(|| format!("Missing argument for {name}"))
+        };
+        options.command = match command.as_deref() {
+            None => Command::Repl,
+            Some("repl") => Command::Repl,
+            Some("replay") => Command::Replay(required("replay")?),
+            Some("run") => Command::Run(required("run")?),
+            Some("check") => Command::Check(required("check")?),
+            Some("lint") => Command::Lint(required("lint")?),
+            Some("fmt") => Command::Fmt(required("fmt")?),
+            Some("ast") => Command::Ast(required("ast")?),
+            Some("tokens") => Command::Tokens(required("tokens")?),
+            Some("debug") => Command::Debug(required("debug")?),
+            Some("test") => Command::Test(required("test")?),
+            Some("bench") => Command::Bench(required("bench")?),
+            Some("kernel") => Command::Kernel(required("kernel")?),
+            Some("lsp") => Command::Lsp,
+            Some(file_path) => match argument {
+                None => Command::Run(file_path.to_string()),
+                Some(extra) => return Err(format!("Unexpected argument {extra:?}")),
+            },
+        };
+        Ok(options)
+    }
+
+    /// Test files to run at once: `--jobs`, or else one per CPU.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| thread::available_parallelism().ok().map(usize::from))
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    fn parse_warning_flag(&mut self, flag: &str) -> Result<(), String> {
+        let (name, enable) = match flag.strip_prefix("-Wno-") {
+            Some(name) => (name, false),
+            None => (&flag[2..], true),
+        };
+        match Warning::from_name(name) {
+            Some(warning) if enable => self.warnings.enable(warning),
+            Some(warning) => self.warnings.disable(warning),
+            None => return Err(format!("Unknown warning {name:?} in {flag}")),
+        }
+        Ok(())
+    }
+
+    pub fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Auto => io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+
+    pub fn reporter<'a>(&self, source: &'a str, origin: &'a str) -> Reporter<'a> {
+        let mut renderer = DiagnosticRenderer::new(source, origin, self.use_color());
+        if self.dialect.jlox {
+            renderer.set_format(DiagnosticFormat::Jlox);
+        } else {
+            renderer.set_format(self.diagnostics);
+        }
+        Reporter {
+            renderer,
+            max_errors: self.max_errors,
+            errors: 0,
+        }
+    }
+}
+
+/// Prints diagnostics to stderr, giving up on errors past `--max-errors` so
+/// one missing brace doesn't bury the first, useful message.
+pub struct Reporter<'a> {
+    renderer: DiagnosticRenderer<'a>,
+    max_errors: usize,
+    errors: usize,
+}
+
+impl Reporter<'_> {
+    pub fn report(&mut self, diagnostic: &Diagnostic) {
+        if diagnostic.is_error() {
+            self.errors += 1;
+            if self.max_errors != 0 && self.errors > self.max_errors {
+                return;
+            }
+        }
+        eprintln!("{}", self.renderer.render(diagnostic));
+    }
+
+    /// Notes how many errors were left out, if any.
+    pub fn finish(&self) {
+        if self.max_errors != 0 && self.errors > self.max_errors {
+            eprintln!(
+                "note: {} further errors suppressed (use --max-errors to raise the limit)",
+                self.errors - self.max_errors
+            );
+        }
+    }
+}
+
+/// A size in bytes, or in kibibytes, mebibytes or gibibytes with a `K`,
+/// `M` or `G` after it, like `64M`.
+pub fn parse_stack_size(value: &str) -> Result<usize, String> {
+    let (digits, shift) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 30),
+        _ => (value, 0),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(1 << shift))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| format!("Invalid stack size {value:?}, expected bytes like 8388608 or 64M"))
+}
+
+fn parse_error_limit(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid error limit {value:?}"))
+}
+
+fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid count {value:?} for {flag}"))
+}
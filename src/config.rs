@@ -0,0 +1,182 @@
+use crate::cli::{parse_stack_size, CliOptions, ColorChoice};
+use rlox::dialect::Dialect;
+use rlox::diagnostic::{DiagnosticFormat, Warning, WarningConfig};
+use rlox::interpreter::Capability;
+use rlox::messages;
+use std::fs;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Config file picked up from the current directory when `--config` isn't given.
+pub const PROJECT_CONFIG_FILE: &str = "rlox.toml";
+
+/// Applies the settings of a config file to `options`. Command-line flags
+/// are applied afterwards, so they take precedence.
+///
+/// ```toml
+/// allow = ["env"]
+/// color = "never"        # auto, always or never
+/// diagnostics = "json"   # human or json
+/// keep-going = true
+/// max-errors = 50
+/// plugins = ["./libextras.so"]
+/// prelude = false
+/// stack-size = "64M"     # or a number of bytes
+/// time = true
+///
+/// [dialect]
+/// ieee-division = true
+///
+/// [messages]             # rewords messages by code
+/// E0301 = "Невизначена змінна {name}."
+///
+/// [warnings]
+/// all = true
+/// as-errors = true
+/// unused = false
+/// ```
+pub fn apply_config_file(path: &Path, options: &mut CliOptions) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Cant read config {}: {e}", path.display()))?;
+    let table: Table = text
+        .parse()
+        .map_err(|e| format!("Invalid config {}: {e}", path.display()))?;
+    apply(&table, options).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn apply(table: &Table, options: &mut CliOptions) -> Result<(), String> {
+    for (key, value) in table {
+        match key.as_str() {
+            "allow" => {
+                for name in as_array(key, value)? {
+                    let name = as_str(key, name)?;
+                    let capability = Capability::from_name(name)
+                        .ok_or_else(|| format!("Unknown capability {name:?}"))?;
+                    options.capabilities.push(capability);
+                }
+            }
+            "color" => {
+                options.color = match as_str(key, value)? {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    other => {
+                        return Err(format!(
+                            "Invalid color {other:?}, expected auto, always or never"
+                        ))
+                    }
+                }
+            }
+            "diagnostics" => {
+                options.diagnostics = match as_str(key, value)? {
+                    "human" => DiagnosticFormat::Human,
+                    "json" => DiagnosticFormat::Json,
+                    #[cfg(feature = "fancy")]
+                    "fancy" => DiagnosticFormat::Fancy,
+                    other => {
+                        return Err(format!(
+                            "Invalid diagnostics format {other:?}, expected human, json or fancy"
+                        ))
+                    }
+                }
+            }
+            "keep-going" => options.keep_going = as_bool(key, value)?,
+            "max-errors" => {
+                options.max_errors = value
+                    .as_integer()
+                    .and_then(|n| usize::try_from(n).ok())
+                    .ok_or_else(|| format!("Expected a non-negative integer for {key:?}"))?
+            }
+            "plugins" => {
+                for plugin in as_array(key, value)? {
+                    options.plugins.push(as_str(key, plugin)?.to_string());
+                }
+            }
+            "prelude" => options.no_prelude = !as_bool(key, value)?,
+            "stack-size" => {
+                let size = match value {
+                    Value::String(size) => parse_stack_size(size)?,
+                    Value::Integer(size) => parse_stack_size(&size.to_string())?,
+                    _ => return Err(format!("Expected a string or integer for {key:?}")),
+                };
+                options.stack_size = Some(size);
+            }
+            "time" => options.time = as_bool(key, value)?,
+            "warnings" => {
+                let warnings = value
+                    .as_table()
+                    .ok_or_else(|| format!("Expected a table for {key:?}"))?;
+                apply_warnings(warnings, &mut options.warnings)?
+            }
+            "dialect" => {
+                let dialect = value
+                    .as_table()
+                    .ok_or_else(|| format!("Expected a table for {key:?}"))?;
+                for (name, enabled) in dialect {
+                    if !options.dialect.set(name, as_bool(name, enabled)?) {
+                        return Err(format!(
+                            "Unknown dialect option {name:?}, expected one of {}",
+                            Dialect::OPTIONS.join(", ")
+                        ));
+                    }
+                }
+            }
+            "messages" => {
+                let messages = value
+                    .as_table()
+                    .ok_or_else(|| format!("Expected a table for {key:?}"))?;
+                for (code, template) in messages {
+                    if !messages::ALL.iter().any(|message| message.code == code) {
+                        return Err(format!("Unknown message code {code:?}"));
+                    }
+                    options.messages.set(code, as_str(code, template)?);
+                }
+            }
+            _ => return Err(format!("Unknown key {key:?}")),
+        }
+    }
+    Ok(())
+}
+
+/// `all` is applied first so single warnings can still be turned off.
+fn apply_warnings(table: &Table, warnings: &mut WarningConfig) -> Result<(), String> {
+    if let Some(all) = table.get("all") {
+        if as_bool("all", all)? {
+            warnings.enable_all();
+        } else {
+            for warning in Warning::ALL {
+                warnings.disable(warning);
+            }
+        }
+    }
+    for (key, value) in table {
+        match key.as_str() {
+            "all" => {}
+            "as-errors" => warnings.warnings_as_errors = as_bool(key, value)?,
+            name => match Warning::from_name(name) {
+                Some(warning) if as_bool(key, value)? => warnings.enable(warning),
+                Some(warning) => warnings.disable(warning),
+                None => return Err(format!("Unknown warning {name:?}")),
+            },
+        }
+    }
+    Ok(())
+}
+
+fn as_str<'a>(key: &str, value: &'a Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("Expected a string for {key:?}"))
+}
+
+fn as_array<'a>(key: &str, value: &'a Value) -> Result<&'a Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("Expected an array for {key:?}"))
+}
+
+fn as_bool(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("Expected true or false for {key:?}"))
+}
@@ -0,0 +1,301 @@
+//! `rlox debug <file>`: runs a script under a debugger prompt, stopping at
+//! the first statement, at breakpoints and after each step.
+
+use rlox::hook::Hook;
+use rlox::interpreter::{Binding, Interpreter, RuntimeError};
+use rlox::parser::{Parser, Stmt, Value};
+use rlox::scanner::Scanner;
+use rustyline::DefaultEditor;
+use std::collections::{BTreeSet, VecDeque};
+use std::path::Path;
+
+const HELP: &str = "\
+Commands:
+  break <line>, b         Stop at a line, also written file:line
+  delete <line>           Remove a breakpoint
+  breakpoints             List breakpoints
+  step, s                 Run the next statement, entering calls
+  next, n                 Run until the next statement of the current call
+  continue, c             Run until a breakpoint
+  frames, bt              List the calls in progress
+  locals                  Show the variables of the enclosing blocks
+  globals                 Show the global variables, without natives
+  print <expr>, p         Evaluate an expression where the script stopped
+  list, l                 Show the source around the current line
+  quit, q                 Stop the script
+  help, h                 Print this help";
+
+/// Lines shown on each side of the current one by `list`.
+const LIST_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    /// Stop at the next statement.
+    Step,
+    /// Stop at the next statement in a call at most this deep.
+    Next(usize),
+    /// Stop only at breakpoints.
+    Continue,
+}
+
+/// Where debugger commands are read from.
+enum Commands {
+    Editor(Box<DefaultEditor>),
+    /// Commands given up front, for tests. The script stops after the last.
+    #[cfg_attr(not(test), allow(dead_code))]
+    Script(VecDeque<String>),
+}
+
+pub struct Debugger {
+    file: String,
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    /// Line of the statement before, so a line with several statements
+    /// only hits its breakpoint once.
+    last_line: Option<usize>,
+    commands: Option<Commands>,
+}
+
+impl Debugger {
+    pub fn new(file: &str, source: &str) -> Self {
+        Debugger {
+            file: file.to_string(),
+            source: source.lines().map(str::to_string).collect(),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last_line: None,
+            commands: DefaultEditor::new().ok().map(|e| Commands::Editor(Box::new(e))),
+        }
+    }
+
+    fn should_stop(&self, interpreter: &Interpreter, line: usize) -> bool {
+        match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) if interpreter.call_stack().len() <= depth => true,
+            _ => self.breakpoints.contains(&line) && self.last_line != Some(line),
+        }
+    }
+
+    /// Reads commands until one resumes the script.
+    fn prompt(&mut self, interpreter: &mut Interpreter, line: usize) -> Result<(), RuntimeError> {
+        loop {
+            let input = self.read_command()?;
+            let (command, argument) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+            let argument = argument.trim();
+            match command {
+                "" => {}
+                "step" | "s" => {
+                    self.mode = Mode::Step;
+                    return Ok(());
+                }
+                "next" | "n" => {
+                    self.mode = Mode::Next(interpreter.call_stack().len());
+                    return Ok(());
+                }
+                "continue" | "c" => {
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                }
+                "quit" | "q" => return Err(RuntimeError::new("Stopped by the debugger.")),
+                "break" | "b" => match self.breakpoint_line(argument) {
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                        println!("Breakpoint at {}:{line}", self.file);
+                    }
+                    Err(e) => eprintln!("{e}"),
+                },
+                "delete" => match self.breakpoint_line(argument) {
+                    Ok(line) if self.breakpoints.remove(&line) => {
+                        println!("Removed breakpoint at {}:{line}", self.file)
+                    }
+                    Ok(line) => eprintln!("No breakpoint at {}:{line}", self.file),
+                    Err(e) => eprintln!("{e}"),
+                },
+                "breakpoints" => {
+                    for line in self.breakpoints.iter() {
+                        println!("{}:{line}", self.file);
+                    }
+                }
+                "frames" | "bt" => {
+                    for frame in interpreter.call_stack().iter().rev() {
+                        println!(
+                            "  {} called at {}:{}",
+                            frame.function, self.file, frame.line
+                        );
+                    }
+                    println!("  <script> at {}:{line}", self.file);
+                }
+                "locals" => {
+                    if interpreter.locals().all(|scope| scope.is_empty()) {
+                        println!("No local variables.");
+                    }
+                    for (depth, scope) in interpreter.locals().enumerate() {
+                        for binding in scope.sorted() {
+                            println!("  [{depth}] {}", self.describe(&binding));
+                        }
+                    }
+                }
+                "globals" => {
+                    for binding in interpreter.globals().sorted() {
+                        if !matches!(binding.value, Value::NativeFunction(_)) {
+                            println!("  {}", self.describe(&binding));
+                        }
+                    }
+                }
+                "print" | "p" => match evaluate(interpreter, argument) {
+                    Ok(value) => println!("{value:?}"),
+                    Err(e) => eprintln!("error: {e}"),
+                },
+                "list" | "l" => self.list(line),
+                "help" | "h" => println!("{HELP}"),
+                _ => eprintln!("Unknown command {command:?}, try help."),
+            }
+        }
+    }
+
+    fn read_command(&mut self) -> Result<String, RuntimeError> {
+        let stopped = || RuntimeError::new("Stopped by the debugger.");
+        match &mut self.commands {
+            Some(Commands::Editor(editor)) => {
+                let input = editor.readline("(debug) ").map_err(|_| stopped())?;
+                let _ = editor.add_history_entry(input.as_str());
+                Ok(input)
+            }
+            Some(Commands::Script(commands)) => commands.pop_front().ok_or_else(stopped),
+            None => Err(RuntimeError::new("Cant read debugger commands.")),
+        }
+    }
+
+    /// Reads `line` or `file:line`, where the file has to be the script.
+    fn breakpoint_line(&self, argument: &str) -> Result<usize, String> {
+        let (file, line) = match argument.rsplit_once(':') {
+            Some((file, line)) => (Some(file), line),
+            None => (None, argument),
+        };
+        if let Some(file) = file {
+            if Path::new(file).file_name() != Path::new(&self.file).file_name() {
+                return Err(format!("Only {} can have breakpoints", self.file));
+            }
+        }
+        match line.parse() {
+            Ok(line) if line >= 1 => Ok(line),
+            _ => Err(format!("Invalid line {line:?}")),
+        }
+    }
+
+    /// `name: type = value`, with the line of the declaration if known.
+    fn describe(&self, binding: &Binding) -> String {
+        let (name, type_name) = (&binding.name, binding.type_name());
+        let mut text = format!("{name}: {type_name} = {:?}", binding.value);
+        if let Some(line) = binding.line {
+            text.push_str(&format!(" (line {line})"));
+        }
+        text
+    }
+
+    fn list(&self, line: usize) {
+        let first = line.saturating_sub(LIST_CONTEXT).max(1);
+        let last = (line + LIST_CONTEXT).min(self.source.len());
+        for number in first..=last {
+            let marker = if number == line { "->" } else { "  " };
+            println!("{marker} {number:>4} {}", self.source[number - 1]);
+        }
+    }
+}
+
+impl Hook for Debugger {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        _stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        let stop = self.should_stop(interpreter, line);
+        self.last_line = Some(line);
+        if !stop {
+            return Ok(());
+        }
+        let text = self.source.get(line - 1).map_or("", |text| text.trim());
+        println!("Stopped at {}:{line}: {text}", self.file);
+        self.prompt(interpreter, line)
+    }
+}
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<Value, String> {
+    let mut scanner = Scanner::with_dialect(source, interpreter.dialect());
+    scanner.scan_tokens();
+    if let Some(e) = scanner.errors.first() {
+        return Err(e.message.clone());
+    }
+    let expr = Parser::new(scanner.tokens)
+        .parse_expression()
+        .map_err(|e| e.message)?;
+    interpreter.evaluate(&expr).map_err(|e| e.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox::testing::transcript;
+    use rlox::Runner;
+
+    /// Runs `source` under a debugger answering with `commands`.
+    fn debug(source: &str, commands: &[&str]) -> String {
+        let mut debugger = Debugger::new("test.lox", source);
+        let commands = commands.iter().map(|command| command.to_string()).collect();
+        debugger.commands = Some(Commands::Script(commands));
+        let mut runner = Runner::new();
+        runner.interpreter_mut().set_hook(debugger);
+        transcript(&mut runner, source)
+    }
+
+    #[test]
+    fn stops_at_the_first_statement() {
+        assert_eq!(
+            debug("print 1;\nprint 2;", &["q"]),
+            "runtime error: Stopped by the debugger.\n"
+        );
+        assert_eq!(debug("print 1;\nprint 2;", &["s", "c"]), "1\n2\n");
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        let source = "print 1;\nprint 2;\nprint 3;";
+        assert_eq!(
+            debug(source, &["b 3", "c", "q"]),
+            "1\n2\nruntime error: Stopped by the debugger.\n"
+        );
+        assert_eq!(debug(source, &["b test.lox:3", "delete 3", "c"]), "1\n2\n3\n");
+        assert_eq!(debug(source, &["b other.lox:3", "b 0", "c"]), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn steps_into_or_over_calls() {
+        let source = "fun f() {\n  print \"in\";\n}\nf();\nprint \"after\";";
+        assert_eq!(
+            debug(source, &["s", "s", "q"]),
+            "[line 4] runtime error: Stopped by the debugger.\n"
+        );
+        assert_eq!(
+            debug(source, &["s", "n", "q"]),
+            "in\nruntime error: Stopped by the debugger.\n"
+        );
+    }
+
+    #[test]
+    fn evaluates_where_the_script_stopped() {
+        let source = "var a = 1;\n{\n  var a = 2;\n  print a;\n}\nprint a;";
+        let commands = ["b 4", "c", "p a = a + 10", "b 6", "c", "p a = 5", "c"];
+        assert_eq!(debug(source, &commands), "12\n5\n");
+    }
+
+    #[test]
+    fn reads_breakpoint_lines() {
+        let debugger = Debugger::new("dir/test.lox", "");
+        assert_eq!(debugger.breakpoint_line("4"), Ok(4));
+        assert_eq!(debugger.breakpoint_line("other/test.lox:7"), Ok(7));
+        assert!(debugger.breakpoint_line("0").is_err());
+        assert!(debugger.breakpoint_line("x.lox:1").is_err());
+    }
+}
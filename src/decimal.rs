@@ -0,0 +1,110 @@
+use crate::messages::{self, Text};
+use crate::parser::Value;
+use crate::scanner::TokenType;
+use rust_decimal::prelude::ToPrimitive;
+pub use rust_decimal::Decimal;
+
+/// The number literal `lexeme` as an exact decimal, how the `decimal`
+/// dialect reads literals.
+pub fn literal(lexeme: &str) -> Result<Value, Text> {
+    lexeme
+        .parse()
+        .map(Value::Decimal)
+        .map_err(|_| messages::DECIMAL_OUT_OF_RANGE.text())
+}
+
+/// `value` as a decimal, `None` for values other than numbers and numbers
+/// too large for one. A number becomes the shortest decimal that reads
+/// back as it, so a `0.1` from a native stays `0.1`.
+pub fn to_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Number(n) if n.is_finite() => n.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// `d` as the closest number, for natives taking numbers.
+pub fn to_f64(d: &Decimal) -> f64 {
+    d.to_f64().unwrap_or(f64::NAN)
+}
+
+/// The result of a binary operator when one side is a decimal and the
+/// other a decimal or number, computed exactly. `None` when the operands
+/// aren't such a pair, or the operator isn't arithmetic or a comparison,
+/// so they are handled like any other values.
+pub(crate) fn binary_operation(
+    operator: &TokenType,
+    left: &Value,
+    right: &Value,
+) -> Option<Result<Value, Text>> {
+    if !matches!(
+        (left, right),
+        (Value::Decimal(_), Value::Decimal(_) | Value::Number(_))
+            | (Value::Number(_), Value::Decimal(_))
+    ) {
+        return None;
+    }
+    let (Some(left), Some(right)) = (to_decimal(left), to_decimal(right)) else {
+        return Some(Err(messages::DECIMAL_OUT_OF_RANGE.text()));
+    };
+    let result = match operator {
+        TokenType::Plus => left.checked_add(right),
+        TokenType::Minus => left.checked_sub(right),
+        TokenType::Star => left.checked_mul(right),
+        TokenType::Slash if right.is_zero() => return Some(Err(messages::DIVISION_BY_ZERO.text())),
+        TokenType::Slash => left.checked_div(right),
+        TokenType::Less => return Some(Ok(Value::Boolean(left < right))),
+        TokenType::LessEqual => return Some(Ok(Value::Boolean(left <= right))),
+        TokenType::Greater => return Some(Ok(Value::Boolean(left > right))),
+        TokenType::GreaterEqual => return Some(Ok(Value::Boolean(left >= right))),
+        TokenType::EqualEqual => return Some(Ok(Value::Boolean(left == right))),
+        TokenType::BangEqual => return Some(Ok(Value::Boolean(left != right))),
+        _ => return None,
+    };
+    Some(
+        result
+            .map(Value::Decimal)
+            .ok_or_else(|| messages::DECIMAL_OUT_OF_RANGE.text()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::Dialect;
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        let mut runner = Runner::new();
+        let dialect = Dialect {
+            decimal: true,
+            ..Dialect::default()
+        };
+        runner.interpreter_mut().set_dialect(dialect);
+        transcript(&mut runner, source)
+    }
+
+    #[test]
+    fn literals_are_exact() {
+        let source = "print 0.1 + 0.2 == 0.3; print 0.1 + 0.2; print 1 / 4; print type(1.5);";
+        assert_eq!(run(source), "true\n0.3\n0.25\nnumber\n");
+    }
+
+    #[test]
+    fn natives_mix_with_decimals() {
+        assert_eq!(run("print len(\"abc\") * 0.1; print 2.5 < len(\"abc\");"), "0.3\ntrue\n");
+    }
+
+    #[test]
+    fn errors_out_of_range() {
+        assert_eq!(
+            run("print 1 / 0;"),
+            "[line 1] runtime error: Division by zero.\n"
+        );
+        assert_eq!(
+            run("var n = 10000000000000000000000000000; print n * n;"),
+            "[line 1] runtime error: Number out of range for decimal arithmetic.\n"
+        );
+    }
+}
@@ -0,0 +1,628 @@
+use crate::interpreter::RuntimeError;
+use crate::messages::Text;
+use crate::parser::ParsingError;
+use crate::scanner::{ScanError, Span, TokenInfo};
+use std::collections::HashSet;
+use std::fmt::Display;
+
+const ERROR_COLOR: &str = "\x1b[1;31m";
+const WARNING_COLOR: &str = "\x1b[1;33m";
+const GUTTER_COLOR: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Scan,
+    Parse,
+    /// Found by the static passes after parsing.
+    Resolve,
+    Runtime,
+    Warning(Warning),
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::Scan => write!(f, "scan"),
+            DiagnosticKind::Parse => write!(f, "parse"),
+            DiagnosticKind::Resolve => write!(f, "resolve"),
+            DiagnosticKind::Runtime => write!(f, "runtime"),
+            DiagnosticKind::Warning(warning) => write!(f, "{}", warning.name()),
+        }
+    }
+}
+
+impl DiagnosticKind {
+    /// Stable identifier of diagnostics of this kind for tools consuming
+    /// JSON output, used when the message has no code of its own in
+    /// [`messages`](crate::messages).
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::Scan => "E0100",
+            DiagnosticKind::Parse => "E0200",
+            DiagnosticKind::Resolve => "E0400",
+            DiagnosticKind::Runtime => "E0300",
+            DiagnosticKind::Warning(warning) => warning.code(),
+        }
+    }
+}
+
+/// Suspicious but valid code reported by the static passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    /// A local variable that is declared but never read.
+    Unused,
+    /// A value stored in a local variable that is overwritten or goes out
+    /// of scope before it is read.
+    UnusedAssignment,
+    /// `x = x`.
+    SelfAssignment,
+    /// An `if` condition that is a literal, or a `while` condition that is
+    /// a falsy literal. `while (true)` is a normal way to loop forever.
+    ConstantCondition,
+    /// A local variable declared without a value, read before anything
+    /// could have assigned it.
+    Uninitialized,
+    /// A local variable declared with the name of a local variable of an
+    /// enclosing scope.
+    Shadowing,
+}
+
+impl Warning {
+    pub const ALL: [Warning; 6] = [
+        Warning::Unused,
+        Warning::UnusedAssignment,
+        Warning::SelfAssignment,
+        Warning::ConstantCondition,
+        Warning::Uninitialized,
+        Warning::Shadowing,
+    ];
+
+    /// Name used by the `-W<name>` and `-Wno-<name>` flags.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::Unused => "unused",
+            Warning::UnusedAssignment => "unused-assignment",
+            Warning::SelfAssignment => "self-assignment",
+            Warning::ConstantCondition => "constant-condition",
+            Warning::Uninitialized => "uninitialized",
+            Warning::Shadowing => "shadowing",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::Unused => "W0001",
+            Warning::UnusedAssignment => "W0002",
+            Warning::SelfAssignment => "W0003",
+            Warning::ConstantCondition => "W0004",
+            Warning::Uninitialized => "W0005",
+            Warning::Shadowing => "W0006",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        Warning::ALL.into_iter().find(|w| w.name() == name)
+    }
+
+    /// Warnings off by default are lints, turned on by `rlox lint`.
+    fn enabled_by_default(&self) -> bool {
+        match self {
+            Warning::Unused | Warning::Uninitialized => true,
+            Warning::UnusedAssignment
+            | Warning::SelfAssignment
+            | Warning::ConstantCondition
+            | Warning::Shadowing => false,
+        }
+    }
+}
+
+/// Which warnings get reported and whether they count as errors.
+#[derive(Debug, Clone)]
+pub struct WarningConfig {
+    enabled: HashSet<Warning>,
+    /// Warnings turned off on purpose, which `enable_lints` leaves off.
+    disabled: HashSet<Warning>,
+    pub warnings_as_errors: bool,
+}
+
+impl Default for WarningConfig {
+    fn default() -> Self {
+        WarningConfig {
+            enabled: Warning::ALL
+                .into_iter()
+                .filter(Warning::enabled_by_default)
+                .collect(),
+            disabled: HashSet::new(),
+            warnings_as_errors: false,
+        }
+    }
+}
+
+impl WarningConfig {
+    pub fn disable_all(&mut self) {
+        self.enabled.clear();
+        self.disabled.extend(Warning::ALL);
+    }
+
+    pub fn enable_all(&mut self) {
+        self.enabled.extend(Warning::ALL);
+        self.disabled.clear();
+    }
+
+    /// Enables every warning that wasn't disabled explicitly, for linting.
+    pub fn enable_lints(&mut self) {
+        let disabled = &self.disabled;
+        self.enabled
+            .extend(Warning::ALL.into_iter().filter(|w| !disabled.contains(w)));
+    }
+
+    pub fn enable(&mut self, warning: Warning) {
+        self.enabled.insert(warning);
+        self.disabled.remove(&warning);
+    }
+
+    pub fn disable(&mut self, warning: Warning) {
+        self.enabled.remove(&warning);
+        self.disabled.insert(warning);
+    }
+
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.enabled.contains(&warning)
+    }
+
+    /// Drops disabled warnings and, with `warnings_as_errors`, turns the rest
+    /// into errors.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter(|d| match d.kind {
+                DiagnosticKind::Warning(warning) => self.is_enabled(warning),
+                _ => true,
+            })
+            .map(|mut d| {
+                if self.warnings_as_errors {
+                    d.severity = Severity::Error;
+                }
+                d
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagnosticFormat {
+    #[default]
+    Human,
+    Json,
+    /// Rendered by ariadne, with labels and help drawn around the source.
+    #[cfg(feature = "fancy")]
+    Fancy,
+    /// The plain lines jlox prints, like `[line 1] Error at 'x': message`.
+    Jlox,
+}
+
+/// An error from any phase of the pipeline, in a form that can be rendered
+/// against the source it came from.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub line: Option<usize>,
+    pub span: Option<Span>,
+    /// Other places involved, each with a note on its part.
+    pub labels: Vec<(Span, String)>,
+    /// Advice on fixing it.
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn warning(warning: Warning, token: &TokenInfo, message: impl Display) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Warning(warning),
+            severity: Severity::Warning,
+            code: warning.code(),
+            message: message.to_string(),
+            line: Some(token.line),
+            span: Some(token.span),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// An error found by the static passes, which keeps the program from
+    /// running.
+    pub fn resolve_error(token: &TokenInfo, message: Text) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Resolve,
+            severity: Severity::Error,
+            code: message.code,
+            message: message.text,
+            line: Some(token.line),
+            span: Some(token.span),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, note: impl Display) -> Self {
+        self.labels.push((span, note.to_string()));
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Display) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl From<&ScanError> for Diagnostic {
+    fn from(e: &ScanError) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Scan,
+            severity: Severity::Error,
+            code: e.code,
+            message: e.message.clone(),
+            line: Some(e.line),
+            span: Some(e.span),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+}
+
+impl From<&ParsingError> for Diagnostic {
+    fn from(e: &ParsingError) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Parse,
+            severity: Severity::Error,
+            code: e.code,
+            message: match &e.hint {
+                Some(hint) => format!("{} {hint}", e.message),
+                None => e.message.clone(),
+            },
+            line: Some(e.line),
+            span: Some(e.span),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Runtime,
+            severity: Severity::Error,
+            code: e.code,
+            message: e.message.clone(),
+            line: e.line,
+            span: e.span,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+}
+
+/// Where each line of a source starts, built once so spans can be turned
+/// into lines and columns without rescanning the source for each one.
+/// Offsets are in characters, like spans, and lines count from 0.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    /// Character and byte offset of the start of each line.
+    starts: Vec<(usize, usize)>,
+    /// Length of the source in characters and in bytes.
+    end: (usize, usize),
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut starts = vec![(0, 0)];
+        let mut chars = 0;
+        for (byte, c) in source.char_indices() {
+            chars += 1;
+            if c == '\n' {
+                starts.push((chars, byte + 1));
+            }
+        }
+        LineIndex {
+            starts,
+            end: (chars, source.len()),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The line the character at `offset` is on. Offsets past the end are
+    /// on the last line.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|(start, _)| *start <= offset) - 1
+    }
+
+    /// Character offset of the start of `line`.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line).map(|(start, _)| *start)
+    }
+
+    /// The text of `line` in `source`, the source the index was built
+    /// from, without its line break.
+    pub fn line_text<'s>(&self, source: &'s str, line: usize) -> &'s str {
+        let Some((_, start)) = self.starts.get(line) else {
+            return "";
+        };
+        let end = self
+            .starts
+            .get(line + 1)
+            .map_or(self.end.1, |(_, end)| *end);
+        source[*start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// Length of the source in characters.
+    pub fn len(&self) -> usize {
+        self.end.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end.0 == 0
+    }
+}
+
+/// Position of a span inside the source, with the text of its line.
+struct Location {
+    line: usize,
+    column: usize,
+    line_text: String,
+    underline_length: usize,
+}
+
+pub struct DiagnosticRenderer<'a> {
+    source: &'a str,
+    lines: LineIndex,
+    origin: &'a str,
+    color: bool,
+    format: DiagnosticFormat,
+}
+
+impl<'a> DiagnosticRenderer<'a> {
+    /// `origin` names where the source came from, like a file path or `<repl>`.
+    pub fn new(source: &'a str, origin: &'a str, color: bool) -> Self {
+        DiagnosticRenderer {
+            source,
+            lines: LineIndex::new(source),
+            origin,
+            color,
+            format: DiagnosticFormat::default(),
+        }
+    }
+
+    pub fn set_format(&mut self, format: DiagnosticFormat) {
+        self.format = format;
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match self.format {
+            DiagnosticFormat::Human => self.render_human(diagnostic),
+            DiagnosticFormat::Json => self.render_json(diagnostic),
+            #[cfg(feature = "fancy")]
+            DiagnosticFormat::Fancy => self.render_fancy(diagnostic),
+            DiagnosticFormat::Jlox => self.render_jlox(diagnostic),
+        }
+    }
+
+    /// Runtime errors give the message, then the line. Other errors name
+    /// the token they are at.
+    fn render_jlox(&self, diagnostic: &Diagnostic) -> String {
+        let line = diagnostic.line.unwrap_or(0);
+        let message = &diagnostic.message;
+        match diagnostic.kind {
+            DiagnosticKind::Runtime => match diagnostic.line {
+                Some(line) => format!("{message}\n[line {line}]"),
+                None => message.clone(),
+            },
+            // jlox doesn't say which character.
+            DiagnosticKind::Scan if message.starts_with("Unexpected character") => {
+                format!("[line {line}] Error: Unexpected character.")
+            }
+            DiagnosticKind::Scan => format!("[line {line}] Error: {message}"),
+            _ => {
+                let lexeme = diagnostic
+                    .span
+                    .and_then(|span| self.source.get(span.start..span.end));
+                match lexeme.filter(|lexeme| !lexeme.is_empty()) {
+                    Some(lexeme) => format!("[line {line}] Error at '{lexeme}': {message}"),
+                    None => format!("[line {line}] Error at end: {message}"),
+                }
+            }
+        }
+    }
+
+    /// Falls back to the human format for diagnostics without a span.
+    #[cfg(feature = "fancy")]
+    fn render_fancy(&self, diagnostic: &Diagnostic) -> String {
+        use ariadne::{Color, Config, Label, Report, ReportKind, Source};
+        let Some(span) = diagnostic.span else {
+            return self.render_human(diagnostic);
+        };
+        let (kind, color) = match diagnostic.severity {
+            Severity::Error => (ReportKind::Error, Color::Red),
+            Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        };
+        let mut report = Report::build(kind, (self.origin, span.start..span.end))
+            .with_config(Config::default().with_color(self.color))
+            .with_code(diagnostic.code)
+            .with_message(&diagnostic.message)
+            .with_label(
+                Label::new((self.origin, span.start..span.end))
+                    .with_message(diagnostic.kind)
+                    .with_color(color),
+            );
+        for (span, note) in &diagnostic.labels {
+            report.add_label(
+                Label::new((self.origin, span.start..span.end))
+                    .with_message(note)
+                    .with_color(Color::Blue),
+            );
+        }
+        if let Some(help) = &diagnostic.help {
+            report.set_help(help);
+        }
+        let mut rendered = Vec::new();
+        let source = Source::from(self.source);
+        let written = report.finish().write((self.origin, &source), &mut rendered);
+        match written {
+            Ok(()) => String::from_utf8_lossy(&rendered).trim_end().to_string(),
+            Err(_) => self.render_human(diagnostic),
+        }
+    }
+
+    /// One JSON object per diagnostic, on a single line.
+    fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let location = diagnostic.span.and_then(|span| self.locate(span));
+        let line = location.as_ref().map(|l| l.line).or(diagnostic.line);
+        let column = location.as_ref().map(|l| l.column);
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"code\":{},\"message\":{},\"severity\":{},\"help\":{}}}",
+            json_string(self.origin),
+            json_number(line),
+            json_number(column),
+            json_string(diagnostic.code),
+            json_string(&diagnostic.message),
+            json_string(&diagnostic.severity.to_string()),
+            diagnostic.help.as_deref().map_or("null".to_string(), json_string),
+        )
+    }
+
+    fn render_human(&self, diagnostic: &Diagnostic) -> String {
+        let color = match diagnostic.severity {
+            Severity::Error => ERROR_COLOR,
+            Severity::Warning => WARNING_COLOR,
+        };
+        let mut rendered = format!(
+            "{}{}",
+            self.paint(
+                color,
+                format!("{}[{}]", diagnostic.severity, diagnostic.kind)
+            ),
+            self.paint(BOLD, format!(": {}", diagnostic.message)),
+        );
+        let Some(location) = diagnostic.span.and_then(|span| self.locate(span)) else {
+            if let Some(line) = diagnostic.line {
+                rendered.push_str(&format!(
+                    "\n {} {}:{}",
+                    self.paint(GUTTER_COLOR, "-->"),
+                    self.origin,
+                    line
+                ));
+            }
+            return rendered;
+        };
+        let line_number = location.line.to_string();
+        let padding = " ".repeat(line_number.len());
+        let gutter = self.paint(GUTTER_COLOR, format!("{padding} |"));
+        rendered.push_str(&format!(
+            "\n{padding}{} {}:{}:{}",
+            self.paint(GUTTER_COLOR, "-->"),
+            self.origin,
+            location.line,
+            location.column
+        ));
+        rendered.push_str(&format!("\n{gutter}"));
+        rendered.push_str(&format!(
+            "\n{} {}",
+            self.paint(GUTTER_COLOR, format!("{line_number} |")),
+            location.line_text
+        ));
+        rendered.push_str(&format!(
+            "\n{gutter} {}{}",
+            " ".repeat(location.column - 1),
+            self.paint(color, "^".repeat(location.underline_length))
+        ));
+        let equals = self.paint(GUTTER_COLOR, "=");
+        for (span, note) in diagnostic.labels.iter() {
+            if let Some(label) = self.locate(*span) {
+                rendered.push_str(&format!(
+                    "\n{padding} {equals} note: {note} at {}:{}:{}",
+                    self.origin, label.line, label.column
+                ));
+            }
+        }
+        if let Some(help) = &diagnostic.help {
+            rendered.push_str(&format!("\n{padding} {equals} help: {help}"));
+        }
+        rendered
+    }
+
+    fn paint(&self, color: &str, text: impl Display) -> String {
+        if self.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn locate(&self, span: Span) -> Option<Location> {
+        if span.start > self.lines.len() {
+            return None;
+        }
+        let line = self.lines.line_of(span.start);
+        let line_start = self.lines.line_start(line)?;
+        let line_text = self.lines.line_text(self.source, line);
+        let line_end = self
+            .lines
+            .line_start(line + 1)
+            .map_or(self.lines.len(), |next| next - 1);
+        let underline_end = span.end.min(line_end).max(span.start + 1);
+        Some(Location {
+            line: line + 1,
+            column: span.start - line_start + 1,
+            line_text: line_text.to_string(),
+            underline_length: underline_end - span.start,
+        })
+    }
+}
+
+fn json_number(n: Option<usize>) -> String {
+    n.map_or("null".to_string(), |n| n.to_string())
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,158 @@
+use crate::parser::Value;
+use crate::util::format_number;
+
+/// Choices about how the language behaves where Lox implementations
+/// differ or beginners are better served by an error. Set with
+/// [`Interpreter::set_dialect`](crate::interpreter::Interpreter::set_dialect).
+/// Every option is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dialect {
+    /// Dividing by zero gives infinity or NaN, as IEEE 754 says, instead
+    /// of raising an error.
+    pub ieee_division: bool,
+    /// `<`, `<=`, `>` and `>=` compare strings by their characters, besides
+    /// numbers.
+    pub string_comparison: bool,
+    /// `==` and `!=` raise an error for values of different types, instead
+    /// of finding them different. Anything can still be compared with nil.
+    pub strict_equality: bool,
+    /// Reading a variable declared without a value before assigning it
+    /// raises an error, instead of giving nil.
+    pub uninitialized_error: bool,
+    /// Arithmetic giving infinity or NaN raises an error at the operator,
+    /// instead of carrying the value on.
+    pub checked_arithmetic: bool,
+    /// Number literals are exact decimals, so `0.1 + 0.2 == 0.3`.
+    /// Arithmetic and comparisons mixing them with numbers from natives
+    /// are exact too, and fail instead of losing precision or overflowing
+    /// past about 7.9e28. Needs the `decimal` feature.
+    #[cfg(feature = "decimal")]
+    pub decimal: bool,
+    /// Behaves like jlox, the reference implementation from Crafting
+    /// Interpreters: `print` shows numbers as Java does, runtime errors
+    /// use its wording and errors are reported in its format. Turning it on
+    /// with `set` starts from [`Dialect::jlox`].
+    pub jlox: bool,
+}
+
+impl Dialect {
+    /// Names of the options, as given to `set`.
+    #[cfg(not(feature = "decimal"))]
+    pub const OPTIONS: &'static [&'static str] = &[
+        "ieee-division",
+        "string-comparison",
+        "strict-equality",
+        "uninitialized-error",
+        "checked-arithmetic",
+        "jlox",
+    ];
+    /// Names of the options, as given to `set`.
+    #[cfg(feature = "decimal")]
+    pub const OPTIONS: &'static [&'static str] = &[
+        "ieee-division",
+        "string-comparison",
+        "strict-equality",
+        "uninitialized-error",
+        "checked-arithmetic",
+        "jlox",
+        "decimal",
+    ];
+
+    /// The options matching jlox: dividing by zero gives infinity and
+    /// nothing else is checked.
+    pub fn jlox() -> Self {
+        Dialect {
+            jlox: true,
+            ieee_division: true,
+            ..Dialect::default()
+        }
+    }
+
+    /// Turns the option called `name` on or off. Returns false for an
+    /// unknown name.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let option = match name {
+            "ieee-division" => &mut self.ieee_division,
+            "string-comparison" => &mut self.string_comparison,
+            "strict-equality" => &mut self.strict_equality,
+            "uninitialized-error" => &mut self.uninitialized_error,
+            "checked-arithmetic" => &mut self.checked_arithmetic,
+            #[cfg(feature = "decimal")]
+            "decimal" => &mut self.decimal,
+            "jlox" if enabled => {
+                *self = Dialect::jlox();
+                return true;
+            }
+            "jlox" => &mut self.jlox,
+            _ => return false,
+        };
+        *option = enabled;
+        true
+    }
+
+    /// How `print` shows `value`.
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Number(n) if self.jlox => java_number(*n),
+            Value::NativeFunction(_) if self.jlox => "<native fn>".to_string(),
+            value => value.to_string(),
+        }
+    }
+}
+
+/// `n` as Java's `Double.toString` writes it, without a trailing `.0`,
+/// like jlox prints numbers: plain between 10^-3 and 10^7, in scientific
+/// notation like `1.5E-7` otherwise.
+fn java_number(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        return format_number(&n);
+    }
+    let scientific = format!("{n:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    if mantissa.contains('.') {
+        format!("{mantissa}E{exponent}")
+    } else {
+        format!("{mantissa}.0E{exponent}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jlox_prints_numbers_like_java() {
+        let jlox = Dialect::jlox();
+        let print = |n: f64| jlox.display(&Value::Number(n));
+        assert_eq!(print(2.0), "2");
+        assert_eq!(print(2.5), "2.5");
+        assert_eq!(print(-0.0), "-0");
+        assert_eq!(print(1e7), "1.0E7");
+        assert_eq!(print(1.5e-7), "1.5E-7");
+        assert_eq!(print(f64::NAN), "NaN");
+        assert_eq!(print(f64::NEG_INFINITY), "-Infinity");
+    }
+
+    #[test]
+    fn rlox_prints_numbers_in_full() {
+        let print = |n: f64| Dialect::default().display(&Value::Number(n));
+        assert_eq!(print(2.0), "2");
+        assert_eq!(print(1e7), "10000000");
+    }
+
+    #[test]
+    fn set_turns_options_on_by_name() {
+        let mut dialect = Dialect::default();
+        assert!(dialect.set("strict-equality", true));
+        assert!(dialect.strict_equality);
+        assert!(!dialect.set("no-such-option", true));
+        assert!(dialect.set("jlox", true));
+        assert_eq!(dialect, Dialect::jlox());
+    }
+}
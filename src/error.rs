@@ -0,0 +1,62 @@
+use crate::interpreter::RuntimeError;
+use crate::parser::ParsingError;
+use crate::scanner::ScanError;
+use std::fmt::Display;
+
+/// Any error the interpreter can produce, for embedders that want one type
+/// to propagate with `?`.
+#[derive(Debug, Clone)]
+pub enum Error {
+    Scan(ScanError),
+    Parse(ParsingError),
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    /// Line the error was found on, when known.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Error::Scan(e) => Some(e.line),
+            Error::Parse(e) => Some(e.line),
+            Error::Runtime(e) => e.line,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Scan(e) => write!(f, "{e}"),
+            Error::Parse(e) => write!(f, "{e}"),
+            Error::Runtime(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Scan(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Runtime(e) => Some(e),
+        }
+    }
+}
+
+impl From<ScanError> for Error {
+    fn from(e: ScanError) -> Self {
+        Error::Scan(e)
+    }
+}
+
+impl From<ParsingError> for Error {
+    fn from(e: ParsingError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Error::Runtime(e)
+    }
+}
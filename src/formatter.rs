@@ -0,0 +1,167 @@
+use crate::scanner::{Scanner, TokenInfo, TokenType};
+
+const INDENT: &str = "    ";
+
+/// Reprints Lox source with consistent indentation and spacing. Works on
+/// tokens rather than the syntax tree so comments and `for` loops survive;
+/// callers should make sure the source scans and parses first.
+pub fn format_source(source: &str) -> String {
+    let mut scanner = Scanner::new_lenient(source);
+    scanner.scan_tokens();
+    let mut formatter = Formatter {
+        source: source.chars().collect(),
+        output: String::with_capacity(source.len()),
+        indent: 0,
+        paren_depth: 0,
+        at_line_start: true,
+        after_unary: false,
+    };
+    formatter.format(&scanner.tokens);
+    formatter.output
+}
+
+struct Formatter {
+    source: Vec<char>,
+    output: String,
+    indent: usize,
+    paren_depth: usize,
+    at_line_start: bool,
+    after_unary: bool,
+}
+
+impl Formatter {
+    fn format(&mut self, tokens: &[TokenInfo]) {
+        let mut previous: Option<&TokenInfo> = None;
+        for (i, token) in tokens.iter().enumerate() {
+            if token.token_type == TokenType::EOF {
+                break;
+            }
+            let next = tokens.get(i + 1);
+            match token.token_type {
+                TokenType::Comment => self.write_comment(token, previous),
+                TokenType::LeftBrace => {
+                    self.write_token(token, previous);
+                    self.indent += 1;
+                    self.newline();
+                }
+                TokenType::RightBrace => {
+                    self.newline();
+                    self.indent = self.indent.saturating_sub(1);
+                    self.write_token(token, previous);
+                    if !next.is_some_and(|t| t.token_type == TokenType::Else) {
+                        self.newline();
+                    }
+                }
+                TokenType::LeftParen => {
+                    self.write_token(token, previous);
+                    self.paren_depth += 1;
+                }
+                TokenType::RightParen => {
+                    self.paren_depth = self.paren_depth.saturating_sub(1);
+                    self.write_token(token, previous);
+                }
+                TokenType::Semicolon => {
+                    self.write_token(token, previous);
+                    if self.paren_depth == 0 {
+                        self.newline();
+                    }
+                }
+                _ => self.write_token(token, previous),
+            }
+            if token.token_type != TokenType::Comment {
+                previous = Some(token);
+            }
+        }
+        self.newline();
+    }
+
+    fn write_token(&mut self, token: &TokenInfo, previous: Option<&TokenInfo>) {
+        if self.at_line_start {
+            self.start_line(token, previous);
+        } else if self.needs_space(previous, token) {
+            self.output.push(' ');
+        }
+        let text = self.text(token);
+        self.output.push_str(&text);
+        self.after_unary = matches!(token.token_type, TokenType::Minus | TokenType::Bang)
+            && !previous.is_some_and(ends_operand);
+    }
+
+    /// Comments on the same line as code stay there, others get their own line.
+    fn write_comment(&mut self, comment: &TokenInfo, previous: Option<&TokenInfo>) {
+        let trailing = previous.is_some_and(|p| p.line == self.start_line_of(comment));
+        if trailing {
+            if self.output.ends_with('\n') {
+                self.output.pop();
+            }
+            self.output.push(' ');
+        } else {
+            self.newline();
+            self.start_line(comment, previous);
+        }
+        let text = self.text(comment);
+        self.output.push_str(text.trim_end());
+        self.at_line_start = false;
+        self.newline();
+    }
+
+    fn start_line(&mut self, token: &TokenInfo, previous: Option<&TokenInfo>) {
+        let had_blank_line = previous.is_some_and(|p| {
+            p.token_type != TokenType::LeftBrace && self.start_line_of(token) > p.line + 1
+        });
+        if had_blank_line && !self.output.is_empty() && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+        self.output.push_str(&INDENT.repeat(self.indent));
+        self.at_line_start = false;
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start {
+            self.output.push('\n');
+            self.at_line_start = true;
+        }
+    }
+
+    fn needs_space(&self, previous: Option<&TokenInfo>, token: &TokenInfo) -> bool {
+        let Some(previous) = previous else {
+            return false;
+        };
+        match (&previous.token_type, &token.token_type) {
+            (_, TokenType::Semicolon | TokenType::Comma | TokenType::RightParen) => false,
+            (_, TokenType::Dot) | (TokenType::Dot | TokenType::LeftParen, _) => false,
+            (TokenType::Identifier | TokenType::RightParen, TokenType::LeftParen) => false,
+            _ => !self.after_unary,
+        }
+    }
+
+    fn text(&self, token: &TokenInfo) -> String {
+        self.source[token.span.start..token.span.end]
+            .iter()
+            .collect()
+    }
+
+    /// Tokens record the line they end on; strings can span several lines.
+    fn start_line_of(&self, token: &TokenInfo) -> usize {
+        let newlines = self.source[token.span.start..token.span.end]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        token.line - newlines
+    }
+}
+
+fn ends_operand(token: &TokenInfo) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Identifier
+            | TokenType::Number
+            | TokenType::String
+            | TokenType::RightParen
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::Super
+    )
+}
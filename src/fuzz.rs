@@ -0,0 +1,89 @@
+use crate::diagnostic::DiagnosticKind;
+use crate::runner::Runner;
+use std::io;
+
+/// Longest input run, in bytes. Longer inputs are cut.
+pub const MAX_INPUT: usize = 4096;
+
+/// Statements a fuzzed script may run, so endless loops end.
+pub const FUEL: u64 = 10_000;
+
+/// How deep calls may nest in a fuzzed script, so recursion ends before
+/// the stack of the thread does.
+pub const MAX_CALL_DEPTH: usize = 32;
+
+/// How a fuzzed script ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// It ran to the end.
+    Completed,
+    /// It didn't scan or parse.
+    CompileError,
+    /// It raised an error while running.
+    RuntimeError,
+    /// It ran out of fuel.
+    OutOfFuel,
+}
+
+/// Runs arbitrary bytes as a script, for fuzzers like cargo-fuzz. Any
+/// panic is a bug. Invalid UTF-8 is replaced, input past `MAX_INPUT` is
+/// ignored, nesting is capped by the parser, calls can't nest deeper than
+/// `MAX_CALL_DEPTH` and the script gets `FUEL`. Lists and maps however
+/// deeply nested are dropped, printed and compared without recursing.
+/// Its output is thrown away, input natives see no input, no capability
+/// is granted and random numbers are seeded, so runs are repeatable.
+/// Memory isn't limited.
+pub fn fuzz_run(bytes: &[u8]) -> Outcome {
+    let source = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_INPUT)]);
+    let mut runner = Runner::new();
+    let interpreter = runner.interpreter_mut();
+    interpreter.set_output(io::sink());
+    interpreter.set_input(io::empty());
+    interpreter.seed_random(0);
+    interpreter.set_fuel(Some(FUEL));
+    interpreter.set_max_call_depth(Some(MAX_CALL_DEPTH));
+    let result = runner.run(&source);
+    let error = result.diagnostics.iter().find(|d| d.is_error());
+    match error.map(|d| d.kind) {
+        None => Outcome::Completed,
+        Some(DiagnosticKind::Runtime) if runner.interpreter().fuel() == Some(0) => {
+            Outcome::OutOfFuel
+        }
+        Some(DiagnosticKind::Runtime) => Outcome::RuntimeError,
+        Some(_) => Outcome::CompileError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(fuzz_run(b"print 1;"), Outcome::Completed);
+        assert_eq!(fuzz_run(b"print"), Outcome::CompileError);
+        assert_eq!(fuzz_run(b"nil();"), Outcome::RuntimeError);
+        assert_eq!(fuzz_run(b"while (true) {}"), Outcome::OutOfFuel);
+    }
+
+    #[test]
+    fn endless_recursion_is_a_runtime_error() {
+        assert_eq!(fuzz_run(b"fun f() { f(); } f();"), Outcome::RuntimeError);
+    }
+
+    #[test]
+    fn invalid_utf8_and_long_input() {
+        assert_eq!(fuzz_run(b"print \"\xff\xfe\";"), Outcome::Completed);
+        let long = "print 1;".repeat(MAX_INPUT);
+        assert_eq!(fuzz_run(long.as_bytes()), Outcome::Completed);
+    }
+
+    #[test]
+    fn deeply_nested_lists() {
+        let source = "var l = list(); for (var i = 0; i < 50000; i = i + 1) l = list(l); print 1;";
+        assert_eq!(fuzz_run(source.as_bytes()), Outcome::OutOfFuel);
+        let source = "var l = list(); for (var i = 0; i < 2000; i = i + 1) l = list(l);
+                      print l; print deepEquals(l, list(l));";
+        assert_eq!(fuzz_run(source.as_bytes()), Outcome::Completed);
+    }
+}
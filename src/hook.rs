@@ -0,0 +1,84 @@
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Expr, Stmt, Value};
+
+/// Observes a script as it runs, for debuggers, tracers and profilers. Set
+/// with [`Interpreter::set_hook`]. The hook is taken out of the interpreter
+/// while one of its methods runs, so code it evaluates isn't observed.
+/// Every method does nothing by default.
+pub trait Hook {
+    /// Called before each statement other than a block runs, with the line
+    /// it starts on. An error stops the script as if the statement had
+    /// raised it.
+    fn before_statement(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _stmt: &Stmt,
+        _line: usize,
+    ) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    /// Called after each expression, subexpressions included, evaluates
+    /// to `value`. Not called for expressions that fail.
+    fn after_expression(&mut self, _interpreter: &mut Interpreter, _expr: &Expr, _value: &Value) {}
+
+    /// Called where an error is raised, before it unwinds the statements
+    /// and calls it fails, so the scopes and call stack are as they were.
+    fn on_error(&mut self, _interpreter: &mut Interpreter, _error: &RuntimeError) {}
+
+    /// Called as a function is called, before `frame` is pushed on the
+    /// call stack.
+    fn enter_call(&mut self, _interpreter: &mut Interpreter, _frame: &CallFrame) {}
+
+    /// Called as a call returns or fails, while `frame` is still on top of
+    /// the call stack.
+    fn exit_call(&mut self, _interpreter: &mut Interpreter, _frame: &CallFrame) {}
+}
+
+/// Several hooks, told about each event in order.
+impl Hook for Vec<Box<dyn Hook>> {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        for hook in self.iter_mut() {
+            hook.before_statement(interpreter, stmt, line)?;
+        }
+        Ok(())
+    }
+
+    fn after_expression(&mut self, interpreter: &mut Interpreter, expr: &Expr, value: &Value) {
+        for hook in self.iter_mut() {
+            hook.after_expression(interpreter, expr, value);
+        }
+    }
+
+    fn on_error(&mut self, interpreter: &mut Interpreter, error: &RuntimeError) {
+        for hook in self.iter_mut() {
+            hook.on_error(interpreter, error);
+        }
+    }
+
+    fn enter_call(&mut self, interpreter: &mut Interpreter, frame: &CallFrame) {
+        for hook in self.iter_mut() {
+            hook.enter_call(interpreter, frame);
+        }
+    }
+
+    fn exit_call(&mut self, interpreter: &mut Interpreter, frame: &CallFrame) {
+        for hook in self.iter_mut() {
+            hook.exit_call(interpreter, frame);
+        }
+    }
+}
+
+/// A call in progress, as listed by [`Interpreter::call_stack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Name of the called function.
+    pub function: String,
+    /// Line of the call.
+    pub line: usize,
+}
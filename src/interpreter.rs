@@ -1,83 +1,1025 @@
-use crate::parser::{Expr, Stmt, Value};
-use crate::scanner::{TokenInfo, TokenType};
-use std::collections::HashMap;
-use std::io::Write;
-use std::iter::Rev;
-use std::slice::{Iter, IterMut};
+use crate::dialect::Dialect;
+use crate::diagnostic::DiagnosticKind;
+use crate::hook::{CallFrame, Hook};
+use crate::loader;
+use crate::messages::{self, Message, Text};
+use crate::natives::Random;
+use crate::parser::{Distance, Expr, FunctionDecl, Key, Stmt, Value};
+use crate::scanner::{Span, TokenInfo, TokenType};
+use crate::runner::{RunResult, Runner};
+use crate::util::closest_match;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-#[derive(Debug)]
-struct Environment {
-    scopes: Vec<VariableScope>,
-}
+/// The variables of one scope, the globals or those of a block, call or
+/// module, linked to the scope enclosing it. Functions hold on to the
+/// scope they were declared in, so it lives on after its block is over.
 #[derive(Debug, Default)]
-struct VariableScope {
+struct Environment {
     values: HashMap<String, Value>,
+    /// Variables declared without a value and not assigned yet, which hold
+    /// nil until then.
+    uninitialized: HashSet<String>,
+    /// Line of the declaration of each variable a script declared.
+    lines: HashMap<String, usize>,
+    /// `None` for the globals.
+    enclosing: Option<EnvironmentRef>,
+}
+
+type EnvironmentRef = Rc<RefCell<Environment>>;
+
+/// Bytes of stack a call of a Lox function can take, with a margin for
+/// bodies nesting blocks and loops. Unoptimized builds take about three
+/// times as much.
+const CALL_STACK_SIZE: usize = if cfg!(debug_assertions) {
+    32 * 1024
+} else {
+    12 * 1024
+};
+
+/// How deep calls of Lox functions nest by default before raising "Stack
+/// overflow.", low enough for the 8 MiB stack main threads usually have.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = max_call_depth_for(8 << 20);
+
+/// How deep calls of Lox functions can nest on a thread with `stack_size`
+/// bytes of stack, for [`Interpreter::set_max_call_depth`].
+pub const fn max_call_depth_for(stack_size: usize) -> usize {
+    stack_size / CALL_STACK_SIZE
 }
 
 impl Environment {
-    fn new() -> Self {
-        Environment {
-            scopes: vec![VariableScope::default()],
-        }
+    fn new(enclosing: Option<EnvironmentRef>) -> EnvironmentRef {
+        Rc::new(RefCell::new(Environment {
+            enclosing,
+            ..Environment::default()
+        }))
     }
-    fn scopes_iter(&self) -> Rev<Iter<VariableScope>> {
-        self.scopes.iter().rev()
+    /// `scope` and the scopes enclosing it, innermost first.
+    fn chain(scope: &EnvironmentRef) -> impl Iterator<Item = EnvironmentRef> {
+        std::iter::successors(Some(scope.clone()), |scope| scope.borrow().enclosing.clone())
     }
-    fn scopes_iter_mut(&mut self) -> Rev<IterMut<VariableScope>> {
-        self.scopes.iter_mut().rev()
+    /// The scope `distance` scopes out from `scope`, or the outermost one
+    /// if there are fewer.
+    fn ancestor(scope: &EnvironmentRef, distance: usize) -> EnvironmentRef {
+        Environment::chain(scope)
+            .take(distance + 1)
+            .last()
+            .unwrap_or_else(|| scope.clone())
     }
-    fn get(&self, name: String) -> Result<&Value, String> {
-        for scope in self.scopes_iter() {
-            if let Some(value) = scope.values.get(&name) {
-                return Ok(value);
+    fn get(scope: &EnvironmentRef, name: &str) -> Result<Value, Text> {
+        for scope in Environment::chain(scope) {
+            let scope = scope.borrow();
+            if let Some(value) = scope.values.get(name) {
+                if scope.uninitialized.contains(name) {
+                    return Err(messages::UNASSIGNED_VARIABLE.with(&[("name", &name)]));
+                }
+                return Ok(value.clone());
             }
         }
-        Err(format!("Undefined variable {name}."))
+        Err(Environment::undefined(scope, name))
     }
 
-    fn assign(&mut self, name: String, value: Value) -> Result<(), String> {
-        for scope in self.scopes_iter_mut() {
-            if scope.values.contains_key(&name) {
-                scope.values.insert(name, value);
+    fn assign(scope: &EnvironmentRef, name: &str, value: Value) -> Result<(), Text> {
+        for scope in Environment::chain(scope) {
+            let mut scope = scope.borrow_mut();
+            if let Some(slot) = scope.values.get_mut(name) {
+                *slot = value;
+                scope.uninitialized.remove(name);
                 return Ok(());
             }
         }
-        Err(format!("Undefined variable {name}."))
+        Err(Environment::undefined(scope, name))
+    }
+    /// The error for an undefined variable, hinting at a visible one with a
+    /// similar name.
+    fn undefined(scope: &EnvironmentRef, name: &str) -> Text {
+        let visible: Vec<String> = Environment::chain(scope)
+            .flat_map(|scope| scope.borrow().values.keys().cloned().collect::<Vec<_>>())
+            .collect();
+        match closest_match(name, visible.iter().map(String::as_str)) {
+            Some(similar) => {
+                messages::UNDEFINED_VARIABLE_SIMILAR.with(&[("name", &name), ("similar", &similar)])
+            }
+            None => messages::UNDEFINED_VARIABLE.with(&[("name", &name)]),
+        }
     }
-    fn define(&mut self, name: String, value: Value) {
-        self.scopes.last_mut().unwrap().values.insert(name, value);
+    fn define(&mut self, name: String, value: Value, line: usize) {
+        self.uninitialized.remove(&name);
+        self.lines.insert(name.clone(), line);
+        self.values.insert(name, value);
     }
-    fn jump_in_scope(&mut self) {
-        self.scopes.push(VariableScope::default())
+    fn define_uninitialized(&mut self, name: String, line: usize) {
+        self.uninitialized.insert(name.clone());
+        self.lines.insert(name.clone(), line);
+        self.values.insert(name, Value::Nil);
     }
-    fn jump_out_scope(&mut self) {
-        if self.scopes.len() != 1 {
-            self.scopes.pop();
-        } else {
-            panic!("Try delete global scope")
+    /// Defines a variable the host provided.
+    fn bind(&mut self, name: String, value: Value) {
+        self.uninitialized.remove(&name);
+        self.lines.remove(&name);
+        self.values.insert(name, value);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    /// Code of the message, see [`messages`].
+    pub code: &'static str,
+    pub message: String,
+    pub line: Option<usize>,
+    pub span: Option<Span>,
+    /// Whether the hook was told about it, so only the place it was raised
+    /// is reported and not every statement and call it fails.
+    observed: bool,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Display) -> Self {
+        RuntimeError {
+            code: DiagnosticKind::Runtime.code(),
+            message: message.to_string(),
+            line: None,
+            span: None,
+            observed: false,
+        }
+    }
+    pub fn at(token: &TokenInfo, message: impl Display) -> Self {
+        RuntimeError {
+            code: DiagnosticKind::Runtime.code(),
+            message: message.to_string(),
+            line: Some(token.line),
+            span: Some(token.span),
+            observed: false,
+        }
+    }
+    /// An error with a message from the catalog, keeping its code.
+    pub fn text(message: Text) -> Self {
+        RuntimeError {
+            code: message.code,
+            ..RuntimeError::new(message.text)
+        }
+    }
+    pub fn text_at(token: &TokenInfo, message: Text) -> Self {
+        RuntimeError {
+            code: message.code,
+            ..RuntimeError::at(token, message.text)
+        }
+    }
+}
+
+impl RuntimeError {
+    /// Whether the error stops the whole program, even with
+    /// [`Interpreter::interpret_keep_going`]: running out of fuel or being
+    /// cancelled.
+    pub(crate) fn is_fatal(&self) -> bool {
+        [messages::OUT_OF_FUEL.code, messages::CANCELLED.code].contains(&self.code)
+    }
+    /// Locates an error raised without a position at `token`.
+    pub(crate) fn or_at(mut self, token: &TokenInfo) -> Self {
+        if self.line.is_none() {
+            self.line = Some(token.line);
+            self.span = Some(token.span);
+        }
+        self
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Signature of Rust functions exposed to scripts.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>;
+
+/// Prepares the interpreter of a thread started by `spawn`, set with
+/// [`Interpreter::set_thread_setup`].
+pub type ThreadSetup = dyn Fn(&mut Interpreter) + Send + Sync;
+
+/// A Rust function callable from Lox, registered with
+/// [`Interpreter::define_native`].
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    /// Whether calls may pass more than `arity` arguments.
+    pub variadic: bool,
+    function: NativeBody,
+}
+
+enum NativeBody {
+    Sync(Box<NativeFn>),
+    #[cfg(feature = "async")]
+    Async(Box<crate::async_interpreter::AsyncNativeFn>),
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) -> Self {
+        NativeFunction {
+            name: name.into(),
+            arity,
+            variadic: false,
+            function: NativeBody::Sync(Box::new(function)),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn new_async(
+        name: String,
+        arity: usize,
+        function: Box<crate::async_interpreter::AsyncNativeFn>,
+    ) -> Self {
+        NativeFunction {
+            name,
+            arity,
+            variadic: false,
+            function: NativeBody::Async(function),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn as_async(&self) -> Option<&crate::async_interpreter::AsyncNativeFn> {
+        match &self.function {
+            NativeBody::Async(function) => Some(function.as_ref()),
+            NativeBody::Sync(_) => None,
+        }
+    }
+
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        match &self.function {
+            NativeBody::Sync(function) => function(interpreter, arguments),
+            #[cfg(feature = "async")]
+            NativeBody::Async(_) => Err(RuntimeError::new(format!(
+                "Async function '{}' can only be called from interpret_async.",
+                self.name
+            ))),
+        }
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("variadic", &self.variadic)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Functions are only equal to themselves.
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A function declared in Lox with `fun`.
+pub struct LoxFunction {
+    declaration: Rc<FunctionDecl>,
+    /// The scope the function was declared in, which its body sees.
+    closure: EnvironmentRef,
+    /// Whether it is the `init` method of a class, which returns `this`.
+    initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+    /// Line of the declaration.
+    pub fn line(&self) -> usize {
+        self.declaration.name.line
+    }
+    pub(crate) fn source(&self) -> &str {
+        &self.declaration.source
+    }
+    /// The value of `name` in the scope the function was declared in, or
+    /// bound to as a method.
+    pub(crate) fn captured(&self, name: &str) -> Option<Value> {
+        Environment::get(&self.closure, name).ok()
+    }
+    /// The method bound to `instance`, in a scope of its own where `this`
+    /// is the instance.
+    fn bind(&self, instance: Value) -> LoxFunction {
+        crate::stats::count(crate::stats::AllocationKind::Scope);
+        let scope = Environment::new(Some(self.closure.clone()));
+        scope.borrow_mut().bind("this".to_string(), instance);
+        LoxFunction {
+            declaration: self.declaration.clone(),
+            closure: scope,
+            initializer: self.initializer,
+        }
+    }
+}
+
+impl Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxFunction")
+            .field("name", &self.name())
+            .field("arity", &self.arity())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Functions are only equal to themselves.
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A class declared in Lox with `class`. Calling it makes an instance.
+pub struct LoxClass {
+    name: String,
+    methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The method of the class called `name`.
+    pub fn method(&self, name: &str) -> Option<&Rc<LoxFunction>> {
+        self.methods.get(name)
+    }
+    /// The arguments calling the class takes, those of its `init` method.
+    pub fn arity(&self) -> usize {
+        self.method("init").map_or(0, |init| init.arity())
+    }
+}
+
+impl Debug for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut methods: Vec<&str> = self.methods.keys().map(String::as_str).collect();
+        methods.sort_unstable();
+        f.debug_struct("LoxClass")
+            .field("name", &self.name)
+            .field("methods", &methods)
+            .finish()
+    }
+}
+
+/// Classes are only equal to themselves.
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// An instance of a [`LoxClass`].
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+
+impl LoxInstance {
+    pub fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+    /// The field called `name`, if it was set.
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+    pub(crate) fn set(&self, name: &TokenInfo, value: Value) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+/// Instances are only equal to themselves.
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A value a call expression can call.
+pub(crate) enum Callee<'a> {
+    Native(&'a NativeFunction),
+    Function(&'a LoxFunction),
+    Class(&'a Rc<LoxClass>),
+}
+
+impl Callee<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Callee::Native(function) => &function.name,
+            Callee::Function(function) => function.name(),
+            Callee::Class(class) => class.name(),
+        }
+    }
+
+    /// The async native being called, if it is one.
+    #[cfg(feature = "async")]
+    pub(crate) fn as_async(&self) -> Option<&crate::async_interpreter::AsyncNativeFn> {
+        match self {
+            Callee::Native(function) => function.as_async(),
+            Callee::Function(_) | Callee::Class(_) => None,
+        }
+    }
+}
+
+/// Access to the host that scripts don't get unless the embedder allows it
+/// with [`Interpreter::allow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// `setEnv`, changing the environment of the whole process.
+    SetEnv,
+    /// `exec`, running other programs.
+    Exec,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 2] = [Capability::SetEnv, Capability::Exec];
+
+    /// Name used by the `--allow=<name>` flag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::SetEnv => "env",
+            Capability::Exec => "exec",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|c| c.name() == name)
+    }
 }
 
+/// Stops a running script from another thread, got from
+/// [`Interpreter::cancel_token`]. Once cancelled, the script fails with
+/// "Cancelled." before its next statement, and natives waiting, like
+/// `recv`, give up. Threads the script spawned are cancelled with it.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    /// Lets scripts run again after a cancellation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A named set of natives and constants that scripts bring into scope with
+/// `import name;`, registered with [`Interpreter::register_module`].
 #[derive(Debug)]
+pub struct NativeModule {
+    pub name: String,
+    /// For modules loaded from files, the scope their statements ran in,
+    /// which their functions keep changing.
+    members: EnvironmentRef,
+}
+
+impl NativeModule {
+    pub fn new(name: impl Into<String>) -> Self {
+        NativeModule {
+            name: name.into(),
+            members: Environment::new(None),
+        }
+    }
+
+    /// Adds a constant, read by scripts as `module.name`.
+    pub fn define(&mut self, name: impl Into<String>, value: Value) {
+        self.members.borrow_mut().values.insert(name.into(), value);
+    }
+
+    /// Adds a function, called by scripts as `module.name(...)`.
+    pub fn define_native(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let name = name.into();
+        let native = NativeFunction::new(format!("{}.{name}", self.name), arity, function);
+        self.define(name, Value::NativeFunction(Rc::new(native)));
+    }
+
+    /// The current value of the member `name`.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.members.borrow().values.get(name).cloned()
+    }
+
+    /// Names and current values of the members, in no particular order.
+    pub fn members(&self) -> Vec<(String, Value)> {
+        let members = self.members.borrow();
+        let members = members.values.iter();
+        members.map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+}
+
+/// Modules are only equal to themselves.
+impl PartialEq for NativeModule {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A read-only view of the variables of a scope, see
+/// [`Interpreter::scopes`].
+#[derive(Debug, Clone)]
+pub struct Scope(EnvironmentRef);
+
+impl Scope {
+    /// The variables of the scope, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = Binding> {
+        let scope = self.0.borrow();
+        let bindings: Vec<_> = scope
+            .values
+            .iter()
+            .map(|(name, value)| Binding {
+                name: name.clone(),
+                value: value.clone(),
+                line: scope.lines.get(name).copied(),
+            })
+            .collect();
+        bindings.into_iter()
+    }
+    /// The variables of the scope, sorted by name.
+    pub fn sorted(&self) -> Vec<Binding> {
+        let mut bindings: Vec<_> = self.iter().collect();
+        bindings.sort_by(|a, b| a.name.cmp(&b.name));
+        bindings
+    }
+    pub fn get(&self, name: &str) -> Option<Binding> {
+        let scope = self.0.borrow();
+        let value = scope.values.get(name)?;
+        Some(Binding {
+            name: name.to_string(),
+            value: value.clone(),
+            line: scope.lines.get(name).copied(),
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.0.borrow().values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().values.is_empty()
+    }
+}
+
+/// A variable of a [`Scope`].
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub value: Value,
+    /// Line of the declaration, `None` for natives, modules and globals the
+    /// host defined.
+    pub line: Option<usize>,
+}
+
+impl Binding {
+    /// Type of the value, as `type()` names it.
+    pub fn type_name(&self) -> &'static str {
+        self.value.type_name()
+    }
+}
+
+/// `print` output kept in memory. Clones share the buffer, so one can be
+/// given to [`Interpreter::set_output`] and the other read afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl CapturedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 pub struct Interpreter {
-    environment: Environment,
+    globals: EnvironmentRef,
+    /// The innermost scope of the code running.
+    environment: EnvironmentRef,
+    output: Box<dyn Write>,
+    /// Where input natives read from, stdin when `None`.
+    input: Option<Box<dyn BufRead>>,
+    modules: HashMap<String, Rc<NativeModule>>,
+    /// State of the `random` natives.
+    random: Random,
+    /// Whether `number()` fails on text that isn't a number instead of
+    /// returning nil.
+    strict_conversions: bool,
+    capabilities: HashSet<Capability>,
+    hook: Option<Box<dyn Hook>>,
+    call_stack: Vec<CallFrame>,
+    /// Statements left to run, unlimited when `None`.
+    fuel: Option<u64>,
+    /// Calls of Lox functions running, innermost included.
+    call_depth: usize,
+    /// How deep calls can nest before raising "Stack overflow.", unlimited
+    /// when `None`.
+    max_call_depth: Option<usize>,
+    dialect: Dialect,
+    thread_setup: Option<Arc<ThreadSetup>>,
+    cancel: CancelToken,
+    /// Documentation of natives by name, for `help`.
+    docs: HashMap<String, String>,
+    /// Directories searched for `name.lox` by `import name;` when it isn't
+    /// next to the importing file.
+    module_path: Vec<PathBuf>,
+    /// Directory of the file running, which imports are relative to. The
+    /// working directory when `None`.
+    script_dir: Option<PathBuf>,
+    /// Modules loaded from files, by canonical path, so each file runs
+    /// once however many files import it.
+    file_modules: HashMap<PathBuf, Rc<NativeModule>>,
+    /// Files of the modules being loaded, innermost last.
+    loading: Vec<PathBuf>,
+    /// Value of the `return` running, which skips the rest of the
+    /// statements up to the call it returns from.
+    returning: Option<Value>,
+}
+
+impl Debug for Interpreter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("environment", &self.environment)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
+    /// An interpreter with the builtin natives, like `clock`, defined.
     pub fn new() -> Self {
-        Interpreter {
-            environment: Environment::new(),
+        let globals = Environment::new(None);
+        let mut interpreter = Interpreter {
+            globals: globals.clone(),
+            environment: globals,
+            output: Box::new(std::io::stdout()),
+            input: None,
+            modules: HashMap::new(),
+            random: Random::from_time(),
+            strict_conversions: false,
+            capabilities: HashSet::new(),
+            hook: None,
+            call_stack: Vec::new(),
+            fuel: None,
+            call_depth: 0,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            dialect: Dialect::default(),
+            thread_setup: None,
+            cancel: CancelToken::default(),
+            docs: HashMap::new(),
+            module_path: Vec::new(),
+            script_dir: None,
+            file_modules: HashMap::new(),
+            loading: Vec::new(),
+            returning: None,
+        };
+        crate::natives::define_builtins(&mut interpreter);
+        interpreter
+    }
+    pub fn define_global(&mut self, name: impl Into<String>, value: Value) {
+        self.globals.borrow_mut().bind(name.into(), value);
+    }
+    /// The global called `name`, natives included.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().values.get(name).cloned()
+    }
+    /// Names of every global, natives included, in no particular order.
+    pub fn global_names(&self) -> impl Iterator<Item = String> {
+        let names: Vec<_> = self.globals.borrow().values.keys().cloned().collect();
+        names.into_iter()
+    }
+    /// Removes the globals, natives included, whose name `keep` rejects.
+    pub fn retain_globals(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let mut scope = self.globals.borrow_mut();
+        scope.values.retain(|name, _| keep(name));
+        scope.uninitialized.retain(|name| keep(name));
+        scope.lines.retain(|name, _| keep(name));
+    }
+    /// The global scope, natives and modules included.
+    pub fn globals(&self) -> Scope {
+        Scope(self.globals.clone())
+    }
+    /// Exposes a Rust function to scripts as a global. Calls with a number
+    /// of arguments other than `arity` fail before `function` runs.
+    pub fn define_native(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let name = name.into();
+        let native = NativeFunction::new(name.clone(), arity, function);
+        self.define_global(name, Value::NativeFunction(Rc::new(native)));
+    }
+    /// Like `define_native`, for functions taking `min_arity` or more
+    /// arguments.
+    pub fn define_variadic_native(
+        &mut self,
+        name: impl Into<String>,
+        min_arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let name = name.into();
+        let native = NativeFunction {
+            variadic: true,
+            ..NativeFunction::new(name.clone(), min_arity, function)
+        };
+        self.define_global(name, Value::NativeFunction(Rc::new(native)));
+    }
+    /// Makes a module available to `import`. A module registered under an
+    /// existing name replaces it.
+    pub fn register_module(&mut self, module: NativeModule) {
+        self.modules.insert(module.name.clone(), Rc::new(module));
+    }
+    pub(crate) fn module(&self, name: &str) -> Option<Rc<NativeModule>> {
+        self.modules.get(name).cloned()
+    }
+    /// Names of the modules scripts can import, in no particular order.
+    pub fn module_names(&self) -> impl Iterator<Item = &str> {
+        self.modules.keys().map(String::as_str)
+    }
+    /// Documents the native called `name`, like `math.sqrt` for a member of
+    /// a module, for `help`. The first line of `doc` is the summary listed
+    /// by `help()`.
+    pub fn document(&mut self, name: impl Into<String>, doc: impl Into<String>) {
+        self.docs.insert(name.into(), doc.into());
+    }
+    /// The documentation of the native called `name`, see `document`.
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.docs.get(name).map(String::as_str)
+    }
+    /// What `help` prints: the documentation of the native, module or
+    /// global called `name`, or every native with its summary for `None`.
+    pub fn help(&self, name: Option<&str>) -> Result<String, RuntimeError> {
+        crate::natives::help(self, name)
+    }
+    /// Runs `setup` on the interpreter of every thread scripts `spawn`,
+    /// after the builtin natives are defined, so the natives and modules
+    /// of the embedder are there too.
+    pub fn set_thread_setup(&mut self, setup: impl Fn(&mut Interpreter) + Send + Sync + 'static) {
+        self.thread_setup = Some(Arc::new(setup));
+    }
+    /// Makes the interpreter of a spawned thread, to be called on that
+    /// thread. It has globals of its own, and the dialect, capabilities,
+    /// thread setup and cancel token of this one.
+    pub(crate) fn thread_interpreter(&self) -> impl FnOnce() -> Interpreter + Send + 'static {
+        let dialect = self.dialect;
+        let strict_conversions = self.strict_conversions;
+        let capabilities = self.capabilities.clone();
+        let thread_setup = self.thread_setup.clone();
+        let cancel = self.cancel.clone();
+        move || {
+            let mut interpreter = Interpreter::new();
+            interpreter.dialect = dialect;
+            interpreter.strict_conversions = strict_conversions;
+            interpreter.capabilities = capabilities;
+            interpreter.cancel = cancel;
+            if let Some(setup) = &thread_setup {
+                setup(&mut interpreter);
+            }
+            interpreter.thread_setup = thread_setup;
+            interpreter
+        }
+    }
+    /// Redirect the output of `print` statements, stdout by default.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+    pub(crate) fn replace_output(&mut self, output: Box<dyn Write>) -> Box<dyn Write> {
+        std::mem::replace(&mut self.output, output)
+    }
+    /// Feed input natives from `input` instead of stdin, like canned input
+    /// in tests.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+    /// Reads a line for an input native, without its line ending. Returns
+    /// `None` at the end of the input.
+    pub fn read_line(&mut self) -> Result<Option<String>, RuntimeError> {
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        }
+        .map_err(|e| RuntimeError::text(messages::CANT_READ_INPUT.with(&[("error", &e)])))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    }
+    /// Makes the `random` natives repeat the same numbers on every run,
+    /// like `seedRandom` does from scripts.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+    /// Limits how many more statements run, blocks and loop iterations
+    /// included, before scripts fail with "Out of fuel.". `None` removes
+    /// the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+    /// Statements left to run, `None` without a limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+    /// Limits how deep calls of Lox functions can nest before scripts fail
+    /// with "Stack overflow.", instead of overflowing the stack of the
+    /// thread. `None` removes the limit.
+    pub fn set_max_call_depth(&mut self, depth: Option<usize>) {
+        self.max_call_depth = depth;
+    }
+    /// How deep calls can nest, `None` without a limit.
+    pub fn max_call_depth(&self) -> Option<usize> {
+        self.max_call_depth
+    }
+    /// A token that stops this interpreter's scripts when cancelled, for
+    /// other threads like a Ctrl-C handler.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+    pub(crate) fn random_mut(&mut self) -> &mut Random {
+        &mut self.random
+    }
+    /// Makes `number()` raise a runtime error for text that isn't a number,
+    /// instead of returning nil.
+    pub fn set_strict_conversions(&mut self, strict: bool) {
+        self.strict_conversions = strict;
+    }
+    pub(crate) fn strict_conversions(&self) -> bool {
+        self.strict_conversions
+    }
+    /// Directories `import` looks for module files in, in order, after the
+    /// directory of the importing file. Usually taken from `RLOX_PATH`.
+    pub fn set_module_path(&mut self, module_path: Vec<PathBuf>) {
+        self.module_path = module_path;
+    }
+    /// Tells the interpreter the file it runs, so that `import name;`
+    /// finds `name.lox` next to it.
+    pub fn set_script_path(&mut self, path: impl AsRef<Path>) {
+        self.script_dir = path.as_ref().parent().map(Path::to_path_buf);
+    }
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+    /// Lets scripts use the natives guarded by `capability`.
+    pub fn allow(&mut self, capability: Capability) {
+        self.capabilities.insert(capability);
+    }
+    pub fn is_allowed(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+    /// Fails unless `capability` was allowed, for the native `name`.
+    pub(crate) fn require(&self, capability: Capability, name: &str) -> Result<(), RuntimeError> {
+        if self.is_allowed(capability) {
+            Ok(())
+        } else {
+            Err(RuntimeError::new(format!(
+                "{name} isn't allowed, the host has to grant the '{}' capability.",
+                capability.name()
+            )))
         }
     }
-    pub fn interpret(&mut self, statments: Vec<Stmt>) -> Result<(), String> {
+    /// Lets `hook` observe the statements run from now on, replacing any
+    /// earlier hook.
+    pub fn set_hook(&mut self, hook: impl Hook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+    pub fn remove_hook(&mut self) -> Option<Box<dyn Hook>> {
+        self.hook.take()
+    }
+    /// Calls in progress, outermost first.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+    /// Every scope in reach, innermost first. The last one holds the
+    /// globals, natives included.
+    pub fn scopes(&self) -> impl Iterator<Item = Scope> {
+        Environment::chain(&self.environment).map(Scope)
+    }
+    /// The scopes of blocks in reach, innermost first, without the globals.
+    pub fn locals(&self) -> impl Iterator<Item = Scope> {
+        let globals = self.globals.clone();
+        Environment::chain(&self.environment)
+            .take_while(move |scope| !Rc::ptr_eq(scope, &globals))
+            .map(Scope)
+    }
+    /// Stops if cancelled, burns fuel for `stmt` and runs the hook, if any,
+    /// before it. Blocks are skipped by the hook, which sees their
+    /// statements instead.
+    pub(crate) fn observe(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        if self.cancel.is_cancelled() {
+            return Err(RuntimeError::text(messages::CANCELLED.text()));
+        }
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel
+                .checked_sub(1)
+                .ok_or_else(|| RuntimeError::text(messages::OUT_OF_FUEL.text()))?;
+        }
+        let Some(line) = stmt.line().filter(|_| !matches!(stmt, Stmt::Block(_))) else {
+            return Ok(());
+        };
+        self.with_hook(|hook, interpreter| hook.before_statement(interpreter, stmt, line))
+            .unwrap_or(Ok(()))
+    }
+    /// Tells the hook, if any, about an error where it's raised, before it
+    /// unwinds the scopes and calls it fails.
+    pub(crate) fn observe_error<T>(
+        &mut self,
+        result: Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        let mut error = match result {
+            Err(error) if !error.observed && self.hook.is_some() => error,
+            result => return result,
+        };
+        error.observed = true;
+        self.with_hook(|hook, interpreter| hook.on_error(interpreter, &error));
+        Err(error)
+    }
+    /// Runs `f` with the hook taken out of the interpreter, `None` without a
+    /// hook.
+    pub(crate) fn with_hook<T>(&mut self, f: impl FnOnce(&mut dyn Hook, &mut Self) -> T) -> Option<T> {
+        let mut hook = self.hook.take()?;
+        let result = f(hook.as_mut(), self);
+        // The hook may have set a new hook, which wins.
+        self.hook.get_or_insert(hook);
+        Some(result)
+    }
+    /// Runs `source` with `print` output captured in the result instead of
+    /// written out. Definitions stay in this interpreter.
+    pub fn run_captured(&mut self, source: &str) -> RunResult {
+        let mut runner = Runner::with_interpreter(std::mem::take(self));
+        let result = runner.run_captured(source);
+        *self = runner.into_interpreter();
+        result
+    }
+    pub fn interpret(&mut self, statments: Vec<Stmt>) -> Result<(), RuntimeError> {
+        let result = self.execute_statments(&statments);
+        // The resolver keeps `return` out of top-level code, statements
+        // that weren't resolved can still have one.
+        self.returning = None;
+        result
+    }
+    /// Like `interpret`, but an error only stops the top-level statement
+    /// raising it and the next one runs. Returns the errors in the order
+    /// they were raised. Running out of fuel or being cancelled still stops
+    /// the program.
+    pub fn interpret_keep_going(&mut self, statments: Vec<Stmt>) -> Vec<RuntimeError> {
+        let mut errors = Vec::new();
         for stmt in statments {
-            self.execute(&stmt)?;
+            if let Err(error) = self.execute(&stmt) {
+                let fatal = error.is_fatal();
+                errors.push(error);
+                if fatal {
+                    break;
+                }
+            }
+            if self.take_return().is_some() {
+                break;
+            }
         }
-        Ok(())
+        errors
     }
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), String> {
-        match stmt {
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.observe(stmt)?;
+        let result = match stmt {
             Stmt::Expression(e) => self.execute_expression(e),
             Stmt::Print(e) => self.execute_print(e),
             Stmt::Var { name, initializer } => self.execute_variable_declaration(name, initializer),
@@ -88,41 +1030,210 @@ impl Interpreter {
                 else_branch,
             } => self.execute_if(condition, then_branch.as_ref(), else_branch),
             Stmt::While { condition, body } => self.execute_while(condition,body.as_ref()),
+            Stmt::Import(name) => self.import_module(name),
+            Stmt::Function(declaration) => {
+                self.define_function(declaration);
+                Ok(())
+            }
+            Stmt::Class { name, methods } => {
+                self.define_class(name, methods);
+                Ok(())
+            }
+            Stmt::Return { value, .. } => self.execute_return(value.as_ref()),
+        };
+        self.observe_error(result)
+    }
+    /// Binds the module called `name` in the current scope: the registered
+    /// one, or else the one `name.lox` defines.
+    pub(crate) fn import_module(&mut self, name: &TokenInfo) -> Result<(), RuntimeError> {
+        let module = match self.modules.get(&name.lexeme) {
+            Some(module) => module.clone(),
+            None => self.load_module(name)?,
+        };
+        self.define_variable(name, Value::Module(module));
+        Ok(())
+    }
+    /// Runs the file of the module `name`, unless it already ran, in a
+    /// scope of its own on top of the globals. Its variables are the
+    /// members of the module.
+    fn load_module(&mut self, name: &TokenInfo) -> Result<Rc<NativeModule>, RuntimeError> {
+        let dir = self.script_dir.clone().unwrap_or_else(|| PathBuf::from("."));
+        let path = loader::find(&name.lexeme, &dir, &self.module_path).ok_or_else(|| {
+            RuntimeError::text_at(name, messages::UNKNOWN_MODULE.with(&[("name", &name.lexeme)]))
+        })?;
+        if let Some(module) = self.file_modules.get(&path) {
+            return Ok(module.clone());
+        }
+        let shown = path.display();
+        if self.loading.contains(&path) {
+            let message =
+                messages::CIRCULAR_IMPORT.with(&[("name", &name.lexeme), ("path", &shown)]);
+            return Err(RuntimeError::text_at(name, message));
+        }
+        let module_error = |line: Option<usize>, error: &dyn Display| {
+            let location = match line {
+                Some(line) => format!("{shown}:{line}"),
+                None => shown.to_string(),
+            };
+            let message = messages::MODULE_ERROR
+                .with(&[("name", &name.lexeme), ("location", &location), ("error", error)]);
+            RuntimeError::text_at(name, message)
+        };
+        let source = std::fs::read_to_string(&path).map_err(|e| module_error(None, &e))?;
+        let statments = loader::parse(&source).map_err(|e| module_error(e.line, &e.message))?;
+
+        self.loading.push(path.clone());
+        let module_dir = path.parent().map(Path::to_path_buf);
+        let script_dir = std::mem::replace(&mut self.script_dir, module_dir);
+        let scope = self.new_scope(self.globals.clone());
+        let locals = std::mem::replace(&mut self.environment, scope);
+        let result = self.execute_statments(&statments);
+        self.returning = None;
+        let scope = std::mem::replace(&mut self.environment, locals);
+        self.script_dir = script_dir;
+        self.loading.pop();
+        match result {
+            Err(error) if error.is_fatal() => return Err(error),
+            Err(error) => return Err(module_error(error.line, &error.message)),
+            Ok(()) => {}
+        }
+        let module = Rc::new(NativeModule {
+            name: name.lexeme.clone(),
+            members: scope,
+        });
+        self.file_modules.insert(path, module.clone());
+        Ok(module)
+    }
+    /// Binds the function `declaration` declares in the current scope.
+    pub(crate) fn define_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        let function = self.function(declaration);
+        self.define_variable(&declaration.name, Value::Function(Rc::new(function)));
+    }
+    /// Binds the class called `name` in the current scope. Its methods see
+    /// the scope, like functions declared there.
+    pub(crate) fn define_class(&mut self, name: &TokenInfo, methods: &[Rc<FunctionDecl>]) {
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let function = LoxFunction {
+                    initializer: method.name.lexeme == "init",
+                    ..self.function(method)
+                };
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+        let class = LoxClass {
+            name: name.lexeme.clone(),
+            methods,
+        };
+        self.define_variable(name, Value::Class(Rc::new(class)));
+    }
+    fn function(&self, declaration: &Rc<FunctionDecl>) -> LoxFunction {
+        LoxFunction {
+            declaration: declaration.clone(),
+            closure: self.environment.clone(),
+            initializer: false,
         }
     }
-    fn execute_block(&mut self, statments: &Vec<Stmt>) -> Result<(), String> {
-        self.environment.jump_in_scope();
+    fn execute_block(&mut self, statments: &[Stmt]) -> Result<(), RuntimeError> {
+        self.begin_scope();
+        // The scope goes away on errors too, so the code running next, like
+        // the next input of a REPL, doesn't see its variables.
+        let result = self.execute_statments(statments);
+        self.end_scope();
+        result
+    }
+    /// Runs `statments` in order, up to one returning.
+    fn execute_statments(&mut self, statments: &[Stmt]) -> Result<(), RuntimeError> {
         for stmt in statments {
-            self.execute(stmt)?
+            self.execute(stmt)?;
+            if self.is_returning() {
+                break;
+            }
         }
-        self.environment.jump_out_scope();
         Ok(())
     }
+    fn execute_return(&mut self, value: Option<&Expr>) -> Result<(), RuntimeError> {
+        let value = match value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+        self.set_return(value);
+        Ok(())
+    }
+    /// Starts returning `value` from the function running.
+    pub(crate) fn set_return(&mut self, value: Value) {
+        self.returning = Some(value);
+    }
+    /// Whether a `return` is skipping the statements left.
+    pub(crate) fn is_returning(&self) -> bool {
+        self.returning.is_some()
+    }
+    /// Ends the `return` running, giving its value.
+    pub(crate) fn take_return(&mut self) -> Option<Value> {
+        self.returning.take()
+    }
     fn execute_variable_declaration(
         &mut self,
         name: &TokenInfo,
         initializer: &Option<Expr>,
-    ) -> Result<(), String> {
-        let value = match initializer {
-            Some(expr) => self.evaluate(&expr)?,
-            None => Value::Nil,
-        };
-        self.environment.define(name.lexeme.clone(), value);
+    ) -> Result<(), RuntimeError> {
+        match initializer {
+            Some(expr) => {
+                let value = self.evaluate(expr)?;
+                self.define_variable(name, value);
+            }
+            None => self.define_uninitialized(name),
+        }
         Ok(())
     }
-    fn execute_print(&mut self, expr: &Expr) -> Result<(), String> {
+    fn execute_print(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
         let value = self.evaluate(expr)?;
-        println!("{value}");
-        std::io::stdout().flush().unwrap();
-        Ok(())
+        self.print_value(&value)
+    }
+    pub(crate) fn print_value(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        let text = self.dialect.display(value);
+        self.write_output(&format!("{text}\n"))
     }
 
-    fn execute_expression(&mut self, expr: &Expr) -> Result<(), String> {
+    /// Writes to the `print` output and flushes it, for natives.
+    pub(crate) fn write_output(&mut self, text: &str) -> Result<(), RuntimeError> {
+        self.output
+            .write_all(text.as_bytes())
+            .and_then(|_| self.output.flush())
+            .map_err(|e| RuntimeError::text(messages::CANT_WRITE_OUTPUT.with(&[("error", &e)])))
+    }
+
+    fn execute_expression(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
         self.evaluate(expr)?;
         Ok(())
     }
 
-    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, String> {
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let result = self.evaluate_expr(expr);
+        let value = self.observe_error(result)?;
+        self.with_hook(|hook, interpreter| hook.after_expression(interpreter, expr, &value));
+        Ok(value)
+    }
+
+    /// Evaluates `expr` in a scope of its own holding `bindings`, which
+    /// shadow globals of the same name. The scope is gone afterwards, so
+    /// the bindings don't leak into later runs.
+    pub fn evaluate_with(
+        &mut self,
+        expr: &Expr,
+        bindings: &HashMap<String, Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.begin_scope();
+        for (name, value) in bindings {
+            self.environment.borrow_mut().bind(name.clone(), value.clone());
+        }
+        let result = self.evaluate(expr);
+        self.end_scope();
+        result
+    }
+
+    fn evaluate_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         match expr {
             Expr::Binary {
                 left,
@@ -131,40 +1242,350 @@ impl Interpreter {
             } => self.evaluate_binary(left.as_ref(), operator, right.as_ref()),
             Expr::Unary { operator, right } => self.evaluate_unary(operator, right.as_ref()),
             Expr::Grouping(e) => self.evaluate(e),
-            Expr::Literal(v) => Ok(v.clone()),
-            Expr::Variable(t) => Ok(self.environment.get(t.lexeme.clone())?.clone()),
-            Expr::Assign { name, value } => self.evaluate_assigment(name, value.as_ref()),
+            Expr::Literal { value, token } => self.literal(value, token),
+            Expr::Variable { name, distance } => self.look_up_variable(name, distance),
+            Expr::Assign {
+                name,
+                value,
+                distance,
+            } => self.evaluate_assigment(name, distance, value.as_ref()),
             Expr::Logical {
                 left,
                 operator,
                 right,
             } => self.evaluate_logical(left.as_ref(), operator, right.as_ref()),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => self.evaluate_call(callee.as_ref(), paren, arguments),
+            Expr::Get { object, name } => {
+                let object = self.evaluate(object)?;
+                Interpreter::get_property(&object, name)
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let instance = Interpreter::instance_to_set(&object, name)?;
+                let value = self.evaluate(value)?;
+                instance.set(name, value.clone());
+                Ok(value)
+            }
+            Expr::This { keyword, distance } => self.look_up_variable(keyword, distance),
         }
     }
 
-    fn evaluate_assigment(&mut self, name: &TokenInfo, expr: &Expr) -> Result<Value, String> {
-        let value = self.evaluate(expr)?;
+    /// The instance `object` is, to set its field `name`.
+    pub(crate) fn instance_to_set<'a>(
+        object: &'a Value,
+        name: &TokenInfo,
+    ) -> Result<&'a LoxInstance, RuntimeError> {
+        match object {
+            Value::Instance(instance) => Ok(instance),
+            _ => Err(RuntimeError::text_at(
+                name,
+                messages::ONLY_INSTANCES_HAVE_FIELDS.text(),
+            )),
+        }
+    }
+
+    pub(crate) fn get_property(object: &Value, name: &TokenInfo) -> Result<Value, RuntimeError> {
+        let module = match object {
+            Value::Module(module) => module,
+            Value::Map(map) => {
+                let key = Key::from(name.lexeme.as_str());
+                return map.borrow().get(&key).cloned().ok_or_else(|| {
+                    let message = messages::UNDEFINED_KEY.with(&[("name", &name.lexeme)]);
+                    RuntimeError::text_at(name, message)
+                })
+            }
+            Value::List(list) => {
+                return crate::natives::list_method(list, &name.lexeme).ok_or_else(|| {
+                    let message = messages::UNDEFINED_LIST_METHOD.with(&[("name", &name.lexeme)]);
+                    RuntimeError::text_at(name, message)
+                })
+            }
+            Value::Foreign(foreign) => {
+                return crate::natives::foreign_method(foreign, &name.lexeme).ok_or_else(|| {
+                    let args: [(&str, &dyn Display); 2] =
+                        [("object", object), ("name", &name.lexeme)];
+                    RuntimeError::text_at(name, messages::UNDEFINED_FOREIGN_METHOD.with(&args))
+                })
+            }
+            Value::Instance(instance) => {
+                if let Some(value) = instance.field(&name.lexeme) {
+                    return Ok(value);
+                }
+                let method = instance.class.method(&name.lexeme);
+                let method = method.map(|method| method.bind(object.clone()));
+                return method.map(|method| Value::Function(Rc::new(method))).ok_or_else(|| {
+                    let message =
+                        messages::UNDEFINED_INSTANCE_PROPERTY.with(&[("name", &name.lexeme)]);
+                    RuntimeError::text_at(name, message)
+                });
+            }
+            Value::Number(_) | Value::String(_) | Value::Boolean(_) => {
+                return crate::natives::primitive_method(object, &name.lexeme).ok_or_else(|| {
+                    let args: [(&str, &dyn Display); 2] =
+                        [("type", &object.type_name()), ("name", &name.lexeme)];
+                    RuntimeError::text_at(name, messages::UNDEFINED_VALUE_METHOD.with(&args))
+                })
+            }
+            _ => return Err(RuntimeError::text_at(name, messages::NO_PROPERTIES.text())),
+        };
+        module.get(&name.lexeme).ok_or_else(|| {
+            let args: [(&str, &dyn Display); 2] =
+                [("name", &name.lexeme), ("module", &module.name)];
+            RuntimeError::text_at(name, messages::UNDEFINED_PROPERTY.with(&args))
+        })
+    }
+
+    fn evaluate_call(
+        &mut self,
+        callee: &Expr,
+        paren: &TokenInfo,
+        arguments: &[Expr],
+    ) -> Result<Value, RuntimeError> {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call_value(callee, paren, arguments)
+    }
+
+    /// Calls a function value from Rust, like natives taking callbacks do.
+    pub fn call(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        let function = Interpreter::callable(callee, arguments.len()).map_err(RuntimeError::new)?;
+        self.invoke(function, arguments)
+    }
+
+    pub(crate) fn call_value(
+        &mut self,
+        callee: Value,
+        paren: &TokenInfo,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let function = Interpreter::check_call(&callee, paren, arguments.len())?;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("call", function = %function.name(), line = paren.line)
+            .entered();
+        let frame = CallFrame {
+            function: function.name().to_string(),
+            line: paren.line,
+        };
+        self.with_hook(|hook, interpreter| hook.enter_call(interpreter, &frame));
+        self.call_stack.push(frame);
+        let result = self.invoke(function, &arguments);
+        let result = self.observe_error(result);
+        self.with_hook(|hook, interpreter| {
+            if let Some(frame) = interpreter.call_stack.last().cloned() {
+                hook.exit_call(interpreter, &frame);
+            }
+        });
+        self.call_stack.pop();
+        // Errors raised by the native itself point at the call.
+        result.map_err(|e| e.or_at(paren))
+    }
+
+    fn invoke(&mut self, callee: Callee, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        match callee {
+            Callee::Native(function) => function.call(self, arguments),
+            Callee::Function(function) => self.call_function(function, arguments),
+            Callee::Class(class) => {
+                crate::stats::count(crate::stats::AllocationKind::Instance);
+                let instance = Value::Instance(Rc::new(LoxInstance {
+                    class: class.clone(),
+                    fields: RefCell::default(),
+                }));
+                if let Some(init) = class.method("init") {
+                    self.call_function(&init.bind(instance.clone()), arguments)?;
+                }
+                Ok(instance)
+            }
+        }
+    }
+
+    /// Runs the body of `function` in a call environment of its own: a
+    /// scope holding the arguments inside the scope the function was
+    /// declared in. The scopes of the caller are out of reach until the call
+    /// is over.
+    fn call_function(
+        &mut self,
+        function: &LoxFunction,
+        arguments: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        if self.max_call_depth.is_some_and(|max| self.call_depth >= max) {
+            return Err(RuntimeError::text(messages::STACK_OVERFLOW.text()));
+        }
+        let declaration = &function.declaration;
+        let scope = self.new_scope(function.closure.clone());
+        let caller = std::mem::replace(&mut self.environment, scope);
+        for (param, argument) in declaration.params.iter().zip(arguments) {
+            self.define_variable(param, argument.clone());
+        }
+        self.call_depth += 1;
+        let result = self.execute_statments(&declaration.body);
+        self.call_depth -= 1;
+        self.environment = caller;
+        let mut value = self.take_return().unwrap_or(Value::Nil);
+        if function.initializer {
+            value = Environment::get(&function.closure, "this").unwrap_or(Value::Nil);
+        }
+        result.map(|()| value)
+    }
+
+    /// Returns the function being called if it takes `argument_count` arguments.
+    pub(crate) fn check_call<'a>(
+        callee: &'a Value,
+        paren: &TokenInfo,
+        argument_count: usize,
+    ) -> Result<Callee<'a>, RuntimeError> {
+        Interpreter::callable(callee, argument_count).map_err(|e| RuntimeError::text_at(paren, e))
+    }
+
+    fn callable(callee: &Value, argument_count: usize) -> Result<Callee<'_>, Text> {
+        let (callee, arity, variadic) = match callee {
+            Value::NativeFunction(function) => {
+                (Callee::Native(function), function.arity, function.variadic)
+            }
+            Value::Function(function) => (Callee::Function(function), function.arity(), false),
+            Value::Class(class) => (Callee::Class(class), class.arity(), false),
+            _ => return Err(messages::NOT_CALLABLE.text()),
+        };
+        let args: [(&str, &dyn Display); 2] = [("arity", &arity), ("count", &argument_count)];
+        if variadic && argument_count < arity {
+            return Err(messages::TOO_FEW_ARGUMENTS.with(&args));
+        }
+        if !variadic && argument_count != arity {
+            return Err(messages::WRONG_ARGUMENT_COUNT.with(&args));
+        }
+        Ok(callee)
+    }
+
+    /// The value of the variable `name`, looked up from the scope
+    /// `distance` scopes out from the innermost one.
+    pub(crate) fn look_up_variable(
+        &self,
+        name: &TokenInfo,
+        distance: &Distance,
+    ) -> Result<Value, RuntimeError> {
+        let scope = Environment::ancestor(&self.environment, distance.get());
+        Environment::get(&scope, &name.lexeme).map_err(|e| self.variable_error(name, e))
+    }
+
+    pub(crate) fn assign_variable(
+        &mut self,
+        name: &TokenInfo,
+        distance: &Distance,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let scope = Environment::ancestor(&self.environment, distance.get());
+        Environment::assign(&scope, &name.lexeme, value).map_err(|e| self.variable_error(name, e))
+    }
+
+    /// In jlox mode the only error a variable access can have is being
+    /// undefined, worded without a hint.
+    fn variable_error(&self, name: &TokenInfo, message: Text) -> RuntimeError {
+        if self.dialect.jlox {
+            let message = messages::JLOX_UNDEFINED_VARIABLE.with(&[("name", &name.lexeme)]);
+            RuntimeError::text_at(name, message)
+        } else {
+            RuntimeError::text_at(name, message)
+        }
+    }
+
+    pub(crate) fn define_variable(&mut self, name: &TokenInfo, value: Value) {
         self.environment
-            .assign(name.lexeme.clone(), value.clone())?;
+            .borrow_mut()
+            .define(name.lexeme.clone(), value, name.line);
+    }
+
+    /// Defines a variable declared without a value: nil, or with the
+    /// `uninitialized_error` dialect option an error to read until assigned.
+    pub(crate) fn define_uninitialized(&mut self, name: &TokenInfo) {
+        if self.dialect.uninitialized_error {
+            self.environment
+                .borrow_mut()
+                .define_uninitialized(name.lexeme.clone(), name.line);
+        } else {
+            self.define_variable(name, Value::Nil);
+        }
+    }
+
+    pub(crate) fn begin_scope(&mut self) {
+        let scope = self.new_scope(self.environment.clone());
+        self.environment = scope;
+    }
+
+    pub(crate) fn end_scope(&mut self) {
+        let enclosing = self.environment.borrow().enclosing.clone();
+        self.environment = enclosing.expect("Try delete global scope");
+    }
+
+    fn new_scope(&self, enclosing: EnvironmentRef) -> EnvironmentRef {
+        crate::stats::count(crate::stats::AllocationKind::Scope);
+        Environment::new(Some(enclosing))
+    }
+
+    fn evaluate_assigment(
+        &mut self,
+        name: &TokenInfo,
+        distance: &Distance,
+        expr: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(expr)?;
+        self.assign_variable(name, distance, value.clone())?;
         Ok(value)
     }
-    fn evaluate_unary(&mut self, operator: &TokenInfo, right: &Expr) -> Result<Value, String> {
+    fn evaluate_unary(
+        &mut self,
+        operator: &TokenInfo,
+        right: &Expr,
+    ) -> Result<Value, RuntimeError> {
         let right = self.evaluate(right)?;
+        self.unary_operation(operator, right)
+    }
+    /// The value of a literal. In the decimal dialect number literals are
+    /// read again from their text as decimals.
+    #[cfg_attr(not(feature = "decimal"), allow(unused_variables))]
+    pub(crate) fn literal(&self, value: &Value, token: &TokenInfo) -> Result<Value, RuntimeError> {
+        #[cfg(feature = "decimal")]
+        if self.dialect.decimal && matches!(value, Value::Number(_)) {
+            return crate::decimal::literal(&token.lexeme)
+                .map_err(|e| RuntimeError::text_at(token, e));
+        }
+        Ok(value.clone())
+    }
+    pub(crate) fn unary_operation(
+        &self,
+        operator: &TokenInfo,
+        right: Value,
+    ) -> Result<Value, RuntimeError> {
         match &operator.token_type {
-            TokenType::Minus => {
-                if let Value::Number(n) = right {
-                    Ok(Value::Number(-n))
-                } else {
-                    Err("Operand must be number".to_string())
+            TokenType::Minus => match right {
+                Value::Number(n) => Ok(Value::Number(-n)),
+                #[cfg(feature = "decimal")]
+                Value::Decimal(d) => Ok(Value::Decimal(-d)),
+                _ => {
+                    let message = if self.dialect.jlox {
+                        messages::JLOX_OPERAND_NOT_NUMBER
+                    } else {
+                        messages::OPERAND_NOT_NUMBER
+                    };
+                    Err(RuntimeError::text_at(operator, message.text()))
                 }
-            }
+            },
             TokenType::Bang => {
                 let boolean_value = right.is_truthy();
                 Ok(Value::Boolean(!boolean_value))
             }
-            t => Err(format!(
-                "IllegalOperation wrong operator for unary expression {:?}",
-                t
+            t => Err(RuntimeError::at(
+                operator,
+                format!("IllegalOperation wrong operator for unary expression {:?}", t),
             )),
         }
     }
@@ -173,83 +1594,154 @@ impl Interpreter {
         left: &Expr,
         operator: &TokenInfo,
         right: &Expr,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, RuntimeError> {
         let left = self.evaluate(left)?;
         let right = self.evaluate(right)?;
-        match operator.token_type {
-            TokenType::Plus => Interpreter::add_values(left, right),
-            TokenType::Minus => Interpreter::subtract_values(left, right),
-            TokenType::Star => Interpreter::multiply_values(left, right),
-            TokenType::Slash => Interpreter::divide_values(left, right),
-
-            TokenType::Less => Interpreter::compare_lt(left, right),
-            TokenType::LessEqual => Interpreter::compare_le(left, right),
-            TokenType::Greater => Interpreter::compare_gt(left, right),
-            TokenType::GreaterEqual => Interpreter::compare_ge(left, right),
-
-            TokenType::EqualEqual => Interpreter::is_equal(left, right),
-            TokenType::BangEqual => Interpreter::is_not_equal(left, right),
+        self.binary_operation(operator, left, right)
+    }
+    pub(crate) fn binary_operation(
+        &self,
+        operator: &TokenInfo,
+        left: Value,
+        right: Value,
+    ) -> Result<Value, RuntimeError> {
+        #[cfg(feature = "decimal")]
+        if let Some(result) = crate::decimal::binary_operation(&operator.token_type, &left, &right)
+        {
+            return result.map_err(|e| RuntimeError::text_at(operator, e));
+        }
+        let result = match operator.token_type {
+            TokenType::Plus => self.add_values(left, right),
+            TokenType::Minus => self.subtract_values(left, right),
+            TokenType::Star => self.multiply_values(left, right),
+            TokenType::Slash => self.divide_values(left, right),
+
+            TokenType::Less => self.compare_lt(left, right),
+            TokenType::LessEqual => self.compare_le(left, right),
+            TokenType::Greater => self.compare_gt(left, right),
+            TokenType::GreaterEqual => self.compare_ge(left, right),
+
+            TokenType::EqualEqual => self.is_equal(left, right),
+            TokenType::BangEqual => self.is_not_equal(left, right),
             _ => todo!(),
+        };
+        let result = result.and_then(|value| self.check_finite(operator, value));
+        result.map_err(|e| RuntimeError::text_at(operator, e))
+    }
+    /// With checked arithmetic, fails if an arithmetic operator gave
+    /// infinity or NaN.
+    fn check_finite(&self, operator: &TokenInfo, value: Value) -> Result<Value, Text> {
+        match value {
+            Value::Number(n) if self.dialect.checked_arithmetic && !n.is_finite() => {
+                let message = if n.is_nan() {
+                    messages::NAN_RESULT
+                } else {
+                    messages::INFINITE_RESULT
+                };
+                Err(message.with(&[("operator", &operator.lexeme)]))
+            }
+            value => Ok(value),
         }
     }
-    fn divide_values(left: Value, right: Value) -> Result<Value, String> {
+    fn divide_values(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
+            (Value::Number(_), Value::Number(right)) if right == 0.0 && !self.dialect.ieee_division => {
+                Err(messages::DIVISION_BY_ZERO.text())
+            }
             (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left / right)),
-            (_, _) => Err("To divide operands must be two numbers".to_string()),
+            (_, _) => Err(self.numbers_error(messages::DIVIDE_OPERANDS)),
         }
     }
-    fn multiply_values(left: Value, right: Value) -> Result<Value, String> {
+    fn multiply_values(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left * right)),
-            (_, _) => Err("To multiply operands must be two numbers".to_string()),
+            (_, _) => Err(self.numbers_error(messages::MULTIPLY_OPERANDS)),
         }
     }
-    fn is_equal(left: Value, right: Value) -> Result<Value, String> {
+    /// The error for an operator given something other than numbers.
+    fn numbers_error(&self, message: Message) -> Text {
+        if self.dialect.jlox {
+            messages::JLOX_OPERANDS_NOT_NUMBERS.text()
+        } else {
+            message.text()
+        }
+    }
+    fn is_equal(&self, left: Value, right: Value) -> Result<Value, Text> {
+        self.check_equality_types(&left, &right)?;
         Ok(Value::Boolean(left == right))
     }
-    fn is_not_equal(left: Value, right: Value) -> Result<Value, String> {
+    fn is_not_equal(&self, left: Value, right: Value) -> Result<Value, Text> {
+        self.check_equality_types(&left, &right)?;
         Ok(Value::Boolean(left != right))
     }
+    /// With strict equality, values of different types can't be compared,
+    /// except with nil.
+    fn check_equality_types(&self, left: &Value, right: &Value) -> Result<(), Text> {
+        let (left, right) = (left.type_name(), right.type_name());
+        if !self.dialect.strict_equality || left == right || left == "nil" || right == "nil" {
+            return Ok(());
+        }
+        Err(messages::EQUALITY_TYPES.with(&[("left", &left), ("right", &right)]))
+    }
+    fn comparison_error(&self) -> Text {
+        if self.dialect.jlox {
+            messages::JLOX_OPERANDS_NOT_NUMBERS.text()
+        } else if self.dialect.string_comparison {
+            messages::COMPARE_OPERANDS_OR_STRINGS.text()
+        } else {
+            messages::COMPARE_OPERANDS.text()
+        }
+    }
 
-    fn compare_lt(left: Value, right: Value) -> Result<Value, String> {
+    fn compare_lt(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Boolean(left < right)),
-            (_, _) => Err("To compare operands must be two numbers".to_string()),
+            (Value::String(left), Value::String(right)) if self.dialect.string_comparison => {
+                Ok(Value::Boolean(left < right))
+            }
+            (_, _) => Err(self.comparison_error()),
         }
     }
-    fn compare_gt(left: Value, right: Value) -> Result<Value, String> {
+    fn compare_gt(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Boolean(left > right)),
-            (_, _) => Err("To compare operands must be two numbers".to_string()),
+            (Value::String(left), Value::String(right)) if self.dialect.string_comparison => {
+                Ok(Value::Boolean(left > right))
+            }
+            (_, _) => Err(self.comparison_error()),
         }
     }
-    fn compare_le(left: Value, right: Value) -> Result<Value, String> {
+    fn compare_le(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Boolean(left <= right)),
-            (_, _) => Err("To compare operands must be two numbers".to_string()),
+            (Value::String(left), Value::String(right)) if self.dialect.string_comparison => {
+                Ok(Value::Boolean(left <= right))
+            }
+            (_, _) => Err(self.comparison_error()),
         }
     }
-    fn compare_ge(left: Value, right: Value) -> Result<Value, String> {
+    fn compare_ge(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Boolean(left >= right)),
-            (_, _) => Err("To compare operands must be two numbers".to_string()),
+            (Value::String(left), Value::String(right)) if self.dialect.string_comparison => {
+                Ok(Value::Boolean(left >= right))
+            }
+            (_, _) => Err(self.comparison_error()),
         }
     }
-    fn add_values(left: Value, right: Value) -> Result<Value, String> {
+    fn add_values(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left + right)),
-            (Value::String(left), Value::String(right)) => {
-                let concated_string = format!("{left}{right}");
-                Ok(Value::String(concated_string))
-            }
-            (_, _) => Err("To add operands must be two numbers or two strings".to_string()),
+            (Value::String(left), Value::String(right)) => Ok(Value::String(left.concat(&right))),
+            (_, _) if self.dialect.jlox => Err(messages::JLOX_ADD_OPERANDS.text()),
+            (_, _) => Err(messages::ADD_OPERANDS.text()),
         }
     }
 
-    fn subtract_values(left: Value, right: Value) -> Result<Value, String> {
+    fn subtract_values(&self, left: Value, right: Value) -> Result<Value, Text> {
         match (left, right) {
             (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left - right)),
-            (_, _) => Err("To subtract operands must be two numbers".to_string()),
+            (_, _) => Err(self.numbers_error(messages::SUBTRACT_OPERANDS)),
         }
     }
 
@@ -258,8 +1750,8 @@ impl Interpreter {
         condition: &Expr,
         then_branch: &Stmt,
         else_branch: &Option<Box<Stmt>>,
-    ) -> Result<(), String> {
-        if self.evaluate(&condition)?.is_truthy() {
+    ) -> Result<(), RuntimeError> {
+        if self.evaluate(condition)?.is_truthy() {
             self.execute(then_branch)?;
         } else if let Some(else_branch) = else_branch {
             self.execute(else_branch.as_ref())?;
@@ -272,20 +1764,120 @@ impl Interpreter {
         left: &Expr,
         operator: &TokenInfo,
         right: &Expr,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, RuntimeError> {
         let left = self.evaluate(left)?;
         match operator.token_type {
             TokenType::And if !left.is_truthy()  =>  Ok(left),
             TokenType::Or if left.is_truthy() =>  Ok(left),
             TokenType::And | TokenType::Or=>self.evaluate(right),
-            _ =>  Err("For logical operation operator must be 'and' or 'or'".to_string()),
+            _ => Err(RuntimeError::at(
+                operator,
+                "For logical operation operator must be 'and' or 'or'",
+            )),
         }
     }
 
-    fn execute_while(&mut self, condition: &Expr, body: &Stmt) -> Result<(), String> {
+    fn execute_while(&mut self, condition: &Expr, body: &Stmt) -> Result<(), RuntimeError> {
         while self.evaluate(condition)?.is_truthy() {
            self.execute(body)?;
+           if self.is_returning() {
+               break;
+           }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn closures_keep_their_variables() {
+        let source = "fun counter() { var i = 0; fun inc() { i = i + 1; return i; } return inc; }
+                      var c = counter(); c(); print c(); print counter()();";
+        assert_eq!(run(source), "2\n1\n");
+    }
+
+    #[test]
+    fn closures_resolve_variables_where_they_are_declared() {
+        let source = r#"var a = "global";
+                        { fun show() { print a; } show(); var a = "block"; show(); print a; }"#;
+        assert_eq!(run(source), "global\nglobal\nblock\n");
+    }
+
+    #[test]
+    fn module_members_follow_the_functions_changing_them() {
+        let dir = std::env::temp_dir().join(format!("rlox-modules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = "var count = 0; fun bump() { count = count + 1; }";
+        std::fs::write(dir.join("util.lox"), source).unwrap();
+        let mut runner = Runner::new();
+        runner.interpreter_mut().set_module_path(vec![dir.clone()]);
+        let source = "import util; util.bump(); util.bump(); print util.count;";
+        let output = transcript(&mut runner, source);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn classes_take_the_arguments_of_init() {
+        let source = "class Point { init(x, y) { this.x = x; this.y = y; }
+                                     sum() { return this.x + this.y; } }
+                      var p = Point(1, 2); print p.sum(); p.x = 10; print p.sum(); print p;";
+        assert_eq!(run(source), "3\n12\nPoint instance\n");
+        assert_eq!(
+            run("class A { init(x) {} } A();"),
+            "[line 1] runtime error: Expected 1 arguments but got 0.\n"
+        );
+        assert_eq!(run("class A {} print A();"), "A instance\n");
+    }
+
+    #[test]
+    fn init_returns_the_instance() {
+        let source = "class A { init() { this.n = 1; return; } } var a = A(); print a.init() == a;";
+        assert_eq!(run(source), "true\n");
+    }
+
+    #[test]
+    fn bound_methods_remember_this() {
+        let source = r#"class A { init(name) { this.name = name; } hi() { print this.name; } }
+                        var hi = A("a").hi; hi();"#;
+        assert_eq!(run(source), "a\n");
+    }
+
+    #[test]
+    fn only_instances_have_fields() {
+        assert_eq!(
+            run(r#"var s = "text"; s.field = 1;"#),
+            "[line 1] runtime error: Only instances have fields.\n"
+        );
+    }
+
+    #[test]
+    fn deep_recursion_is_a_runtime_error() {
+        let mut runner = Runner::new();
+        runner.interpreter_mut().set_max_call_depth(Some(50));
+        let source = "fun f(n) { if (n > 0) f(n - 1); } f(49); print 1; f(50);";
+        assert_eq!(
+            transcript(&mut runner, source),
+            "1\n[line 1] runtime error: Stack overflow.\n"
+        );
+        // Calls that failed don't count anymore.
+        assert_eq!(transcript(&mut runner, "f(49); print 2;"), "2\n");
+    }
+
+    #[test]
+    fn default_call_depth_fits_a_main_thread() {
+        crate::runner::with_stack_size(8 << 20, || {
+            let source = "fun f() { f(); } f();";
+            assert_eq!(run(source), "[line 1] runtime error: Stack overflow.\n");
+        })
+        .unwrap();
+    }
+}
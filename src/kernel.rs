@@ -0,0 +1,581 @@
+//! `rlox kernel <connection-file>`: a Jupyter kernel keeping one interpreter
+//! for the whole notebook. Register it with a kernelspec like
+//!
+//! ```json
+//! {"argv": ["rlox", "kernel", "{connection_file}"], "display_name": "Lox", "language": "lox"}
+//! ```
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use rlox::diagnostic::{Diagnostic, DiagnosticRenderer};
+use rlox::Runner;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use zeromq::prelude::*;
+use zeromq::{PubSocket, RepSocket, RouterSocket, ZmqMessage};
+
+const PROTOCOL_VERSION: &str = "5.3";
+/// Separates the routing identities from the signed parts of a message.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Serves Jupyter requests until the frontend asks the kernel to shut down.
+pub fn run_kernel(connection_file: &Path, runner: Runner) -> Result<(), String> {
+    let text = std::fs::read_to_string(connection_file)
+        .map_err(|e| format!("Cant read {}: {e}", connection_file.display()))?;
+    let connection: Value = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid connection file {}: {e}", connection_file.display()))?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Cant start kernel runtime: {e}"))?;
+    runtime.block_on(serve(&connection, runner))
+}
+
+async fn serve(connection: &Value, runner: Runner) -> Result<(), String> {
+    let endpoint = |port: &str| -> Result<String, String> {
+        let field = |name: &str| {
+            connection
+                .get(name)
+                .ok_or_else(|| format!("Connection file is missing {name:?}"))
+        };
+        let transport = field("transport")?.as_str().unwrap_or("tcp");
+        let ip = field("ip")?.as_str().unwrap_or("127.0.0.1");
+        Ok(format!("{transport}://{ip}:{}", field(port)?))
+    };
+    let key = connection.get("key").and_then(Value::as_str).unwrap_or("");
+    let session = Session::new(key)?;
+
+    spawn_heartbeat(endpoint("hb_port")?);
+    let iopub = spawn_iopub(endpoint("iopub_port")?);
+    let mut shell = bind::<RouterSocket>(&endpoint("shell_port")?).await?;
+    let mut control = bind::<RouterSocket>(&endpoint("control_port")?).await?;
+    // Input requests aren't supported, but frontends expect the socket.
+    let _stdin = bind::<RouterSocket>(&endpoint("stdin_port")?).await?;
+
+    let mut kernel = Kernel {
+        runner,
+        session,
+        iopub,
+        execution_count: 0,
+    };
+    loop {
+        let (message, from_control) = tokio::select! {
+            message = shell.recv() => (message, false),
+            message = control.recv() => (message, true),
+        };
+        let message = message.map_err(|e| format!("Cant receive request: {e}"))?;
+        let request = match kernel.session.parse(message) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("Ignoring message: {e}");
+                continue;
+            }
+        };
+        let (reply, shutdown) = kernel.handle(&request);
+        if let Some(reply) = reply {
+            let socket = if from_control {
+                &mut control
+            } else {
+                &mut shell
+            };
+            socket
+                .send(reply)
+                .await
+                .map_err(|e| format!("Cant send reply: {e}"))?;
+        }
+        if shutdown {
+            return Ok(());
+        }
+    }
+}
+
+async fn bind<S: Socket>(endpoint: &str) -> Result<S, String> {
+    let mut socket = S::new();
+    socket
+        .bind(endpoint)
+        .await
+        .map_err(|e| format!("Cant bind {endpoint}: {e}"))?;
+    Ok(socket)
+}
+
+/// Answers heartbeats from its own thread so they keep coming while a cell
+/// runs.
+fn spawn_heartbeat(endpoint: String) {
+    thread::spawn(move || {
+        background(async move {
+            let mut socket = bind::<RepSocket>(&endpoint).await?;
+            loop {
+                let ping = socket.recv().await.map_err(|e| e.to_string())?;
+                socket.send(ping).await.map_err(|e| e.to_string())?;
+            }
+        })
+    });
+}
+
+/// Publishes iopub messages from its own thread, so output printed by a
+/// running cell reaches the notebook as it is written.
+fn spawn_iopub(endpoint: String) -> UnboundedSender<ZmqMessage> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        background(async move {
+            let mut socket = bind::<PubSocket>(&endpoint).await?;
+            while let Some(message) = receiver.recv().await {
+                socket.send(message).await.map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    });
+    sender
+}
+
+fn background(task: impl std::future::Future<Output = Result<(), String>>) {
+    let result = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+        .and_then(|runtime| runtime.block_on(task));
+    if let Err(e) = result {
+        eprintln!("Kernel socket failed: {e}");
+    }
+}
+
+struct Request {
+    identities: Vec<Bytes>,
+    header: Value,
+    content: Value,
+}
+
+impl Request {
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or("")
+    }
+}
+
+/// Signs outgoing messages and checks incoming ones with the connection key.
+#[derive(Clone)]
+struct Session {
+    /// `None` when the connection file has an empty key, which disables
+    /// signing.
+    mac: Option<Hmac<Sha256>>,
+    id: String,
+    counter: Arc<AtomicU64>,
+}
+
+impl Session {
+    fn new(key: &str) -> Result<Self, String> {
+        let mac = match key {
+            "" => None,
+            key => Some(
+                Hmac::new_from_slice(key.as_bytes()).map_err(|e| format!("Invalid key: {e}"))?,
+            ),
+        };
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Ok(Session {
+            mac,
+            id: format!("{:x}-{nanos:x}", std::process::id()),
+            counter: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    fn signature(&self, parts: &[&[u8]]) -> String {
+        let Some(mac) = &self.mac else {
+            return String::new();
+        };
+        let mut mac = mac.clone();
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn parse(&self, message: ZmqMessage) -> Result<Request, String> {
+        let mut frames = message.into_vec();
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame.as_ref() == DELIMITER)
+            .ok_or("missing delimiter")?;
+        let parts = frames.split_off(delimiter);
+        let [_, signature, header, parent, metadata, content, ..] = parts.as_slice() else {
+            return Err("missing message parts".to_string());
+        };
+        let expected = self.signature(&[header, parent, metadata, content]);
+        if signature.as_ref() != expected.as_bytes() {
+            return Err("invalid signature".to_string());
+        }
+        let json = |part: &Bytes| {
+            serde_json::from_slice(part).map_err(|e| format!("invalid JSON in message: {e}"))
+        };
+        Ok(Request {
+            identities: frames,
+            header: json(header)?,
+            content: json(content)?,
+        })
+    }
+
+    fn message(
+        &self,
+        identities: Vec<Bytes>,
+        msg_type: &str,
+        parent: &Value,
+        content: Value,
+    ) -> ZmqMessage {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        let header = json!({
+            "msg_id": format!("{}-{count}", self.id),
+            "session": self.id,
+            "username": "rlox",
+            "date": timestamp(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+        let parts = [header, parent.clone(), json!({}), content].map(|part| part.to_string());
+        let signature = self.signature(&parts.each_ref().map(|part| part.as_bytes()));
+        let mut frames = identities;
+        frames.push(Bytes::from_static(DELIMITER));
+        frames.push(Bytes::from(signature));
+        frames.extend(parts.map(Bytes::from));
+        ZmqMessage::try_from(frames).expect("message has frames")
+    }
+}
+
+struct Kernel {
+    runner: Runner,
+    session: Session,
+    iopub: UnboundedSender<ZmqMessage>,
+    execution_count: u64,
+}
+
+impl Kernel {
+    /// Returns the reply to send back, if any, and whether to shut down.
+    fn handle(&mut self, request: &Request) -> (Option<ZmqMessage>, bool) {
+        self.publish(
+            &request.header,
+            "status",
+            json!({"execution_state": "busy"}),
+        );
+        let (content, shutdown) = match request.msg_type() {
+            "kernel_info_request" => (Some(kernel_info()), false),
+            "execute_request" => (Some(self.execute(request)), false),
+            "interrupt_request" => (Some(json!({"status": "ok"})), false),
+            "shutdown_request" => {
+                let restart = request.content["restart"].as_bool().unwrap_or(false);
+                (Some(json!({"status": "ok", "restart": restart})), true)
+            }
+            _ => (None, false),
+        };
+        self.publish(
+            &request.header,
+            "status",
+            json!({"execution_state": "idle"}),
+        );
+        let reply = content.map(|content| {
+            let msg_type = request.msg_type().replace("_request", "_reply");
+            self.session.message(
+                request.identities.clone(),
+                &msg_type,
+                &request.header,
+                content,
+            )
+        });
+        (reply, shutdown)
+    }
+
+    fn execute(&mut self, request: &Request) -> Value {
+        let code = request.content["code"].as_str().unwrap_or("");
+        let silent = request.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+            self.publish(
+                &request.header,
+                "execute_input",
+                json!({"code": code, "execution_count": self.execution_count}),
+            );
+        }
+        let stdout = Stream {
+            name: "stdout",
+            buffer: Vec::new(),
+            session: self.session.clone(),
+            iopub: self.iopub.clone(),
+            parent: request.header.clone(),
+        };
+        self.runner.interpreter_mut().set_output(stdout);
+        let result = self.runner.run_interactive(code);
+        self.runner.interpreter_mut().set_output(io::stdout());
+
+        let origin = format!("<cell {}>", self.execution_count);
+        let renderer = DiagnosticRenderer::new(code, &origin, true);
+        let (errors, warnings): (Vec<&Diagnostic>, _) =
+            result.diagnostics.iter().partition(|d| d.is_error());
+        if !warnings.is_empty() {
+            let text: String = warnings.iter().map(|d| renderer.render(d) + "\n").collect();
+            self.publish(
+                &request.header,
+                "stream",
+                json!({"name": "stderr", "text": text}),
+            );
+        }
+        if let Some(first) = errors.first() {
+            let error = json!({
+                "ename": format!("{} error", first.kind),
+                "evalue": first.message,
+                "traceback": errors.iter().map(|d| renderer.render(d)).collect::<Vec<_>>(),
+            });
+            self.publish(&request.header, "error", error.clone());
+            let mut reply = json!({"status": "error", "execution_count": self.execution_count});
+            reply
+                .as_object_mut()
+                .unwrap()
+                .extend(error.as_object().unwrap().clone());
+            return reply;
+        }
+        if let Some(value) = result.value.filter(|_| !silent) {
+            self.publish(
+                &request.header,
+                "execute_result",
+                json!({
+                    "execution_count": self.execution_count,
+                    "data": {"text/plain": format!("{value:?}")},
+                    "metadata": {},
+                }),
+            );
+        }
+        json!({
+            "status": "ok",
+            "execution_count": self.execution_count,
+            "payload": [],
+            "user_expressions": {},
+        })
+    }
+
+    fn publish(&self, parent: &Value, msg_type: &str, content: Value) {
+        publish(&self.session, &self.iopub, parent, msg_type, content);
+    }
+}
+
+fn publish(
+    session: &Session,
+    iopub: &UnboundedSender<ZmqMessage>,
+    parent: &Value,
+    msg_type: &str,
+    content: Value,
+) {
+    let topic = vec![Bytes::from(msg_type.to_string())];
+    // The iopub thread only stops if its socket failed, which it reported.
+    let _ = iopub.send(session.message(topic, msg_type, parent, content));
+}
+
+fn kernel_info() -> Value {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "rlox",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "lox",
+            "version": env!("CARGO_PKG_VERSION"),
+            "mimetype": "text/x-lox",
+            "file_extension": ".lox",
+        },
+        "banner": format!("rlox {}", env!("CARGO_PKG_VERSION")),
+    })
+}
+
+/// Output of a cell, sent to the notebook each time the interpreter flushes.
+struct Stream {
+    name: &'static str,
+    buffer: Vec<u8>,
+    session: Session,
+    iopub: UnboundedSender<ZmqMessage>,
+    parent: Value,
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let text = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.buffer.clear();
+            let content = json!({"name": self.name, "text": text});
+            publish(&self.session, &self.iopub, &self.parent, "stream", content);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The current UTC time in ISO 8601, for message headers.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs();
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    // Days since the epoch to a civil date, after Howard Hinnant's
+    // days_from_civil inverse.
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:06}Z",
+        now.subsec_micros()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn kernel() -> (Kernel, UnboundedReceiver<ZmqMessage>) {
+        let (iopub, messages) = mpsc::unbounded_channel();
+        let kernel = Kernel {
+            runner: Runner::new(),
+            session: Session::new("key").unwrap(),
+            iopub,
+            execution_count: 0,
+        };
+        (kernel, messages)
+    }
+
+    fn request(msg_type: &str, content: Value) -> Request {
+        Request {
+            identities: vec![Bytes::from_static(b"client")],
+            header: json!({"msg_id": "1", "msg_type": msg_type}),
+            content,
+        }
+    }
+
+    /// The type and content of each message published on iopub, without
+    /// the status messages.
+    fn published(messages: &mut UnboundedReceiver<ZmqMessage>) -> Vec<(String, Value)> {
+        let session = Session::new("key").unwrap();
+        let mut published = Vec::new();
+        while let Ok(message) = messages.try_recv() {
+            let message = session.parse(message).unwrap();
+            if message.msg_type() != "status" {
+                published.push((message.msg_type().to_string(), message.content));
+            }
+        }
+        published
+    }
+
+    #[test]
+    fn signs_and_checks_messages() {
+        let session = Session::new("key").unwrap();
+        let identities = vec![Bytes::from_static(b"client")];
+        let message = session.message(identities, "ping", &json!({}), json!({"n": 1}));
+        let request = session.parse(message.clone()).unwrap();
+        assert_eq!(request.identities, [Bytes::from_static(b"client")]);
+        assert_eq!(request.msg_type(), "ping");
+        assert_eq!(request.content, json!({"n": 1}));
+
+        let other = Session::new("other key").unwrap();
+        assert_eq!(other.parse(message).err().unwrap(), "invalid signature");
+        let unsigned = Session::new("").unwrap();
+        assert_eq!(unsigned.signature(&[b"header"]), "");
+        let message = unsigned.message(Vec::new(), "ping", &json!({}), json!({}));
+        assert!(unsigned.parse(message).is_ok());
+    }
+
+    #[test]
+    fn executes_cells() {
+        let (mut kernel, mut messages) = kernel();
+        let execute = request("execute_request", json!({"code": "var a = 1;\nprint a;"}));
+        let (reply, shutdown) = kernel.handle(&execute);
+        assert!(reply.is_some() && !shutdown);
+        let reply = kernel.session.parse(reply.unwrap()).unwrap();
+        assert_eq!(reply.msg_type(), "execute_reply");
+        assert_eq!(reply.content["status"], "ok");
+        assert_eq!(reply.content["execution_count"], 1);
+        let cell = published(&mut messages);
+        assert_eq!(cell[0].0, "execute_input");
+        assert_eq!(cell[0].1["execution_count"], 1);
+        assert_eq!(
+            cell[1],
+            ("stream".to_string(), json!({"name": "stdout", "text": "1\n"}))
+        );
+
+        kernel.handle(&request("execute_request", json!({"code": "a + 1"})));
+        let (msg_type, result) = &published(&mut messages)[1];
+        assert_eq!(msg_type, "execute_result");
+        assert_eq!(result["data"]["text/plain"], "2");
+        assert_eq!(result["execution_count"], 2);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let (mut kernel, mut messages) = kernel();
+        let (reply, _) = kernel.handle(&request("execute_request", json!({"code": "nil();"})));
+        let reply = kernel.session.parse(reply.unwrap()).unwrap();
+        assert_eq!(reply.content["status"], "error");
+        assert_eq!(reply.content["ename"], "runtime error");
+        let published = published(&mut messages);
+        assert_eq!(published[1].0, "error");
+        assert_eq!(published[1].1["evalue"], reply.content["evalue"]);
+    }
+
+    #[test]
+    fn silent_cells_are_not_counted() {
+        let (mut kernel, mut messages) = kernel();
+        let silent = request("execute_request", json!({"code": "1 + 1", "silent": true}));
+        let (reply, _) = kernel.handle(&silent);
+        let reply = kernel.session.parse(reply.unwrap()).unwrap();
+        assert_eq!(reply.content["execution_count"], 0);
+        assert_eq!(published(&mut messages), []);
+    }
+
+    #[test]
+    fn answers_info_and_shutdown() {
+        let (mut kernel, _messages) = kernel();
+        let (reply, shutdown) = kernel.handle(&request("kernel_info_request", json!({})));
+        let reply = kernel.session.parse(reply.unwrap()).unwrap();
+        assert_eq!(reply.content["language_info"]["name"], "lox");
+        assert!(!shutdown);
+        let (reply, shutdown) = kernel.handle(&request("shutdown_request", json!({})));
+        assert!(reply.is_some() && shutdown);
+        let (reply, shutdown) = kernel.handle(&request("comm_info_request", json!({})));
+        assert!(reply.is_none() && !shutdown);
+    }
+
+    #[test]
+    fn timestamps_are_iso_8601() {
+        let timestamp = timestamp();
+        assert_eq!(timestamp.len(), "2024-01-01T00:00:00.000000Z".len());
+        assert!(timestamp.starts_with("20") && timestamp.ends_with('Z'));
+        assert_eq!(&timestamp[10..11], "T");
+    }
+}
@@ -1,4 +1,33 @@
 pub mod scanner;
 pub mod parser;
+pub mod resolver;
+pub mod symbols;
 pub mod interpreter;
+pub mod dialect;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+pub mod diagnostic;
+pub mod messages;
+pub mod formatter;
+pub mod error;
+pub mod runner;
+pub mod bind;
+pub mod hook;
+pub mod stats;
+pub mod testing;
+pub mod fuzz;
+#[cfg(feature = "async")]
+pub mod async_interpreter;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod loader;
+mod natives;
 mod util;
+
+pub use error::Error;
+pub use fuzz::{fuzz_run, Outcome};
+pub use runner::{run, RunResult, Runner};
+#[cfg(feature = "derive")]
+pub use rlox_derive::LoxBind;
@@ -0,0 +1,36 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::{Parser, Stmt};
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use std::path::{Path, PathBuf};
+
+/// The file of the module `name`: `name.lox` in `dir`, the directory of
+/// the importing file, or else in the first directory of `search_path`
+/// having one. The path is canonical, so a module has one identity however
+/// it was found.
+pub(crate) fn find(name: &str, dir: &Path, search_path: &[PathBuf]) -> Option<PathBuf> {
+    let file_name = format!("{name}.lox");
+    std::iter::once(dir)
+        .chain(search_path.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+        .and_then(|path| path.canonicalize().ok())
+}
+
+/// Scans, parses and resolves the source of a module. Returns the first
+/// error if it has any.
+pub(crate) fn parse(source: &str) -> Result<Vec<Stmt>, Box<Diagnostic>> {
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+    if let Some(error) = scanner.errors.first() {
+        return Err(Box::new(Diagnostic::from(error)));
+    }
+    let statments = Parser::new(scanner.tokens)
+        .parse()
+        .map_err(|errors| Box::new(Diagnostic::from(&errors[0])))?;
+    let diagnostics = Resolver::new().resolve(&statments);
+    match diagnostics.into_iter().find(Diagnostic::is_error) {
+        Some(error) => Err(Box::new(error)),
+        None => Ok(statments),
+    }
+}
@@ -0,0 +1,440 @@
+//! `rlox lsp`: a language server speaking LSP over stdin and stdout. It
+//! reports diagnostics as documents change and answers go-to-definition,
+//! document symbol and completion requests. Documents are synced whole.
+
+use rlox::diagnostic::{Diagnostic, LineIndex, Severity};
+use rlox::scanner::KEYWORDS;
+use rlox::symbols::{SymbolIndex, SymbolKind};
+use rlox::Runner;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// JSON-RPC error codes.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves requests until the client sends `exit`.
+pub fn run_lsp(runner: Runner) -> io::Result<()> {
+    let mut server = Server {
+        runner,
+        documents: HashMap::new(),
+        output: io::stdout(),
+    };
+    let mut input = io::stdin().lock();
+    while let Some(message) = read_message(&mut input)? {
+        if !server.handle(message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// An open document with the symbols of its last version, if it parsed.
+struct Document {
+    source: String,
+    lines: LineIndex,
+    symbols: Option<SymbolIndex>,
+}
+
+impl Document {
+    fn range(&self, start: usize, end: usize) -> Value {
+        json!({"start": self.position(start), "end": self.position(end)})
+    }
+
+    /// LSP position of a character offset. LSP counts columns in UTF-16
+    /// code units, spans count characters.
+    fn position(&self, offset: usize) -> Value {
+        let line = self.lines.line_of(offset);
+        let start = self.lines.line_start(line).unwrap_or_default();
+        let character: usize = self
+            .lines
+            .line_text(&self.source, line)
+            .chars()
+            .take(offset - start)
+            .map(char::len_utf16)
+            .sum();
+        json!({"line": line, "character": character})
+    }
+
+    /// Character offset of an LSP position, clamped to the end of its line.
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(start) = self.lines.line_start(line) else {
+            return self.lines.len();
+        };
+        let mut column = 0;
+        let chars = self
+            .lines
+            .line_text(&self.source, line)
+            .chars()
+            .take_while(|c| {
+                column += c.len_utf16();
+                column <= character
+            })
+            .count();
+        start + chars
+    }
+}
+
+struct Server<W> {
+    runner: Runner,
+    documents: HashMap<String, Document>,
+    output: W,
+}
+
+impl<W: Write> Server<W> {
+    /// Handles one message. Returns false once the client asks to exit.
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id").cloned() else {
+            match method {
+                "exit" => return Ok(false),
+                "textDocument/didOpen" => {
+                    let document = &params["textDocument"];
+                    self.update(document["uri"].as_str(), document["text"].as_str())?;
+                }
+                "textDocument/didChange" => {
+                    let text = params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str());
+                    self.update(params["textDocument"]["uri"].as_str(), text)?;
+                }
+                "textDocument/didClose" => {
+                    if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                        self.documents.remove(uri);
+                    }
+                }
+                _ => {}
+            }
+            return Ok(true);
+        };
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": {"name": "rlox", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+        };
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        };
+        self.send(&response)?;
+        Ok(true)
+    }
+
+    /// Stores a new version of a document and publishes its diagnostics.
+    fn update(&mut self, uri: Option<&str>, text: Option<&str>) -> io::Result<()> {
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return Ok(());
+        };
+        let (statments, result) = self.runner.parse(text);
+        let document = Document {
+            source: text.to_string(),
+            lines: LineIndex::new(text),
+            symbols: statments.map(|statments| SymbolIndex::new(&statments)),
+        };
+        let diagnostics: Vec<Value> = result
+            .diagnostics
+            .iter()
+            .map(|d| diagnostic(&document, d))
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (uri, document, offset) = self.locate(params)?;
+        let Some(symbol) = document
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.definition_at(offset))
+        else {
+            return Ok(Value::Null);
+        };
+        Ok(json!({
+            "uri": uri,
+            "range": document.range(symbol.span.start, symbol.span.end),
+        }))
+    }
+
+    fn document_symbols(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let document = self.document(&params["textDocument"])?.1;
+        let symbols = document.symbols.iter().flat_map(|s| s.symbols.iter());
+        let symbols: Vec<Value> = symbols
+            .map(|symbol| {
+                let range = document.range(symbol.span.start, symbol.span.end);
+                json!({
+                    "name": symbol.name,
+                    "kind": symbol_kind(symbol.kind),
+                    "range": range,
+                    "selectionRange": range,
+                })
+            })
+            .collect();
+        Ok(Value::Array(symbols))
+    }
+
+    /// Keywords, globals and the names declared before the cursor.
+    fn completion(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (_, document, offset) = self.locate(params)?;
+        let mut items: Vec<(String, u8)> = KEYWORDS
+            .iter()
+            .map(|keyword| (keyword.to_string(), 14))
+            .collect();
+        items.extend(
+            self.runner
+                .interpreter()
+                .global_names()
+                .map(|name| (name, 3)),
+        );
+        let declared = document.symbols.iter().flat_map(|s| s.symbols.iter());
+        items.extend(
+            declared
+                .filter(|symbol| symbol.span.end <= offset)
+                .map(|symbol| match symbol.kind {
+                    SymbolKind::Variable => (symbol.name.clone(), 6),
+                    SymbolKind::Module => (symbol.name.clone(), 9),
+                    SymbolKind::Function => (symbol.name.clone(), 3),
+                    SymbolKind::Class => (symbol.name.clone(), 7),
+                }),
+        );
+        items.sort();
+        items.dedup_by(|a, b| a.0 == b.0);
+        let items: Vec<Value> = items
+            .into_iter()
+            .map(|(label, kind)| json!({"label": label, "kind": kind}))
+            .collect();
+        Ok(Value::Array(items))
+    }
+
+    fn document(&self, text_document: &Value) -> Result<(String, &Document), (i64, String)> {
+        let uri = text_document["uri"]
+            .as_str()
+            .ok_or((INVALID_PARAMS, "Missing document uri".to_string()))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown document {uri}")))?;
+        Ok((uri.to_string(), document))
+    }
+
+    /// The document and character offset of a text document position.
+    fn locate(&self, params: &Value) -> Result<(String, &Document, usize), (i64, String)> {
+        let (uri, document) = self.document(&params["textDocument"])?;
+        let position = &params["position"];
+        let (Some(line), Some(character)) =
+            (position["line"].as_u64(), position["character"].as_u64())
+        else {
+            return Err((INVALID_PARAMS, "Missing position".to_string()));
+        };
+        let offset = document.offset(line as usize, character as usize);
+        Ok((uri, document, offset))
+    }
+
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()
+    }
+}
+
+/// Reads one message, or `None` at the end of the input. Messages that
+/// aren't valid JSON are skipped.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        let mut body = vec![0; length];
+        input.read_exact(&mut body)?;
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Ok(Some(message));
+        }
+    }
+}
+
+fn diagnostic(document: &Document, diagnostic: &Diagnostic) -> Value {
+    let range = match (diagnostic.span, diagnostic.line) {
+        (Some(span), _) => document.range(span.start, span.end),
+        (None, Some(line)) => json!({
+            "start": {"line": line.saturating_sub(1), "character": 0},
+            "end": {"line": line, "character": 0},
+        }),
+        (None, None) => json!({
+            "start": {"line": 0, "character": 0},
+            "end": {"line": 0, "character": 0},
+        }),
+    };
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    json!({
+        "range": range,
+        "severity": severity,
+        "code": diagnostic.code,
+        "source": "rlox",
+        "message": diagnostic.message,
+    })
+}
+
+fn symbol_kind(kind: SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::Variable => 13,
+        SymbolKind::Module => 2,
+        SymbolKind::Function => 12,
+        SymbolKind::Class => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server<Vec<u8>> {
+        Server {
+            runner: Runner::new(),
+            documents: HashMap::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Handles a message and returns the messages the server sent back.
+    fn handle(server: &mut Server<Vec<u8>>, message: Value) -> Vec<Value> {
+        assert!(server.handle(message).unwrap());
+        let output = std::mem::take(&mut server.output);
+        let mut input = output.as_slice();
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut input).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn open(server: &mut Server<Vec<u8>>, text: &str) -> Vec<Value> {
+        let params = json!({"textDocument": {"uri": "file:///a.lox", "text": text}});
+        handle(server, json!({"method": "textDocument/didOpen", "params": params}))
+    }
+
+    fn request(server: &mut Server<Vec<u8>>, method: &str, line: u64, character: u64) -> Value {
+        let params = json!({
+            "textDocument": {"uri": "file:///a.lox"},
+            "position": {"line": line, "character": character},
+        });
+        let mut messages = handle(server, json!({"id": 1, "method": method, "params": params}));
+        assert_eq!(messages.len(), 1);
+        messages.remove(0)
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let input = "Content-Length: 2\r\n\r\n{]Content-Length: 12\r\n\r\n{\"method\":1}";
+        let mut input = input.as_bytes();
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"method": 1})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn positions_count_utf16() {
+        let text = "var s = \"😀\";\nprint s;";
+        let document = Document {
+            source: text.to_string(),
+            lines: LineIndex::new(text),
+            symbols: None,
+        };
+        assert_eq!(document.position(11), json!({"line": 0, "character": 12}));
+        assert_eq!(document.offset(0, 12), 11);
+        assert_eq!(document.position(document.offset(1, 6)), json!({"line": 1, "character": 6}));
+        assert_eq!(document.offset(1, 100), text.chars().count());
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = server();
+        let messages = open(&mut server, "var a = ;");
+        assert_eq!(messages[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        let messages = open(&mut server, "var a = 1;");
+        assert_eq!(messages[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let mut server = server();
+        open(&mut server, "var a = 1;\nprint a;");
+        let response = request(&mut server, "textDocument/definition", 1, 6);
+        assert_eq!(response["result"]["uri"], "file:///a.lox");
+        assert_eq!(response["result"]["range"]["start"]["line"], 0);
+        let response = request(&mut server, "textDocument/definition", 1, 0);
+        assert_eq!(response["result"], Value::Null);
+    }
+
+    #[test]
+    fn lists_symbols_and_completions() {
+        let mut server = server();
+        open(&mut server, "fun f() {}\nclass C {}\n");
+        let response = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        let symbols = response["result"].as_array().unwrap();
+        let names: Vec<&str> = symbols.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["f", "C"]);
+        assert_eq!(symbols[1]["kind"], 5);
+        let response = request(&mut server, "textDocument/completion", 2, 0);
+        let labels: Vec<&str> = response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        for label in ["C", "f", "clock", "while"] {
+            assert!(labels.contains(&label), "missing {label}");
+        }
+    }
+
+    #[test]
+    fn reports_unknown_methods_and_documents() {
+        let mut server = server();
+        let response = request(&mut server, "textDocument/definition", 0, 0);
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        let messages = handle(&mut server, json!({"id": 2, "method": "nope"}));
+        assert_eq!(messages[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert!(!server.handle(json!({"method": "exit"})).unwrap());
+    }
+}
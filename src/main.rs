@@ -1,61 +1,526 @@
-use rlox::parser::ParsingErrorType;
-use rlox::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+mod alloc_profiler;
+mod bench;
+mod cli;
+mod config;
+mod debugger;
+#[cfg(feature = "kernel")]
+mod kernel;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod post_mortem;
+mod profiler;
+mod repl;
+mod session;
+mod test_runner;
+mod tracer;
+mod transcript;
+
+use alloc_profiler::AllocationProfiler;
+use cli::{CliOptions, Command, ProfileOutput, Trace, USAGE};
+use post_mortem::PostMortem;
+use profiler::Profiler;
+use repl::{editor_config, history_path, rc_path, LoxHelper};
+use tracer::Tracer;
+use transcript::Recorder;
+use rlox::diagnostic::{Diagnostic, DiagnosticKind};
+use rlox::formatter::format_source;
+use rlox::hook::Hook;
+use rlox::messages;
+use rlox::parser::Value;
+use rlox::scanner::Scanner;
+use rlox::{RunResult, Runner};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
 use std::env;
-use std::io::{self, Write};
+use std::io;
+use std::path::Path;
+use std::process;
+
+/// REPL variable holding the result of the last evaluated expression.
+const LAST_VALUE_VARIABLE: &str = "_";
 
 fn main() {
-    let mut interpreter = Interpreter::new();
-    let args = env::args();
-    let args: Vec<String> = args.collect();
-    if let Some(file_path) = args.get(1) {
-        let code = std::fs::read_to_string(file_path).expect("Cant read file");
-        run(&code, &mut interpreter, false);
+    let options = match CliOptions::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            process::exit(64);
+        }
+    };
+    let success = match options.stack_size {
+        Some(stack_size) => {
+            rlox::runner::with_stack_size(stack_size, || run(&options)).unwrap_or_else(|e| {
+                eprintln!("Cant start a thread with a stack of {stack_size} bytes: {e}");
+                process::exit(1);
+            })
+        }
+        None => run(&options),
+    };
+    process::exit(if success { 0 } else { 1 });
+}
+
+/// Runs the command of `options`. Returns whether it succeeded.
+fn run(options: &CliOptions) -> bool {
+    // The catalog is per thread, and this may be one of its own.
+    messages::set_catalog(options.messages.clone());
+    match &options.command {
+        Command::Help => {
+            println!("{USAGE}");
+            true
+        }
+        Command::Repl => {
+            repl(options);
+            true
+        }
+        Command::Run(path) => {
+            let file_path = &script_path(path);
+            let code = read_source(file_path);
+            let mut runner = runner(options);
+            runner.interpreter_mut().set_script_path(file_path);
+            let mut hooks: Vec<Box<dyn Hook>> = Vec::new();
+            let profiler = options.profile.as_ref().map(|_| Profiler::new());
+            if let Some(profiler) = &profiler {
+                hooks.push(Box::new(profiler.clone()));
+            }
+            let allocation_profiler =
+                options.profile_allocations.then(AllocationProfiler::new);
+            if let Some(profiler) = &allocation_profiler {
+                hooks.push(Box::new(profiler.clone()));
+            }
+            if let Some(trace) = options.trace {
+                hooks.push(Box::new(Tracer::new(&code, trace == Trace::Expressions)));
+            }
+            let post_mortem = options.dump_on_error.then(PostMortem::new);
+            if let Some(post_mortem) = &post_mortem {
+                hooks.push(Box::new(post_mortem.clone()));
+            }
+            if !hooks.is_empty() {
+                runner.interpreter_mut().set_hook(hooks);
+            }
+            let result = runner.run(&code);
+            let success = report(&result, &code, file_path, options);
+            if let Some(dump) = post_mortem.and_then(|post_mortem| post_mortem.dump()) {
+                eprint!("{dump}");
+            }
+            if let (Some(profiler), Some(output)) = (profiler, &options.profile) {
+                profiler.finish(runner.interpreter());
+                write_profile(&profiler, output, file_path);
+            }
+            if let Some(profiler) = allocation_profiler {
+                profiler.finish();
+                eprint!("{}", profiler.report(file_path));
+            }
+            if options.dialect.jlox && !success {
+                // Like jlox, exit with 70 for runtime errors and 65 for
+                // errors found before running.
+                let runtime = result.diagnostics.iter().any(|d| d.kind == DiagnosticKind::Runtime);
+                process::exit(if runtime { 70 } else { 65 });
+            }
+            success
+        }
+        Command::Check(file_path) => {
+            let code = read_source(file_path);
+            let result = runner(options).check(&code);
+            report(&result, &code, file_path, options)
+        }
+        Command::Lint(file_path) => {
+            let code = read_source(file_path);
+            let mut runner = runner(options);
+            let mut warnings = options.warnings.clone();
+            warnings.enable_lints();
+            runner.set_warnings(warnings);
+            let result = runner.check(&code);
+            // Unlike check, any warning fails.
+            report(&result, &code, file_path, options) && result.diagnostics.is_empty()
+        }
+        Command::Fmt(file_path) => {
+            let code = read_source(file_path);
+            let result = runner(options).check(&code);
+            let well_formed = report(&result, &code, file_path, options);
+            if well_formed {
+                print!("{}", format_source(&code));
+            }
+            well_formed
+        }
+        Command::Ast(file_path) => {
+            let code = read_source(file_path);
+            let (statments, result) = runner(options).parse(&code);
+            report(&result, &code, file_path, options);
+            for stmt in statments.iter().flatten() {
+                println!("{stmt}");
+            }
+            statments.is_some()
+        }
+        Command::Tokens(file_path) => {
+            let code = read_source(file_path);
+            let mut reporter = options.reporter(&code, file_path);
+            let mut scanner = Scanner::with_dialect(&code, options.dialect);
+            scanner.scan_tokens();
+            for token in scanner.tokens.iter() {
+                println!(
+                    "{:>4} {:?} {:?}",
+                    token.line, token.token_type, token.lexeme
+                );
+            }
+            for e in scanner.errors.iter() {
+                reporter.report(&Diagnostic::from(e));
+            }
+            reporter.finish();
+            scanner.errors.is_empty()
+        }
+        Command::Replay(file_path) => replay(file_path, options),
+        Command::Debug(file_path) => {
+            let code = read_source(file_path);
+            let mut runner = runner(options);
+            let debugger = debugger::Debugger::new(file_path, &code);
+            runner.interpreter_mut().set_hook(debugger);
+            let result = runner.run(&code);
+            report(&result, &code, file_path, options)
+        }
+        Command::Test(dir) if options.suite => {
+            test_runner::run_suite(Path::new(dir), options.chapter, &options.skips, options.jobs())
+        }
+        Command::Test(dir) => test_runner::run_tests(Path::new(dir), options.jobs()),
+        Command::Bench(file_path) => {
+            let code = read_source(file_path);
+            let new_runner = || runner(options);
+            match bench::run_bench(&code, new_runner, options.iterations, options.warmup) {
+                Ok(times) => {
+                    print!("{}", bench::report(file_path, &times, options.warmup));
+                    true
+                }
+                Err(result) => report(&result, &code, file_path, options),
+            }
+        }
+        #[cfg(feature = "kernel")]
+        Command::Kernel(connection_file) => {
+            match kernel::run_kernel(Path::new(connection_file), runner(options)) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("{e}");
+                    false
+                }
+            }
+        }
+        #[cfg(not(feature = "kernel"))]
+        Command::Kernel(_) => {
+            eprintln!("This rlox was built without the kernel feature.");
+            false
+        }
+        #[cfg(feature = "lsp")]
+        Command::Lsp => match lsp::run_lsp(runner(options)) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+        },
+        #[cfg(not(feature = "lsp"))]
+        Command::Lsp => {
+            eprintln!("This rlox was built without the lsp feature.");
+            false
+        }
+    }
+}
+
+fn read_source(file_path: &str) -> String {
+    std::fs::read_to_string(file_path).unwrap_or_else(|e| {
+        eprintln!("Cant read {file_path}: {e}");
+        process::exit(66);
+    })
+}
+
+/// The script `rlox run` runs for `path`: the file itself, or the
+/// `main.lox` of a project directory.
+fn script_path(path: &str) -> String {
+    if Path::new(path).is_dir() {
+        Path::new(path).join("main.lox").to_string_lossy().into_owned()
     } else {
-        let mut s = String::new();
-        loop {
-            print!("> ");
-            io::stdout().flush().unwrap();
-            let read_status = io::stdin().read_line(&mut s);
-            if let Err(_) = read_status {
-                break;
-            };
-            run(&s, &mut interpreter, true);
-            s.clear();
-        }
-    }
-}
-
-fn run(source: &String, interpreter: &mut Interpreter, repl_mode: bool) {
-    let mut scanner = Scanner::new(source);
-    scanner.scan_tokens();
-    // println!("{:#?}", scanner.tokens);
-    let mut parser = Parser::new(scanner.tokens);
-    let statments = parser.parse();
-    if let Err(errors) = statments {
-        if repl_mode
-            && errors.len() == 1
-            && errors[0].error_type == ParsingErrorType::Stmt
-            && errors[0].expression.is_some()
-        {
-            let expr = errors[0].expression.as_ref().unwrap();
-            match interpreter.evaluate(expr) {
-                Ok(value) => {
-                    println!("{value:?}");
+        path.to_string()
+    }
+}
+
+fn runner(options: &CliOptions) -> Runner {
+    let mut runner = if options.dialect.jlox {
+        Runner::jlox()
+    } else if options.no_prelude {
+        Runner::without_prelude()
+    } else {
+        Runner::new()
+    };
+    if !options.dialect.jlox {
+        runner.set_warnings(options.warnings.clone());
+    }
+    runner.set_keep_going(options.keep_going);
+    if let Some(module_path) = env::var_os("RLOX_PATH") {
+        runner.interpreter_mut().set_module_path(env::split_paths(&module_path).collect());
+    }
+    runner.interpreter_mut().set_dialect(options.dialect);
+    if let Some(stack_size) = options.stack_size {
+        let depth = rlox::interpreter::max_call_depth_for(stack_size);
+        runner.interpreter_mut().set_max_call_depth(Some(depth));
+    }
+    for capability in options.capabilities.iter() {
+        runner.interpreter_mut().allow(*capability);
+    }
+    for path in options.plugins.iter() {
+        if let Err(e) = runner.interpreter_mut().load_plugin(path) {
+            eprintln!("Cant load plugin {path}: {e}");
+            process::exit(1);
+        }
+    }
+    if !options.plugins.is_empty() {
+        // Loading already succeeded once, so errors were reported.
+        let plugins = options.plugins.clone();
+        runner.interpreter_mut().set_thread_setup(move |interpreter| {
+            for path in plugins.iter() {
+                let _ = interpreter.load_plugin(path);
+            }
+        });
+    }
+    runner
+}
+
+fn write_profile(profiler: &Profiler, output: &ProfileOutput, origin: &str) {
+    match output {
+        ProfileOutput::Report => eprint!("{}", profiler.report(origin)),
+        ProfileOutput::FoldedStacks(path) => {
+            if let Err(e) = std::fs::write(path, profiler.folded_stacks()) {
+                eprintln!("Cant write profile to {path}: {e}");
+            }
+        }
+    }
+}
+
+/// Prints the diagnostics of a run, and its timings with `--time`. Returns
+/// whether it succeeded.
+fn report(result: &RunResult, source: &str, origin: &str, options: &CliOptions) -> bool {
+    let mut reporter = options.reporter(source, origin);
+    for d in result.diagnostics.iter() {
+        reporter.report(d);
+    }
+    reporter.finish();
+    if options.time {
+        for (phase, duration) in result.timings.iter() {
+            eprintln!("[time] {phase:<10} {duration:?}");
+        }
+    }
+    result.success()
+}
+
+fn repl(options: &CliOptions) {
+    let mut runner = runner(options);
+    if let Some(path) = rc_path().filter(|path| !options.no_rc && path.is_file()) {
+        match std::fs::read_to_string(&path) {
+            Ok(code) => {
+                let result = runner.run(&code);
+                report(&result, &code, &path.to_string_lossy(), options);
+            }
+            Err(e) => eprintln!("Cant read {}: {e}", path.display()),
+        }
+    }
+    let mut editor: Editor<LoxHelper, _> =
+        Editor::with_config(editor_config()).expect("Cant start line editor");
+    editor.set_helper(Some(LoxHelper::new()));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(path);
+    }
+    let mut recorder: Option<Recorder> = None;
+    loop {
+        match editor.readline("> ") {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                let pasted = line.trim().contains('\n');
+                let command = line.trim().strip_prefix(':').filter(|_| !pasted);
+                match command.map(|command| command.split_once(' ').unwrap_or((command, ""))) {
+                    Some(("record", argument)) => {
+                        stop_recording(&mut recorder, &mut runner);
+                        recorder = start_recording(argument.trim(), &mut runner);
+                    }
+                    Some(("stop", _)) => stop_recording(&mut recorder, &mut runner),
+                    _ => {
+                        if let Some(recorder) = &mut recorder {
+                            if let Err(e) = recorder.record_input(&line) {
+                                eprintln!("Cant write to {}: {e}", recorder.path);
+                            }
+                        }
+                        let (_, shown) = run_input(&line, &mut runner, options);
+                        match &mut recorder {
+                            Some(recorder) => {
+                                recorder.show(&shown);
+                                if let Err(e) = recorder.finish_input() {
+                                    eprintln!("Cant write to {}: {e}", recorder.path);
+                                }
+                            }
+                            None => print!("{shown}"),
+                        }
+                    }
                 }
-                Err(e) => eprintln!("{e}"),
-            };
-            return;
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(_) => break,
         }
-        for e in errors.iter() {
-            eprintln!(
-                "[Error while parsing {} at line {}]: {}",
-                e.error_type, e.line, e.message
-            );
+    }
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("Cant save history to {}: {e}", path.display());
         }
-        return;
+    }
+}
+
+/// Handles `:record [--output] <path>`, recording the inputs typed from
+/// now on, and with `--output` what they print, into a transcript.
+fn start_recording(argument: &str, runner: &mut Runner) -> Option<Recorder> {
+    let (with_output, path) = match argument.strip_prefix("--output") {
+        Some(path) => (true, path.trim()),
+        None => (false, argument),
     };
-    // println!("{:#?}", statments);
-    if let Err(e) = interpreter.interpret(statments.unwrap()) {
-        eprintln!("[RuntimeError]: {}", e);
+    if path.is_empty() {
+        eprintln!("Usage: :record [--output] <path>");
+        return None;
+    }
+    match Recorder::create(path, with_output) {
+        Ok(recorder) => {
+            runner.interpreter_mut().set_output(recorder.stdout());
+            Some(recorder)
+        }
+        Err(e) => {
+            eprintln!("Cant create {path}: {e}");
+            None
+        }
+    }
+}
+
+/// Handles `:stop`, ending the recording if there is one.
+fn stop_recording(recorder: &mut Option<Recorder>, runner: &mut Runner) {
+    if recorder.take().is_some() {
+        runner.interpreter_mut().set_output(io::stdout());
+    }
+}
+
+/// Runs an input typed at the prompt, code or a `:command`. Returns
+/// whether it succeeded and what the REPL shows for it, like the value of
+/// an expression.
+fn run_input(input: &str, runner: &mut Runner, options: &CliOptions) -> (bool, String) {
+    let pasted = input.trim().contains('\n');
+    if let Some(command) = input.trim().strip_prefix(':').filter(|_| !pasted) {
+        return run_command(command, runner, options);
+    }
+    let result = runner.run_interactive(input);
+    let success = report(&result, input, "<repl>", options);
+    let mut shown = String::new();
+    if let Some(value) = result.value {
+        shown = format!("{value:?}\n");
+        runner
+            .interpreter_mut()
+            .define_global(LAST_VALUE_VARIABLE, value);
+    }
+    (success, shown)
+}
+
+/// Handles a `:command` typed at the prompt, like `run_input`.
+fn run_command(command: &str, runner: &mut Runner, options: &CliOptions) -> (bool, String) {
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+    let usage = |usage: &str| {
+        eprintln!("Usage: {usage}");
+        (false, String::new())
     };
+    match (name, argument.trim()) {
+        ("load", "") => usage(":load <path>"),
+        ("load", path) => match std::fs::read_to_string(path) {
+            Ok(code) => {
+                let result = runner.run(&code);
+                (report(&result, &code, path, options), String::new())
+            }
+            Err(e) => {
+                eprintln!("Cant read {path}: {e}");
+                (false, String::new())
+            }
+        },
+        ("type", "") => usage(":type <expression>"),
+        ("type", source) => {
+            let result = runner.evaluate(source);
+            let success = report(&result, source, "<repl>", options);
+            let shown = result.value.map(|value| format!("{}\n", value.type_name()));
+            (success, shown.unwrap_or_default())
+        }
+        ("doc", name) => {
+            let name = Some(name).filter(|name| !name.is_empty());
+            match runner.interpreter().help(name) {
+                Ok(text) => (true, text),
+                Err(e) => {
+                    eprintln!("{}", e.message);
+                    (false, String::new())
+                }
+            }
+        }
+        ("env", "") => {
+            let mut shown = String::new();
+            for binding in runner.interpreter().globals().sorted() {
+                if matches!(binding.value, Value::NativeFunction(_) | Value::Module(_)) {
+                    continue;
+                }
+                let (name, type_name) = (&binding.name, binding.type_name());
+                shown.push_str(&format!("{name}: {type_name} = {:?}\n", binding.value));
+            }
+            (true, shown)
+        }
+        ("env", _) => usage(":env"),
+        ("save-session", "") => usage(":save-session <path>"),
+        ("save-session", path) => {
+            let (script, skipped) = session::save(runner.interpreter());
+            if let Err(e) = std::fs::write(path, script) {
+                eprintln!("Cant write {path}: {e}");
+                return (false, String::new());
+            }
+            if !skipped.is_empty() {
+                eprintln!("Not saved, as they can't be written as Lox: {}", skipped.join(", "));
+            }
+            (true, String::new())
+        }
+        ("load-session", "") => usage(":load-session <path>"),
+        ("load-session", path) => match std::fs::read_to_string(path) {
+            Ok(code) => {
+                // The session replaces the globals defined so far.
+                let interpreter = runner.interpreter_mut();
+                let defined: Vec<String> = interpreter
+                    .globals()
+                    .iter()
+                    .filter(|binding| {
+                        !matches!(binding.value, Value::NativeFunction(_) | Value::Module(_))
+                    })
+                    .map(|binding| binding.name)
+                    .collect();
+                interpreter.retain_globals(|name| !defined.iter().any(|defined| defined == name));
+                let result = runner.run(&code);
+                (report(&result, &code, path, options), String::new())
+            }
+            Err(e) => {
+                eprintln!("Cant read {path}: {e}");
+                (false, String::new())
+            }
+        },
+        _ => {
+            eprintln!("Unknown command :{name}");
+            (false, String::new())
+        }
+    }
+}
+
+/// Runs the inputs of a transcript recorded with `:record` as if they were
+/// typed at the prompt, echoing each first. Returns whether all of them
+/// succeeded.
+fn replay(file_path: &str, options: &CliOptions) -> bool {
+    let transcript = read_source(file_path);
+    let mut runner = runner(options);
+    let mut success = true;
+    for input in transcript::parse(&transcript) {
+        print!("{}", transcript::format_input(&input));
+        let (input_success, shown) = run_input(&input, &mut runner, options);
+        print!("{shown}");
+        success &= input_success;
+    }
+    success
 }
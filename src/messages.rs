@@ -0,0 +1,491 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// A message shown to users, known by a stable code. Its English wording
+/// is a template where `{name}` stands for the argument called `name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    pub code: &'static str,
+    pub template: &'static str,
+}
+
+impl Message {
+    /// The message in the wording of this thread's catalog, with each
+    /// `{name}` replaced by the argument called `name`.
+    pub fn with(&self, args: &[(&str, &dyn Display)]) -> Text {
+        let mut text = CATALOG.with(|catalog| catalog.borrow().template(self).to_string());
+        for (name, value) in args {
+            text = text.replace(&format!("{{{name}}}"), &value.to_string());
+        }
+        Text {
+            code: self.code,
+            text,
+        }
+    }
+
+    /// The message, for messages without arguments.
+    pub fn text(&self) -> Text {
+        self.with(&[])
+    }
+}
+
+/// A message worded and filled in, ready to be shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    pub code: &'static str,
+    pub text: String,
+}
+
+impl Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Wording of messages in place of their English templates, to adjust
+/// them or translate them.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    templates: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Catalog::default()
+    }
+
+    /// Words the message with `code` as `template`, which can use the
+    /// same `{name}` arguments as the English one.
+    pub fn set(&mut self, code: impl Into<String>, template: impl Into<String>) {
+        self.templates.insert(code.into(), template.into());
+    }
+
+    pub fn template(&self, message: &Message) -> &str {
+        self.templates
+            .get(message.code)
+            .map_or(message.template, String::as_str)
+    }
+}
+
+thread_local! {
+    static CATALOG: RefCell<Catalog> = RefCell::new(Catalog::default());
+}
+
+/// Words the messages raised on this thread from now on with `catalog`.
+/// Scanning, parsing and running a script all happen on one thread, so
+/// this covers everything a runner reports.
+pub fn set_catalog(catalog: Catalog) {
+    CATALOG.with(|current| *current.borrow_mut() = catalog);
+}
+
+/// The catalog messages raised on this thread are worded with, to pass on
+/// to threads of its own.
+pub fn catalog() -> Catalog {
+    CATALOG.with(|current| current.borrow().clone())
+}
+
+pub const UNEXPECTED_CHARACTER: Message = Message {
+    code: "E0101",
+    template: "Unexpected character {character}.",
+};
+pub const UNTERMINATED_STRING: Message = Message {
+    code: "E0102",
+    template: "Unterminated string.",
+};
+
+pub const EXPECT_EXPRESSION: Message = Message {
+    code: "E0201",
+    template: "Expect expression.",
+};
+pub const TOO_DEEPLY_NESTED: Message = Message {
+    code: "E0202",
+    template: "Too deeply nested.",
+};
+pub const EXPECT_END_OF_EXPRESSION: Message = Message {
+    code: "E0203",
+    template: "Expect end of expression.",
+};
+pub const INVALID_ASSIGNMENT_TARGET: Message = Message {
+    code: "E0204",
+    template: "Invalid assigment target",
+};
+pub const EXPECT_PROPERTY_NAME: Message = Message {
+    code: "E0205",
+    template: "Expect property name after '.'.",
+};
+pub const TOO_MANY_ARGUMENTS: Message = Message {
+    code: "E0206",
+    template: "Can't have more than {max} arguments.",
+};
+pub const EXPECT_PAREN_AFTER_ARGUMENTS: Message = Message {
+    code: "E0207",
+    template: "Expect ')' after arguments.",
+};
+pub const EXPECT_PAREN_AFTER_EXPRESSION: Message = Message {
+    code: "E0208",
+    template: "Expect ')' after expression.",
+};
+pub const RETURN_AT_TOP_LEVEL: Message = Message {
+    code: "E0209",
+    template: "Can't return from top-level code.",
+};
+pub const EXPECT_SEMICOLON_AFTER_VALUE: Message = Message {
+    code: "E0210",
+    template: "Expect ';' after value.",
+};
+pub const EXPECT_SEMICOLON_AFTER_EXPRESSION: Message = Message {
+    code: "E0211",
+    template: "Expect ';' after expression.",
+};
+pub const EXPECT_MODULE_NAME: Message = Message {
+    code: "E0212",
+    template: "Expect module name after 'import'.",
+};
+pub const EXPECT_SEMICOLON_AFTER_IMPORT: Message = Message {
+    code: "E0213",
+    template: "Expect ';' after import.",
+};
+pub const EXPECT_VARIABLE_NAME: Message = Message {
+    code: "E0214",
+    template: "Expect variable name.",
+};
+pub const EXPECT_SEMICOLON_AFTER_VARIABLE: Message = Message {
+    code: "E0215",
+    template: "Expect ';' after variable declaration.",
+};
+pub const EXPECT_BRACE_AFTER_BLOCK: Message = Message {
+    code: "E0216",
+    template: "Expect '}' after block.",
+};
+pub const EXPECT_PAREN_AFTER_IF: Message = Message {
+    code: "E0217",
+    template: "Expect '(' after 'if'.",
+};
+pub const EXPECT_PAREN_AFTER_IF_CONDITION: Message = Message {
+    code: "E0218",
+    template: "Expect ')' after if condition.",
+};
+pub const EXPECT_PAREN_AFTER_WHILE: Message = Message {
+    code: "E0219",
+    template: "Expect '(' after 'while'.",
+};
+pub const EXPECT_PAREN_AFTER_CONDITION: Message = Message {
+    code: "E0220",
+    template: "Expect ')' after condition.",
+};
+pub const EXPECT_PAREN_AFTER_FOR: Message = Message {
+    code: "E0221",
+    template: "Expect '(' after 'for'.",
+};
+pub const EXPECT_SEMICOLON_AFTER_LOOP_CONDITION: Message = Message {
+    code: "E0222",
+    template: "Expect ';' after loop condition.",
+};
+pub const EXPECT_PAREN_AFTER_FOR_CLAUSES: Message = Message {
+    code: "E0223",
+    template: "Expect ')' after for clauses.",
+};
+/// Hint added to a parse error in a statement starting with a misspelled
+/// keyword.
+pub const DID_YOU_MEAN_KEYWORD: Message = Message {
+    code: "E0224",
+    template: "Did you mean '{keyword}'?",
+};
+pub const EXPECT_FUNCTION_NAME: Message = Message {
+    code: "E0225",
+    template: "Expect function name.",
+};
+pub const EXPECT_PAREN_AFTER_FUNCTION_NAME: Message = Message {
+    code: "E0226",
+    template: "Expect '(' after function name.",
+};
+pub const EXPECT_PARAMETER_NAME: Message = Message {
+    code: "E0227",
+    template: "Expect parameter name.",
+};
+pub const EXPECT_PAREN_AFTER_PARAMETERS: Message = Message {
+    code: "E0228",
+    template: "Expect ')' after parameters.",
+};
+pub const EXPECT_BRACE_BEFORE_BODY: Message = Message {
+    code: "E0229",
+    template: "Expect '{' before function body.",
+};
+pub const TOO_MANY_PARAMETERS: Message = Message {
+    code: "E0230",
+    template: "Can't have more than {max} parameters.",
+};
+pub const EXPECT_SEMICOLON_AFTER_RETURN: Message = Message {
+    code: "E0231",
+    template: "Expect ';' after return value.",
+};
+pub const EXPECT_CLASS_NAME: Message = Message {
+    code: "E0232",
+    template: "Expect class name.",
+};
+pub const EXPECT_BRACE_BEFORE_CLASS_BODY: Message = Message {
+    code: "E0233",
+    template: "Expect '{' before class body.",
+};
+pub const EXPECT_BRACE_AFTER_CLASS_BODY: Message = Message {
+    code: "E0234",
+    template: "Expect '}' after class body.",
+};
+pub const EXPECT_METHOD_NAME: Message = Message {
+    code: "E0235",
+    template: "Expect method name.",
+};
+pub const THIS_OUTSIDE_CLASS: Message = Message {
+    code: "E0236",
+    template: "Can't use 'this' outside of a class.",
+};
+pub const RETURN_FROM_INITIALIZER: Message = Message {
+    code: "E0237",
+    template: "Can't return a value from an initializer.",
+};
+
+pub const UNDEFINED_VARIABLE: Message = Message {
+    code: "E0301",
+    template: "Undefined variable {name}.",
+};
+pub const UNDEFINED_VARIABLE_SIMILAR: Message = Message {
+    code: "E0302",
+    template: "Undefined variable {name}. Did you mean '{similar}'?",
+};
+pub const UNASSIGNED_VARIABLE: Message = Message {
+    code: "E0303",
+    template: "Variable '{name}' used before being assigned.",
+};
+pub const OUT_OF_FUEL: Message = Message {
+    code: "E0304",
+    template: "Out of fuel.",
+};
+pub const UNKNOWN_MODULE: Message = Message {
+    code: "E0305",
+    template: "Unknown module '{name}'.",
+};
+pub const UNDEFINED_KEY: Message = Message {
+    code: "E0306",
+    template: "Undefined key '{name}' in map.",
+};
+pub const UNDEFINED_LIST_METHOD: Message = Message {
+    code: "E0307",
+    template: "Lists have no method '{name}'.",
+};
+pub const NO_PROPERTIES: Message = Message {
+    code: "E0308",
+    template: "Only modules, instances, numbers, strings, booleans, lists, maps and foreign \
+               objects have properties.",
+};
+pub const UNDEFINED_PROPERTY: Message = Message {
+    code: "E0309",
+    template: "Undefined property '{name}' in module {module}.",
+};
+pub const NOT_CALLABLE: Message = Message {
+    code: "E0310",
+    template: "Can only call functions and classes.",
+};
+pub const TOO_FEW_ARGUMENTS: Message = Message {
+    code: "E0311",
+    template: "Expected at least {arity} arguments but got {count}.",
+};
+pub const WRONG_ARGUMENT_COUNT: Message = Message {
+    code: "E0312",
+    template: "Expected {arity} arguments but got {count}.",
+};
+pub const OPERAND_NOT_NUMBER: Message = Message {
+    code: "E0313",
+    template: "Operand must be number",
+};
+pub const DIVISION_BY_ZERO: Message = Message {
+    code: "E0314",
+    template: "Division by zero.",
+};
+pub const INFINITE_RESULT: Message = Message {
+    code: "E0315",
+    template: "Result of '{operator}' is infinite.",
+};
+pub const NAN_RESULT: Message = Message {
+    code: "E0316",
+    template: "Result of '{operator}' is not a number.",
+};
+pub const DIVIDE_OPERANDS: Message = Message {
+    code: "E0317",
+    template: "To divide operands must be two numbers",
+};
+pub const MULTIPLY_OPERANDS: Message = Message {
+    code: "E0318",
+    template: "To multiply operands must be two numbers",
+};
+pub const SUBTRACT_OPERANDS: Message = Message {
+    code: "E0319",
+    template: "To subtract operands must be two numbers",
+};
+pub const ADD_OPERANDS: Message = Message {
+    code: "E0320",
+    template: "To add operands must be two numbers or two strings",
+};
+pub const COMPARE_OPERANDS: Message = Message {
+    code: "E0321",
+    template: "To compare operands must be two numbers",
+};
+pub const COMPARE_OPERANDS_OR_STRINGS: Message = Message {
+    code: "E0322",
+    template: "To compare operands must be two numbers or two strings",
+};
+pub const EQUALITY_TYPES: Message = Message {
+    code: "E0323",
+    template: "Cant compare {left} with {right} for equality.",
+};
+pub const CANT_READ_INPUT: Message = Message {
+    code: "E0324",
+    template: "Cant read input: {error}",
+};
+pub const CANT_WRITE_OUTPUT: Message = Message {
+    code: "E0325",
+    template: "Cant write output: {error}",
+};
+/// The jlox wording of `OPERAND_NOT_NUMBER`.
+pub const JLOX_OPERAND_NOT_NUMBER: Message = Message {
+    code: "E0326",
+    template: "Operand must be a number.",
+};
+/// The jlox wording of the errors for operators taking numbers.
+pub const JLOX_OPERANDS_NOT_NUMBERS: Message = Message {
+    code: "E0327",
+    template: "Operands must be numbers.",
+};
+/// The jlox wording of `ADD_OPERANDS`.
+pub const JLOX_ADD_OPERANDS: Message = Message {
+    code: "E0328",
+    template: "Operands must be two numbers or two strings.",
+};
+/// The jlox wording of `UNDEFINED_VARIABLE`.
+pub const JLOX_UNDEFINED_VARIABLE: Message = Message {
+    code: "E0329",
+    template: "Undefined variable '{name}'.",
+};
+pub const DECIMAL_OUT_OF_RANGE: Message = Message {
+    code: "E0330",
+    template: "Number out of range for decimal arithmetic.",
+};
+pub const CANCELLED: Message = Message {
+    code: "E0331",
+    template: "Cancelled.",
+};
+pub const UNDEFINED_FOREIGN_METHOD: Message = Message {
+    code: "E0332",
+    template: "{object} has no method '{name}'.",
+};
+pub const CIRCULAR_IMPORT: Message = Message {
+    code: "E0333",
+    template: "Module '{name}' imports itself, from {path}.",
+};
+pub const MODULE_ERROR: Message = Message {
+    code: "E0334",
+    template: "Cant load module '{name}', {location}: {error}",
+};
+pub const UNDEFINED_VALUE_METHOD: Message = Message {
+    code: "E0335",
+    template: "A {type} has no method '{name}'.",
+};
+pub const UNDEFINED_INSTANCE_PROPERTY: Message = Message {
+    code: "E0336",
+    template: "Undefined property '{name}'.",
+};
+pub const STACK_OVERFLOW: Message = Message {
+    code: "E0337",
+    template: "Stack overflow.",
+};
+pub const ONLY_INSTANCES_HAVE_FIELDS: Message = Message {
+    code: "E0338",
+    template: "Only instances have fields.",
+};
+
+pub const ALREADY_DECLARED: Message = Message {
+    code: "E0401",
+    template: "Already a variable with this name in this scope.",
+};
+
+/// Every message, by code.
+pub const ALL: [Message; 78] = [
+    UNEXPECTED_CHARACTER,
+    UNTERMINATED_STRING,
+    EXPECT_EXPRESSION,
+    TOO_DEEPLY_NESTED,
+    EXPECT_END_OF_EXPRESSION,
+    INVALID_ASSIGNMENT_TARGET,
+    EXPECT_PROPERTY_NAME,
+    TOO_MANY_ARGUMENTS,
+    EXPECT_PAREN_AFTER_ARGUMENTS,
+    EXPECT_PAREN_AFTER_EXPRESSION,
+    RETURN_AT_TOP_LEVEL,
+    EXPECT_SEMICOLON_AFTER_VALUE,
+    EXPECT_SEMICOLON_AFTER_EXPRESSION,
+    EXPECT_MODULE_NAME,
+    EXPECT_SEMICOLON_AFTER_IMPORT,
+    EXPECT_VARIABLE_NAME,
+    EXPECT_SEMICOLON_AFTER_VARIABLE,
+    EXPECT_BRACE_AFTER_BLOCK,
+    EXPECT_PAREN_AFTER_IF,
+    EXPECT_PAREN_AFTER_IF_CONDITION,
+    EXPECT_PAREN_AFTER_WHILE,
+    EXPECT_PAREN_AFTER_CONDITION,
+    EXPECT_PAREN_AFTER_FOR,
+    EXPECT_SEMICOLON_AFTER_LOOP_CONDITION,
+    EXPECT_PAREN_AFTER_FOR_CLAUSES,
+    DID_YOU_MEAN_KEYWORD,
+    EXPECT_FUNCTION_NAME,
+    EXPECT_PAREN_AFTER_FUNCTION_NAME,
+    EXPECT_PARAMETER_NAME,
+    EXPECT_PAREN_AFTER_PARAMETERS,
+    EXPECT_BRACE_BEFORE_BODY,
+    TOO_MANY_PARAMETERS,
+    EXPECT_SEMICOLON_AFTER_RETURN,
+    EXPECT_CLASS_NAME,
+    EXPECT_BRACE_BEFORE_CLASS_BODY,
+    EXPECT_BRACE_AFTER_CLASS_BODY,
+    EXPECT_METHOD_NAME,
+    THIS_OUTSIDE_CLASS,
+    RETURN_FROM_INITIALIZER,
+    UNDEFINED_VARIABLE,
+    UNDEFINED_VARIABLE_SIMILAR,
+    UNASSIGNED_VARIABLE,
+    OUT_OF_FUEL,
+    UNKNOWN_MODULE,
+    UNDEFINED_KEY,
+    UNDEFINED_LIST_METHOD,
+    NO_PROPERTIES,
+    UNDEFINED_PROPERTY,
+    NOT_CALLABLE,
+    TOO_FEW_ARGUMENTS,
+    WRONG_ARGUMENT_COUNT,
+    OPERAND_NOT_NUMBER,
+    DIVISION_BY_ZERO,
+    INFINITE_RESULT,
+    NAN_RESULT,
+    DIVIDE_OPERANDS,
+    MULTIPLY_OPERANDS,
+    SUBTRACT_OPERANDS,
+    ADD_OPERANDS,
+    COMPARE_OPERANDS,
+    COMPARE_OPERANDS_OR_STRINGS,
+    EQUALITY_TYPES,
+    CANT_READ_INPUT,
+    CANT_WRITE_OUTPUT,
+    JLOX_OPERAND_NOT_NUMBER,
+    JLOX_OPERANDS_NOT_NUMBERS,
+    JLOX_ADD_OPERANDS,
+    JLOX_UNDEFINED_VARIABLE,
+    DECIMAL_OUT_OF_RANGE,
+    CANCELLED,
+    UNDEFINED_FOREIGN_METHOD,
+    CIRCULAR_IMPORT,
+    MODULE_ERROR,
+    UNDEFINED_VALUE_METHOD,
+    UNDEFINED_INSTANCE_PROPERTY,
+    STACK_OVERFLOW,
+    ONLY_INSTANCES_HAVE_FIELDS,
+    ALREADY_DECLARED,
+];
@@ -0,0 +1,97 @@
+//! Builtin natives every interpreter starts with.
+
+use crate::interpreter::{Interpreter, NativeFunction, RuntimeError};
+use crate::parser::{Foreign, Value};
+use std::rc::Rc;
+
+mod bytes;
+mod env;
+mod errors;
+mod format;
+mod help;
+mod io;
+mod list;
+mod map;
+mod math;
+mod process;
+mod random;
+#[cfg(feature = "regex")]
+mod regex;
+mod string;
+mod thread;
+mod time;
+mod types;
+mod weak;
+
+pub(crate) use help::help;
+pub(crate) use list::list_method;
+pub(crate) use random::Random;
+
+type Native = fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>;
+
+/// The method `name` of a foreign object made by a builtin native, like
+/// `ref.get()`, `None` if it has none.
+pub(crate) fn foreign_method(foreign: &Foreign, name: &str) -> Option<Value> {
+    weak::method(foreign, name).or_else(|| thread::method(foreign, name))
+}
+
+/// The method `name` of a number, string or boolean, like `"hi".upper()`,
+/// `None` if it has none.
+pub(crate) fn primitive_method(value: &Value, name: &str) -> Option<Value> {
+    let method = match value {
+        Value::Number(n) => math::number_method(*n, name),
+        Value::String(s) => string::string_method(s, name),
+        Value::Boolean(_) => None,
+        _ => return None,
+    };
+    method.or_else(|| types::method(value, name))
+}
+
+/// `function` as a method of `receiver`, a native taking one argument
+/// less that calls it with `receiver` first.
+fn bind_method(receiver: Value, name: &str, arity: usize, function: Native) -> Value {
+    let method = NativeFunction::new(name, arity - 1, move |interpreter, arguments| {
+        let mut bound = Vec::with_capacity(arguments.len() + 1);
+        bound.push(receiver.clone());
+        bound.extend_from_slice(arguments);
+        function(interpreter, &bound)
+    });
+    Value::NativeFunction(Rc::new(method))
+}
+
+pub(crate) fn define_builtins(interpreter: &mut Interpreter) {
+    bytes::define(interpreter);
+    env::define(interpreter);
+    errors::define(interpreter);
+    format::define(interpreter);
+    help::define(interpreter);
+    io::define(interpreter);
+    list::define(interpreter);
+    map::define(interpreter);
+    math::define(interpreter);
+    process::define(interpreter);
+    random::define(interpreter);
+    #[cfg(feature = "regex")]
+    regex::define(interpreter);
+    string::define(interpreter);
+    thread::define(interpreter);
+    time::define(interpreter);
+    types::define(interpreter);
+    weak::define(interpreter);
+}
+
+/// Reads an argument used as a position or count.
+fn index(value: &Value) -> Result<usize, RuntimeError> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Ok(*n as usize),
+        Value::Number(n) => Err(RuntimeError::new(format!(
+            "Expected a non-negative integer but got {n}."
+        ))),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(d) => index(&Value::Number(crate::decimal::to_f64(d))),
+        other => Err(RuntimeError::new(format!(
+            "Expected a non-negative integer but got {}.",
+            other.type_name()
+        ))),
+    }
+}
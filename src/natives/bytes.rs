@@ -0,0 +1,178 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Bytes, Value};
+
+/// Bytes natives. `len`, `get` and `slice` also take bytes, `get` reading a
+/// byte as a number.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    // bytes("hi") is the UTF-8 of a string, bytes("hi", "latin-1") the
+    // same as encode("hi", "latin-1"), bytes(list(104, 105)) takes the byte
+    // values from a list.
+    interpreter.define_variadic_native("bytes", 1, |_, arguments| match arguments {
+        [Value::String(text)] => Ok(Value::Bytes(Bytes::new(text.as_str().as_bytes()))),
+        [Value::String(text), encoding] => {
+            let encoding = Encoding::from_value(encoding)?;
+            Ok(Value::Bytes(Bytes::new(encoding.encode(&text.as_str())?)))
+        }
+        [Value::Bytes(bytes)] => Ok(Value::Bytes(bytes.clone())),
+        [Value::List(list)] => {
+            let data = list
+                .borrow()
+                .iter()
+                .map(byte)
+                .collect::<Result<Vec<u8>, RuntimeError>>()?;
+            Ok(Value::Bytes(Bytes::new(data)))
+        }
+        [other] => Err(RuntimeError::new(format!(
+            "Can't make bytes from {}.",
+            other.type_name()
+        ))),
+        [_, _] => Err(RuntimeError::new("bytes only takes an encoding with a string.")),
+        _ => Err(RuntimeError::new("bytes takes at most two arguments.")),
+    });
+    interpreter.define_native("encode", 2, |_, arguments| {
+        let text = String::from_value(&arguments[0])?;
+        let encoding = Encoding::from_value(&arguments[1])?;
+        Ok(Value::Bytes(Bytes::new(encoding.encode(&text)?)))
+    });
+    interpreter.define_native("decode", 2, |_, arguments| {
+        let bytes = Bytes::from_value(&arguments[0])?;
+        let encoding = Encoding::from_value(&arguments[1])?;
+        Ok(Value::String(encoding.decode(bytes.as_slice())?.into()))
+    });
+    interpreter.define_native("fromHex", 1, |_, arguments| {
+        let hex = String::from_value(&arguments[0])?;
+        let digits: Vec<u32> = hex
+            .chars()
+            .map(|c| c.to_digit(16))
+            .collect::<Option<_>>()
+            .filter(|digits: &Vec<u32>| digits.len().is_multiple_of(2))
+            .ok_or_else(|| RuntimeError::new(format!("{hex:?} isn't valid hex.")))?;
+        let data: Vec<u8> = digits
+            .chunks(2)
+            .map(|pair| (pair[0] * 16 + pair[1]) as u8)
+            .collect();
+        Ok(Value::Bytes(Bytes::new(data)))
+    });
+    interpreter.define_native("toHex", 1, |_, arguments| {
+        let bytes = Bytes::from_value(&arguments[0])?;
+        let hex = bytes
+            .as_slice()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        Ok(Value::String(hex.into()))
+    });
+}
+
+/// Text encodings `encode` and `decode` accept, by name.
+#[derive(Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Latin1,
+    Ascii,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Ascii => "ascii",
+        }
+    }
+
+    /// Largest character the encoding can hold in one byte, UTF-8 holding
+    /// every character.
+    fn max_char(self) -> Option<u32> {
+        match self {
+            Encoding::Utf8 => None,
+            Encoding::Latin1 => Some(0xff),
+            Encoding::Ascii => Some(0x7f),
+        }
+    }
+
+    fn encode(self, text: &str) -> Result<Vec<u8>, RuntimeError> {
+        let Some(max) = self.max_char() else {
+            return Ok(text.as_bytes().to_vec());
+        };
+        text.chars()
+            .map(|c| {
+                u8::try_from(c)
+                    .ok()
+                    .filter(|b| u32::from(*b) <= max)
+                    .ok_or_else(|| {
+                        RuntimeError::new(format!("Can't encode {c:?} as {}.", self.name()))
+                    })
+            })
+            .collect()
+    }
+
+    fn decode(self, data: &[u8]) -> Result<String, RuntimeError> {
+        let invalid = || RuntimeError::new(format!("Bytes aren't valid {}.", self.name()));
+        match self.max_char() {
+            None => String::from_utf8(data.to_vec()).map_err(|_| invalid()),
+            Some(max) if data.iter().any(|b| u32::from(*b) > max) => Err(invalid()),
+            Some(_) => Ok(data.iter().map(|b| char::from(*b)).collect()),
+        }
+    }
+}
+
+impl FromValue for Encoding {
+    fn from_value(value: &Value) -> Result<Self, RuntimeError> {
+        let name = String::from_value(value)?;
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "ascii" => Ok(Encoding::Ascii),
+            _ => Err(RuntimeError::new(format!(
+                "Unknown encoding {name:?}, expected utf-8, latin-1 or ascii."
+            ))),
+        }
+    }
+}
+
+fn byte(value: &Value) -> Result<u8, RuntimeError> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && (0.0..=255.0).contains(n) => Ok(*n as u8),
+        other => Err(RuntimeError::new(format!(
+            "Expected a byte from 0 to 255 but got {other:?}."
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn bytes_from_strings_and_lists() {
+        let source = r#"print toHex(bytes("hé")); print toHex(bytes("hé", "latin-1"));
+                        print toHex(bytes(list(104, 105))); print len(bytes("hé"));"#;
+        assert_eq!(run(source), "68c3a9\n68e9\n6869\n3\n");
+    }
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        let source = r#"var b = encode("café", "latin-1"); print decode(b, "latin-1");
+                        print decode(fromHex("6869"), "ascii");"#;
+        assert_eq!(run(source), "café\nhi\n");
+    }
+
+    #[test]
+    fn bytes_takes_an_encoding_only_with_a_string() {
+        assert_eq!(
+            run(r#"bytes(list(1), "ascii");"#),
+            "[line 1] runtime error: bytes only takes an encoding with a string.\n"
+        );
+        assert_eq!(
+            run(r#"bytes("é", "ascii");"#),
+            "[line 1] runtime error: Can't encode 'é' as ascii.\n"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use crate::bind::{FromValue, IntoValue};
+use crate::interpreter::{Capability, Interpreter, RuntimeError};
+use crate::parser::Value;
+use std::env;
+
+/// Environment variable natives. Reading is always allowed, changing the
+/// environment needs [`Capability::SetEnv`].
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("getEnv", 1, |_, arguments| {
+        let name = String::from_value(&arguments[0])?;
+        Ok(env::var(name).ok().into_value())
+    });
+    interpreter.define_native("setEnv", 2, |interpreter, arguments| {
+        interpreter.require(Capability::SetEnv, "setEnv")?;
+        let name = String::from_value(&arguments[0])?;
+        let value = String::from_value(&arguments[1])?;
+        // set_var panics on these instead of returning an error.
+        if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+            return Err(RuntimeError::new(format!(
+                "Invalid environment variable {name:?}."
+            )));
+        }
+        env::set_var(name, value);
+        Ok(Value::Nil)
+    });
+}
@@ -0,0 +1,20 @@
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::Value;
+
+/// Natives raising runtime errors from scripts. The errors point at the call,
+/// like any other runtime error.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("assert", 2, |_, arguments| {
+        if arguments[0].is_truthy() {
+            Ok(Value::Nil)
+        } else {
+            Err(RuntimeError::new(format!(
+                "Assertion failed: {}",
+                arguments[1]
+            )))
+        }
+    });
+    interpreter.define_native("error", 1, |_, arguments| {
+        Err(RuntimeError::new(&arguments[0]))
+    });
+}
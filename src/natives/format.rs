@@ -0,0 +1,179 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::Value;
+use std::num::IntErrorKind;
+
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_variadic_native("format", 1, |_, arguments| {
+        let template = String::from_value(&arguments[0])?;
+        Ok(Value::String(format(&template, &arguments[1..])?.into()))
+    });
+    interpreter.define_variadic_native("printf", 1, |interpreter, arguments| {
+        let template = String::from_value(&arguments[0])?;
+        interpreter.write_output(&format(&template, &arguments[1..])?)?;
+        Ok(Value::Nil)
+    });
+}
+
+/// Replaces each `{}` in `template` with the next argument, formatted like
+/// `print` does. A placeholder can also be `{:[align][width][.precision]}`,
+/// with align one of `<`, `>` or `^`, like `{:>8.2}`. Numbers are aligned
+/// right by default, everything else left, and neither width nor precision
+/// can be over `MAX_WIDTH`. `{{` and `}}` are literal braces.
+fn format(template: &str, arguments: &[Value]) -> Result<String, RuntimeError> {
+    let mut output = String::new();
+    let mut arguments = arguments.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => output.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => output.push('}'),
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(RuntimeError::new("Unclosed '{' in format string.")),
+                    }
+                }
+                let value = arguments.next().ok_or_else(|| {
+                    RuntimeError::new("Format string has more placeholders than arguments.")
+                })?;
+                output.push_str(&Spec::parse(&spec)?.apply(value)?);
+            }
+            '}' => return Err(RuntimeError::new("Unmatched '}' in format string.")),
+            c => output.push(c),
+        }
+    }
+    if arguments.next().is_some() {
+        return Err(RuntimeError::new(
+            "Format string has fewer placeholders than arguments.",
+        ));
+    }
+    Ok(output)
+}
+
+/// Widest a placeholder pads its value to, and most digits it shows after
+/// the point.
+const MAX_WIDTH: usize = 1024;
+
+#[derive(Default)]
+struct Spec {
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Result<Spec, RuntimeError> {
+        let invalid = || RuntimeError::new(format!("Invalid placeholder {{{spec}}}."));
+        if spec.is_empty() {
+            return Ok(Spec::default());
+        }
+        let mut rest = spec.strip_prefix(':').ok_or_else(invalid)?;
+        let align = rest.chars().next().filter(|c| matches!(c, '<' | '>' | '^'));
+        if align.is_some() {
+            rest = &rest[1..];
+        }
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+        let too_big = || {
+            RuntimeError::new(format!(
+                "Width and precision can't be over {MAX_WIDTH}, in placeholder {{{spec}}}."
+            ))
+        };
+        let number = |digits: &str| match digits.parse::<usize>() {
+            Ok(number) if number > MAX_WIDTH => Err(too_big()),
+            Ok(number) => Ok(number),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow => Err(too_big()),
+            Err(_) => Err(invalid()),
+        };
+        Ok(Spec {
+            align,
+            width: match width {
+                "" => 0,
+                width => number(width)?,
+            },
+            precision: precision.map(number).transpose()?,
+        })
+    }
+
+    fn apply(&self, value: &Value) -> Result<String, RuntimeError> {
+        let text = match (value, self.precision) {
+            (Value::Number(n), Some(precision)) => format!("{n:.precision$}"),
+            #[cfg(feature = "decimal")]
+            (Value::Decimal(d), Some(precision)) => format!("{d:.precision$}"),
+            (_, Some(_)) => {
+                return Err(RuntimeError::new(format!(
+                    "Precision only applies to numbers, not {}.",
+                    value.type_name()
+                )))
+            }
+            (value, None) => value.to_string(),
+        };
+        let default = if value.type_name() == "number" {
+            '>'
+        } else {
+            '<'
+        };
+        let width = self.width;
+        Ok(match self.align.unwrap_or(default) {
+            '>' => format!("{text:>width$}"),
+            '^' => format!("{text:^width$}"),
+            _ => format!("{text:<width$}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_one(template: &str, value: Value) -> Result<String, String> {
+        format(template, &[value]).map_err(|e| e.message)
+    }
+
+    #[test]
+    fn placeholders_take_arguments_in_order() {
+        let arguments = [Value::Number(1.0), Value::String("two".into())];
+        assert_eq!(format("{} and {}", &arguments).unwrap(), "1 and two");
+        assert_eq!(format("{{}}", &[]).unwrap(), "{}");
+    }
+
+    #[test]
+    fn numbers_align_right_by_default() {
+        assert_eq!(format_one("[{:5}]", Value::Number(42.0)).unwrap(), "[   42]");
+        assert_eq!(format_one("[{:5}]", Value::String("ab".into())).unwrap(), "[ab   ]");
+        assert_eq!(format_one("[{:<5}]", Value::Number(42.0)).unwrap(), "[42   ]");
+        assert_eq!(format_one("[{:^7.2}]", Value::Number(1.5)).unwrap(), "[ 1.50  ]");
+    }
+
+    #[test]
+    fn precision_only_applies_to_numbers() {
+        assert_eq!(
+            format_one("{:.2}", Value::Nil).unwrap_err(),
+            "Precision only applies to numbers, not nil."
+        );
+    }
+
+    #[test]
+    fn huge_widths_are_errors() {
+        let error = "Width and precision can't be over 1024, in placeholder {:>999999999}.";
+        assert_eq!(format_one("{:>999999999}", Value::Number(1.0)).unwrap_err(), error);
+        assert!(format_one("{:.99999999999999999999999}", Value::Number(1.0)).is_err());
+        assert_eq!(format_one("{:1024}", Value::Nil).unwrap().len(), 1024);
+    }
+
+    #[test]
+    fn malformed_templates() {
+        assert_eq!(format_one("{:x}", Value::Nil).unwrap_err(), "Invalid placeholder {:x}.");
+        assert_eq!(format_one("{", Value::Nil).unwrap_err(), "Unclosed '{' in format string.");
+        assert_eq!(
+            format("{}", &[]).unwrap_err().message,
+            "Format string has more placeholders than arguments."
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::interpreter::{Interpreter, NativeFunction, RuntimeError};
+use crate::parser::Value;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Documentation of the builtin natives, registered with
+/// [`Interpreter::document`]. The first line of each is the summary
+/// `help()` lists.
+const DOCS: &[(&str, &str)] = &[
+    (
+        "assert",
+        "Raises an error with the message unless the condition is truthy.",
+    ),
+    (
+        "bytes",
+        "Bytes from a string in UTF-8 or an encoding, from bytes or from a list of bytes.",
+    ),
+    (
+        "channel",
+        "A new channel between threads, as {\"send\": sender, \"recv\": receiver}.",
+    ),
+    ("chr", "The character with a Unicode code point."),
+    ("clock", "Seconds since the Unix epoch."),
+    (
+        "contains",
+        "Whether a list has an item equal to a value, or a string contains a substring.",
+    ),
+    ("decode", "The string in bytes, in utf-8, latin-1 or ascii."),
+    (
+        "deepEquals",
+        "Whether two values are equal, comparing lists and maps item by item.",
+    ),
+    (
+        "encode",
+        "The bytes of a string in utf-8, latin-1 or ascii.",
+    ),
+    ("endsWith", "Whether a string ends with a suffix."),
+    ("error", "Raises an error with a message."),
+    (
+        "exec",
+        "Runs a program with a list of arguments and returns its status, stdout and stderr.\n\
+         Needs the exec capability.",
+    ),
+    (
+        "filter",
+        "A new list of the items a function returns a truthy value for.",
+    ),
+    (
+        "format",
+        "A string with each {} of a template replaced by the next argument.",
+    ),
+    (
+        "formatTime",
+        "A timestamp formatted with a strftime-style format, in UTC.",
+    ),
+    ("fromHex", "The bytes a string of hex digits spells."),
+    (
+        "get",
+        "The item, byte or character at an index of a list, bytes or string, or a map value.",
+    ),
+    (
+        "getEnv",
+        "The value of an environment variable, nil if it isn't set.",
+    ),
+    (
+        "graphemes",
+        "The characters of a string as a reader sees them, like a letter with its accents.\n\
+         Needs the graphemes feature.",
+    ),
+    ("has", "Whether a map has a key."),
+    (
+        "help",
+        "Lists the natives, or describes the one, module or global with a name.\n\
+         help(\"math\") lists the members of a module.",
+    ),
+    (
+        "indexOf",
+        "Position of the first item equal to a value or of a substring, -1 if none.",
+    ),
+    (
+        "input",
+        "Prints a prompt and reads a line of input, nil at the end of the input.",
+    ),
+    ("insert", "Inserts an item into a list before an index."),
+    (
+        "join",
+        "The items of a list as strings, joined with a separator.",
+    ),
+    ("keys", "The keys of a map, as a list in insertion order."),
+    (
+        "len",
+        "Length of a list, map or bytes, or of a string in characters.",
+    ),
+    ("list", "A list of the arguments."),
+    ("localOffset", "Seconds local time is ahead of UTC."),
+    ("lower", "A string in lower case."),
+    (
+        "map",
+        "A new list of what a function returns for each item.",
+    ),
+    (
+        "mapOf",
+        "A map of keys and values given in pairs, like mapOf(\"a\", 1, \"b\", 2).",
+    ),
+    (
+        "merge",
+        "A new map with the entries of two, the second winning on clashes.",
+    ),
+    ("now", "Seconds since the Unix epoch."),
+    ("number", "A string as a number, nil if it isn't one."),
+    ("ord", "The Unicode code point of a single character."),
+    (
+        "parseTime",
+        "A time read with a strftime-style format as a timestamp, nil if it doesn't match.",
+    ),
+    ("pop", "Removes and returns the last item of a list."),
+    (
+        "printf",
+        "Prints a template like format does, without a line break.",
+    ),
+    ("push", "Appends an item to a list."),
+    ("random", "A random number from 0 up to, not including, 1."),
+    (
+        "randomInt",
+        "A random integer from low to high, both included.",
+    ),
+    (
+        "readLine",
+        "Reads a line of input, nil at the end of the input.",
+    ),
+    (
+        "reduce",
+        "Folds a list from the left with a function, starting from an initial value.",
+    ),
+    (
+        "reFindAll",
+        "Every match of a regular expression in a string.",
+    ),
+    (
+        "reMatch",
+        "The first match of a regular expression and its groups, nil without a match.",
+    ),
+    ("remove", "Removes a key from a map and returns its value."),
+    (
+        "removeAt",
+        "Removes and returns the item of a list at an index.",
+    ),
+    (
+        "replace",
+        "A string with every occurrence of one substring replaced by another.",
+    ),
+    (
+        "reReplace",
+        "A string with every match of a regular expression replaced.",
+    ),
+    (
+        "recv",
+        "Waits for the next value sent on a channel, nil once every sender is gone.",
+    ),
+    (
+        "reverse",
+        "A new list or string with the items or characters in reverse order.",
+    ),
+    ("seedRandom", "Seeds random and randomInt, so they repeat."),
+    ("send", "Sends a copy of a value on a channel."),
+    (
+        "set",
+        "Sets the item of a list at an index, or the value of a map key.",
+    ),
+    (
+        "setEnv",
+        "Sets an environment variable.\n\
+         Needs the set-env capability.",
+    ),
+    (
+        "slice",
+        "The items of a list, bytes or string from start up to, not including, end.",
+    ),
+    (
+        "sort",
+        "Sorts a list of numbers or strings in place, and returns it.",
+    ),
+    (
+        "sortBy",
+        "Sorts a list in place with a comparator, and returns it.",
+    ),
+    (
+        "spawn",
+        "Calls a function on a new thread with copies of the arguments.\n\
+         The handle's join() method waits for the result.",
+    ),
+    (
+        "split",
+        "The parts of a string between separators, its characters for \"\".",
+    ),
+    ("startsWith", "Whether a string starts with a prefix."),
+    ("str", "A value as a string, the way print shows it."),
+    (
+        "substring",
+        "The characters of a string from start up to, not including, end.",
+    ),
+    ("toHex", "Bytes as a string of hex digits."),
+    ("trim", "A string without whitespace at either end."),
+    ("type", "The name of the type of a value."),
+    ("upper", "A string in upper case."),
+    (
+        "values",
+        "The values of a map, as a list in insertion order.",
+    ),
+    (
+        "weakRef",
+        "A reference to a list, map or foreign object that doesn't keep it alive.",
+    ),
+    ("math.abs", "The absolute value of a number."),
+    ("math.ceil", "The smallest integer not below a number."),
+    ("math.cos", "The cosine of an angle in radians."),
+    ("math.floor", "The largest integer not above a number."),
+    ("math.log", "The natural logarithm of a number."),
+    ("math.max", "The larger of two numbers."),
+    ("math.min", "The smaller of two numbers."),
+    ("math.pow", "A number raised to a power."),
+    (
+        "math.round",
+        "A number rounded to the nearest integer, halves away from zero.",
+    ),
+    ("math.sin", "The sine of an angle in radians."),
+    ("math.sqrt", "The square root of a number."),
+    ("math.tan", "The tangent of an angle in radians."),
+];
+
+/// `help()` prints every native with its arity and summary, `help(name)`
+/// or `help(native)` the documentation of one. A module name lists its
+/// members.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_variadic_native("help", 0, |interpreter, arguments| {
+        let text = match arguments {
+            [] => interpreter.help(None)?,
+            [Value::String(name)] => interpreter.help(Some(&*name.as_str()))?,
+            [Value::NativeFunction(function)] => describe(interpreter, function),
+            [other] => {
+                return Err(RuntimeError::new(format!(
+                    "help takes a name or a native, not {}.",
+                    other.type_name()
+                )))
+            }
+            _ => return Err(RuntimeError::new("help takes at most one argument.")),
+        };
+        interpreter.write_output(&text)?;
+        Ok(Value::Nil)
+    });
+    for (name, doc) in DOCS {
+        interpreter.document(*name, *doc);
+    }
+}
+
+/// What `help` prints about `name`, or about every native without one.
+pub(crate) fn help(interpreter: &Interpreter, name: Option<&str>) -> Result<String, RuntimeError> {
+    let Some(name) = name else {
+        return Ok(overview(interpreter));
+    };
+    if let Some(value) = interpreter.global(name) {
+        return Ok(match value {
+            Value::NativeFunction(function) => describe(interpreter, &function),
+            other => format!("{name} is a {}.\n", other.type_name()),
+        });
+    }
+    if let Some(module) = interpreter.module(name) {
+        let mut text = format!("Module {name}, used after `import {name};`:\n");
+        let mut members = module.members();
+        members.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let entries = members.into_iter().map(|(member, value)| match value {
+            Value::NativeFunction(function) => (
+                signature(&function),
+                summary(interpreter, &function).to_string(),
+            ),
+            other => (member, format!("{other:?}")),
+        });
+        write_table(&mut text, entries);
+        return Ok(text);
+    }
+    let member = name
+        .split_once('.')
+        .and_then(|(module, member)| interpreter.module(module)?.get(member));
+    match member {
+        Some(Value::NativeFunction(function)) => Ok(describe(interpreter, &function)),
+        Some(other) => Ok(format!("{name} is a {}.\n", other.type_name())),
+        None => Err(RuntimeError::new(format!("Nothing is called '{name}'."))),
+    }
+}
+
+/// Every global native with its arity and summary, then the modules.
+fn overview(interpreter: &Interpreter) -> String {
+    let mut natives: Vec<Rc<NativeFunction>> = interpreter
+        .global_names()
+        .filter_map(|name| match interpreter.global(&name) {
+            Some(Value::NativeFunction(function)) => Some(function),
+            _ => None,
+        })
+        .collect();
+    natives.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut text = String::from("Natives:\n");
+    write_table(
+        &mut text,
+        natives.iter().map(|function| {
+            (
+                signature(function),
+                summary(interpreter, function).to_string(),
+            )
+        }),
+    );
+    let mut modules: Vec<&str> = interpreter.module_names().collect();
+    if !modules.is_empty() {
+        modules.sort_unstable();
+        let _ = writeln!(
+            text,
+            "Modules: {}. help(\"name\") lists the members of one.",
+            modules.join(", ")
+        );
+    }
+    text
+}
+
+/// The arity and documentation of a native.
+fn describe(interpreter: &Interpreter, function: &NativeFunction) -> String {
+    let doc = interpreter
+        .doc(&function.name)
+        .unwrap_or("No documentation.");
+    format!("{}\n{doc}\n", signature(function))
+}
+
+/// `name/arity`, with a `+` if it takes more arguments.
+fn signature(function: &NativeFunction) -> String {
+    let more = if function.variadic { "+" } else { "" };
+    format!("{}/{}{more}", function.name, function.arity)
+}
+
+fn summary<'a>(interpreter: &'a Interpreter, function: &NativeFunction) -> &'a str {
+    interpreter
+        .doc(&function.name)
+        .and_then(|doc| doc.lines().next())
+        .unwrap_or("")
+}
+
+/// Writes `entries` as two aligned columns.
+fn write_table(text: &mut String, entries: impl Iterator<Item = (String, String)>) {
+    let entries: Vec<_> = entries.collect();
+    let width = entries
+        .iter()
+        .map(|(left, _)| left.len())
+        .max()
+        .unwrap_or(0);
+    for (left, right) in entries {
+        let line = format!("  {left:<width$}  {right}");
+        let _ = writeln!(text, "{}", line.trim_end());
+    }
+}
@@ -0,0 +1,15 @@
+use crate::bind::{FromValue, IntoValue};
+use crate::interpreter::Interpreter;
+
+/// Input natives, reading from the interpreter's input (stdin by default).
+/// Both return `nil` at the end of the input.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("readLine", 0, |interpreter, _| {
+        Ok(interpreter.read_line()?.into_value())
+    });
+    interpreter.define_native("input", 1, |interpreter, arguments| {
+        let prompt = String::from_value(&arguments[0])?;
+        interpreter.write_output(&prompt)?;
+        Ok(interpreter.read_line()?.into_value())
+    });
+}
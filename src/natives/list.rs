@@ -0,0 +1,384 @@
+use super::{bind_method, Native};
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Bytes, List, Value};
+use std::cmp::Ordering;
+
+/// Natives taking a list first. Each is a global, `push(list, 1)`, and a
+/// method of lists, `list.push(1)`.
+const METHODS: [(&str, usize, Native); 16] = [
+    ("push", 2, push),
+    ("pop", 1, pop),
+    ("insert", 3, insert),
+    ("removeAt", 2, remove_at),
+    ("get", 2, get),
+    ("set", 3, set),
+    ("len", 1, len),
+    ("slice", 3, slice),
+    ("indexOf", 2, index_of),
+    ("map", 2, map),
+    ("filter", 2, filter),
+    ("reduce", 3, reduce),
+    ("contains", 2, contains),
+    ("sort", 1, sort),
+    ("sortBy", 2, sort_by),
+    ("reverse", 1, reverse),
+];
+
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_variadic_native("list", 0, |_, arguments| {
+        Ok(Value::List(List::new(arguments.to_vec())))
+    });
+    for (name, arity, function) in METHODS {
+        interpreter.define_native(name, arity, function);
+    }
+}
+
+/// Natives of `METHODS` that also take a string first, so they are
+/// methods of strings as well.
+const SEQUENCE_METHODS: [&str; 6] = ["len", "get", "slice", "indexOf", "contains", "reverse"];
+
+/// `list.name` as a function with the list bound as its first argument.
+pub(crate) fn list_method(list: &List, name: &str) -> Option<Value> {
+    let (name, arity, function) = METHODS.into_iter().find(|method| method.0 == name)?;
+    Some(bind_method(
+        Value::List(list.clone()),
+        name,
+        arity,
+        function,
+    ))
+}
+
+/// `receiver.name` for the natives taking lists and strings alike, like
+/// `len`.
+pub(super) fn sequence_method(receiver: Value, name: &str) -> Option<Value> {
+    if !SEQUENCE_METHODS.contains(&name) {
+        return None;
+    }
+    let (name, arity, function) = METHODS.into_iter().find(|method| method.0 == name)?;
+    Some(bind_method(receiver, name, arity, function))
+}
+
+fn push(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    List::from_value(&arguments[0])?
+        .borrow_mut()
+        .push(arguments[1].clone());
+    Ok(Value::Nil)
+}
+
+fn pop(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    List::from_value(&arguments[0])?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| RuntimeError::new("Can't pop from an empty list."))
+}
+
+fn insert(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = List::from_value(&arguments[0])?;
+    let mut items = list.borrow_mut();
+    let index = position(&arguments[1], items.len() + 1)?;
+    items.insert(index, arguments[2].clone());
+    Ok(Value::Nil)
+}
+
+fn remove_at(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = List::from_value(&arguments[0])?;
+    let mut items = list.borrow_mut();
+    let index = position(&arguments[1], items.len())?;
+    Ok(items.remove(index))
+}
+
+/// Item at an index, byte at an index as a number, character at an index
+/// as a string, or the value of a map key.
+fn get(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::Map(map) => return super::map::get(map, &arguments[1]),
+        Value::Bytes(bytes) => {
+            let index = position(&arguments[1], bytes.as_slice().len())?;
+            return Ok(Value::Number(f64::from(bytes.as_slice()[index])));
+        }
+        Value::String(s) => {
+            let s = s.as_str();
+            let index = position(&arguments[1], s.chars().count())?;
+            let c = s.chars().nth(index).expect("index is in bounds");
+            return Ok(Value::String(c.to_string().into()));
+        }
+        _ => {}
+    }
+    let list = List::from_value(&arguments[0])?;
+    let items = list.borrow();
+    let index = position(&arguments[1], items.len())?;
+    Ok(items[index].clone())
+}
+
+fn set(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    if let Value::Map(map) = &arguments[0] {
+        return super::map::set(map, &arguments[1], arguments[2].clone());
+    }
+    let list = List::from_value(&arguments[0])?;
+    let mut items = list.borrow_mut();
+    let index = position(&arguments[1], items.len())?;
+    items[index] = arguments[2].clone();
+    Ok(arguments[2].clone())
+}
+
+/// Length of a list, map or bytes, or of a string in characters.
+fn len(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let length = match &arguments[0] {
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        Value::Bytes(bytes) => bytes.as_slice().len(),
+        Value::String(s) => s.as_str().chars().count(),
+        other => return Err(not_a_sequence(other)),
+    };
+    Ok(Value::Number(length as f64))
+}
+
+/// Items `start` up to, not including, `end` as a new list, those bytes as
+/// new bytes or those characters as a new string.
+fn slice(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let start = super::index(&arguments[1])?;
+    let end = super::index(&arguments[2])?;
+    let bounds = |length: usize| {
+        if start > end || end > length {
+            return Err(RuntimeError::new(format!(
+                "Slice {start}..{end} out of bounds for length {length}."
+            )));
+        }
+        Ok(start..end)
+    };
+    match &arguments[0] {
+        Value::Bytes(bytes) => {
+            let range = bounds(bytes.as_slice().len())?;
+            return Ok(Value::Bytes(Bytes::new(&bytes.as_slice()[range])));
+        }
+        Value::String(s) => {
+            let s = s.as_str();
+            let range = bounds(s.chars().count())?;
+            let sliced: String = s.chars().skip(range.start).take(range.len()).collect();
+            return Ok(Value::String(sliced.into()));
+        }
+        _ => {}
+    }
+    let list = List::from_value(&arguments[0])?;
+    let items = list.borrow();
+    let range = bounds(items.len())?;
+    Ok(Value::List(List::new(items[range].to_vec())))
+}
+
+/// Position of the first item equal to the needle, or of a substring in
+/// characters, -1 if there is none.
+fn index_of(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let index = match &arguments[0] {
+        Value::List(list) => list.borrow().iter().position(|item| *item == arguments[1]),
+        Value::String(s) => {
+            let needle = String::from_value(&arguments[1])?;
+            let s = s.as_str();
+            s.find(&needle).map(|byte| s[..byte].chars().count())
+        }
+        other => return Err(not_a_sequence(other)),
+    };
+    Ok(Value::Number(index.map_or(-1.0, |index| index as f64)))
+}
+
+fn map(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let items = List::from_value(&arguments[0])?.borrow().clone();
+    let mapped = items
+        .into_iter()
+        .map(|item| interpreter.call(&arguments[1], &[item]))
+        .collect::<Result<_, _>>()?;
+    Ok(Value::List(List::new(mapped)))
+}
+
+fn filter(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let items = List::from_value(&arguments[0])?.borrow().clone();
+    let mut kept = Vec::new();
+    for item in items {
+        if interpreter
+            .call(&arguments[1], std::slice::from_ref(&item))?
+            .is_truthy()
+        {
+            kept.push(item);
+        }
+    }
+    Ok(Value::List(List::new(kept)))
+}
+
+/// Folds the list from the left: `reduce(list, f, initial)`.
+fn reduce(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let items = List::from_value(&arguments[0])?.borrow().clone();
+    items
+        .into_iter()
+        .try_fold(arguments[2].clone(), |total, item| {
+            interpreter.call(&arguments[1], &[total, item])
+        })
+}
+
+/// Whether a list has an item equal to the needle, or a string contains a
+/// substring.
+fn contains(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let found = match &arguments[0] {
+        Value::List(list) => list.borrow().contains(&arguments[1]),
+        Value::String(s) => s.as_str().contains(&String::from_value(&arguments[1])?),
+        other => return Err(not_a_sequence(other)),
+    };
+    Ok(Value::Boolean(found))
+}
+
+/// Sorts numbers or strings in place, and returns the list.
+fn sort(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = List::from_value(&arguments[0])?;
+    let items = list.borrow().clone();
+    let sorted = merge_sort(items, &mut |a, b| match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(a.total_cmp(b)),
+        (Value::String(a), Value::String(b)) => Ok(a.as_str().cmp(&b.as_str())),
+        (a, b) => Err(RuntimeError::new(format!(
+            "Can't sort {} with {}, only numbers or strings.",
+            a.type_name(),
+            b.type_name()
+        ))),
+    })?;
+    *list.borrow_mut() = sorted;
+    Ok(arguments[0].clone())
+}
+
+/// Sorts in place with a comparator returning a negative number, zero or a
+/// positive number, and returns the list.
+fn sort_by(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = List::from_value(&arguments[0])?;
+    let items = list.borrow().clone();
+    let sorted = merge_sort(items, &mut |a, b| match interpreter
+        .call(&arguments[1], &[a.clone(), b.clone()])?
+    {
+        Value::Number(n) => Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+        other => Err(RuntimeError::new(format!(
+            "Comparator must return a number but returned {}.",
+            other.type_name()
+        ))),
+    })?;
+    *list.borrow_mut() = sorted;
+    Ok(arguments[0].clone())
+}
+
+/// A new list with the items in reverse order, or a new string with the
+/// characters in reverse order. Characters joined into one by combining
+/// marks come apart, see `graphemes`.
+fn reverse(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::String(s) => Ok(Value::String(
+            s.as_str().chars().rev().collect::<String>().into(),
+        )),
+        other => {
+            let items = List::from_value(other)?
+                .borrow()
+                .iter()
+                .rev()
+                .cloned()
+                .collect();
+            Ok(Value::List(List::new(items)))
+        }
+    }
+}
+
+/// A stable merge sort stopping at the first comparison error. The standard
+/// sorts can panic on inconsistent comparators, which scripts may pass.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut dyn FnMut(&Value, &Value) -> Result<Ordering, RuntimeError>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if compare(a, b)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Reads an index that must be below `length`.
+fn position(value: &Value, length: usize) -> Result<usize, RuntimeError> {
+    let index = super::index(value)?;
+    if index >= length {
+        return Err(RuntimeError::new(format!(
+            "Index {index} out of bounds for length {length}."
+        )));
+    }
+    Ok(index)
+}
+
+fn not_a_sequence(value: &Value) -> RuntimeError {
+    RuntimeError::new(format!(
+        "Expected string or list but got {}.",
+        value.type_name()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn methods_change_the_list_in_place() {
+        let source = "var l = list(3, 1, 2); l.push(4); l.sort(); print l; print l.pop(); print l;";
+        assert_eq!(run(source), "[1, 2, 3, 4]\n4\n[1, 2, 3]\n");
+    }
+
+    #[test]
+    fn methods_and_globals_are_the_same_natives() {
+        let source = "var l = list(1, 2, 3); print l.len(); print len(l); print l.slice(1, 3);";
+        assert_eq!(run(source), "3\n3\n[2, 3]\n");
+    }
+
+    #[test]
+    fn methods_call_lox_functions() {
+        let source = "fun double(x) { return x * 2; } print list(1, 2).map(double);";
+        assert_eq!(run(source), "[2, 4]\n");
+    }
+
+    #[test]
+    fn unknown_method() {
+        assert_eq!(
+            run("print list().nope;"),
+            "[line 1] runtime error: Lists have no method 'nope'.\n"
+        );
+    }
+
+    #[test]
+    fn deeply_nested_lists_drop() {
+        let source = "var l = list();
+                      for (var i = 0; i < 200000; i = i + 1) l = list(l);
+                      l = nil; print 1;";
+        assert_eq!(run(source), "1\n");
+    }
+
+    #[test]
+    fn deeply_nested_lists_print() {
+        let source = "var l = list();
+                      for (var i = 0; i < 200000; i = i + 1) l = list(l);
+                      print len(str(l)); var m = mapOf(1, l); print len(str(m));";
+        assert_eq!(run(source), "400002\n400007\n");
+    }
+
+    #[test]
+    fn lists_holding_themselves_print() {
+        let source = "var l = list(1); l.push(l); print l; print mapOf(1, l);";
+        assert_eq!(run(source), "[1, [...]]\n{1: [1, [...]]}\n");
+    }
+}
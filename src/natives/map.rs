@@ -0,0 +1,116 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Key, List, Map, Value};
+
+/// Map natives. Maps keep insertion order: `keys`, `values` and printing
+/// list entries in the order they were first added, and `merge` keeps the
+/// positions of keys already in the first map. Keys are nil, booleans,
+/// numbers or strings, see [`Key`]. String entries are read with `map.key`,
+/// so these are globals rather than methods, which could clash with keys.
+/// `len`, `get` and `set` also take maps.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    // mapOf("a", 1, "b", 2) makes {"a": 1, "b": 2}.
+    interpreter.define_variadic_native("mapOf", 0, |_, arguments| {
+        if arguments.len() % 2 != 0 {
+            return Err(RuntimeError::new("mapOf takes keys and values in pairs."));
+        }
+        let entries = arguments
+            .chunks(2)
+            .map(|pair| Ok((Key::from_value(&pair[0])?, pair[1].clone())))
+            .collect::<Result<_, RuntimeError>>()?;
+        Ok(Value::Map(Map::new(entries)))
+    });
+    interpreter.define_native("keys", 1, |_, arguments| {
+        let map = Map::from_value(&arguments[0])?;
+        let keys = map.borrow().keys().cloned().map(Value::from).collect();
+        Ok(Value::List(List::new(keys)))
+    });
+    interpreter.define_native("values", 1, |_, arguments| {
+        let map = Map::from_value(&arguments[0])?;
+        let values = map.borrow().values().cloned().collect();
+        Ok(Value::List(List::new(values)))
+    });
+    interpreter.define_native("has", 2, |_, arguments| {
+        let map = Map::from_value(&arguments[0])?;
+        let key = Key::from_value(&arguments[1])?;
+        let has = map.borrow().contains_key(&key);
+        Ok(Value::Boolean(has))
+    });
+    // Returns the removed value, or nil if the key wasn't there.
+    interpreter.define_native("remove", 2, |_, arguments| {
+        let map = Map::from_value(&arguments[0])?;
+        let key = Key::from_value(&arguments[1])?;
+        let removed = map.borrow_mut().shift_remove(&key);
+        Ok(removed.unwrap_or(Value::Nil))
+    });
+    // A new map with the entries of both, the second winning on clashes.
+    interpreter.define_native("merge", 2, |_, arguments| {
+        let mut merged = Map::from_value(&arguments[0])?.borrow().clone();
+        let other = Map::from_value(&arguments[1])?;
+        merged.extend(other.borrow().iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(Value::Map(Map::new(merged)))
+    });
+}
+
+/// `get(map, key)`, nil when the key is missing.
+pub(super) fn get(map: &Map, key: &Value) -> Result<Value, RuntimeError> {
+    let key = Key::from_value(key)?;
+    Ok(map.borrow().get(&key).cloned().unwrap_or(Value::Nil))
+}
+
+/// `set(map, key, value)`, adding the key at the end if it is new.
+pub(super) fn set(map: &Map, key: &Value, value: Value) -> Result<Value, RuntimeError> {
+    let key = Key::from_value(key)?;
+    map.borrow_mut().insert(key, value.clone());
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn keys_keep_insertion_order() {
+        let source = r#"var m = mapOf("b", 1, "a", 2); set(m, "c", 3); print keys(m); print m;"#;
+        assert_eq!(run(source), "[\"b\", \"a\", \"c\"]\n{\"b\": 1, \"a\": 2, \"c\": 3}\n");
+    }
+
+    #[test]
+    fn entries_are_properties() {
+        let source = r#"var m = mapOf("a", 1); print m.a; print get(m, "z");"#;
+        assert_eq!(run(source), "1\nnil\n");
+    }
+
+    #[test]
+    fn merge_keeps_positions_and_the_second_wins() {
+        let source = r#"print merge(mapOf("a", 1, "b", 2), mapOf("a", 10, "c", 3));"#;
+        assert_eq!(run(source), "{\"a\": 10, \"b\": 2, \"c\": 3}\n");
+    }
+
+    #[test]
+    fn remove_returns_the_value() {
+        let source = r#"var m = mapOf("a", 1); print remove(m, "a"); print remove(m, "a"); print m;"#;
+        assert_eq!(run(source), "1\nnil\n{}\n");
+    }
+
+    #[test]
+    fn map_of_needs_pairs() {
+        assert_eq!(
+            run(r#"mapOf("a");"#),
+            "[line 1] runtime error: mapOf takes keys and values in pairs.\n"
+        );
+    }
+
+    #[test]
+    fn deeply_nested_maps_drop() {
+        let source = r#"var m = mapOf();
+                        for (var i = 0; i < 200000; i = i + 1) m = mapOf("m", m);
+                        m = nil; print 1;"#;
+        assert_eq!(run(source), "1\n");
+    }
+}
@@ -0,0 +1,68 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, NativeFunction, NativeModule};
+use crate::parser::Value;
+use std::rc::Rc;
+
+type Unary = fn(f64) -> f64;
+type Binary = fn(f64, f64) -> f64;
+
+/// Functions of one number, members of `math` and methods of numbers, like
+/// `math.floor(x)` and `x.floor()`.
+const UNARY: [(&str, Unary); 9] = [
+    ("sqrt", f64::sqrt),
+    ("abs", f64::abs),
+    ("floor", f64::floor),
+    ("ceil", f64::ceil),
+    ("round", f64::round),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+    ("log", f64::ln),
+];
+
+/// Functions of two numbers, members of `math` and methods of numbers, like
+/// `math.pow(x, 2)` and `x.pow(2)`.
+const BINARY: [(&str, Binary); 3] = [("min", f64::min), ("max", f64::max), ("pow", f64::powf)];
+
+/// The `math` module, used as `import math; print math.sqrt(2);`.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    let mut math = NativeModule::new("math");
+    math.define("PI", Value::Number(std::f64::consts::PI));
+    math.define("E", Value::Number(std::f64::consts::E));
+    for (name, function) in UNARY {
+        unary(&mut math, name, function);
+    }
+    for (name, function) in BINARY {
+        binary(&mut math, name, function);
+    }
+    interpreter.register_module(math);
+}
+
+/// `n.name` as a function of the arguments after the number.
+pub(super) fn number_method(n: f64, name: &str) -> Option<Value> {
+    let method = if let Some((name, function)) = UNARY.into_iter().find(|f| f.0 == name) {
+        NativeFunction::new(name, 0, move |_, _| Ok(Value::Number(function(n))))
+    } else {
+        let (name, function) = BINARY.into_iter().find(|f| f.0 == name)?;
+        NativeFunction::new(name, 1, move |_, arguments| {
+            Ok(Value::Number(function(n, f64::from_value(&arguments[0])?)))
+        })
+    };
+    Some(Value::NativeFunction(Rc::new(method)))
+}
+
+fn unary(module: &mut NativeModule, name: &str, function: Unary) {
+    module.define_native(name, 1, move |_, arguments| {
+        Ok(Value::Number(function(f64::from_value(&arguments[0])?)))
+    });
+}
+
+fn binary(module: &mut NativeModule, name: &str, function: Binary) {
+    module.define_native(name, 2, move |_, arguments| {
+        let (a, b) = (
+            f64::from_value(&arguments[0])?,
+            f64::from_value(&arguments[1])?,
+        );
+        Ok(Value::Number(function(a, b)))
+    });
+}
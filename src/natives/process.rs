@@ -0,0 +1,30 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Capability, Interpreter, RuntimeError};
+use crate::parser::{Map, Value};
+use std::process::Command;
+
+/// `exec(command, arguments)` runs a program to completion, without a
+/// shell, and returns `{"status", "stdout", "stderr"}`. The status is nil
+/// when the program was killed by a signal. Needs [`Capability::Exec`].
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("exec", 2, |interpreter, arguments| {
+        interpreter.require(Capability::Exec, "exec")?;
+        let command = String::from_value(&arguments[0])?;
+        let arguments = Vec::<String>::from_value(&arguments[1])?;
+        let output = Command::new(&command)
+            .args(&arguments)
+            .output()
+            .map_err(|e| RuntimeError::new(format!("Cant run {command}: {e}")))?;
+        let status = output
+            .status
+            .code()
+            .map_or(Value::Nil, |code| Value::Number(f64::from(code)));
+        let text =
+            |bytes: Vec<u8>| Value::String(String::from_utf8_lossy(&bytes).into_owned().into());
+        Ok(Value::Map(Map::from_iter([
+            ("status", status),
+            ("stdout", text(output.stdout)),
+            ("stderr", text(output.stderr)),
+        ])))
+    });
+}
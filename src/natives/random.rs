@@ -0,0 +1,73 @@
+use crate::bind::FromValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64, small and good enough for scripts. Not for cryptography.
+#[derive(Debug, Clone)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Seeded from the time, so runs differ unless a seed is set.
+    pub(crate) fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Random::new(nanos)
+    }
+
+    pub(crate) fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[0, bound)`.
+    fn below(&mut self, bound: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(bound)) >> 64) as u64
+    }
+}
+
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("random", 0, |interpreter, _| {
+        Ok(Value::Number(interpreter.random_mut().next_f64()))
+    });
+    interpreter.define_native("randomInt", 2, |interpreter, arguments| {
+        let low = integer(&arguments[0])?;
+        let high = integer(&arguments[1])?;
+        if low > high {
+            return Err(RuntimeError::new(format!(
+                "randomInt range {low}..{high} is empty."
+            )));
+        }
+        let offset = interpreter.random_mut().below((high - low) as u64 + 1);
+        Ok(Value::Number((low + offset as i64) as f64))
+    });
+    interpreter.define_native("seedRandom", 1, |interpreter, arguments| {
+        let seed = f64::from_value(&arguments[0])?;
+        interpreter.seed_random(seed.to_bits());
+        Ok(Value::Nil)
+    });
+}
+
+fn integer(value: &Value) -> Result<i64, RuntimeError> {
+    match f64::from_value(value)? {
+        n if n.fract() == 0.0 => Ok(n as i64),
+        n => Err(RuntimeError::new(format!(
+            "Expected an integer but got {n}."
+        ))),
+    }
+}
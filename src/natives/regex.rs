@@ -0,0 +1,79 @@
+use crate::bind::{FromValue, IntoValue};
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::Value;
+use regex::Regex;
+
+/// Regular expression natives, using the syntax of the `regex` crate.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    // The first match as a list of the whole match and each group, with nil
+    // for groups that didn't take part, or nil without a match.
+    interpreter.define_native("reMatch", 2, |_, arguments| {
+        let regex = compile(&arguments[0])?;
+        let text = String::from_value(&arguments[1])?;
+        let captures = regex.captures(&text).map(|captures| {
+            captures
+                .iter()
+                .map(|group| group.map(|group| group.as_str().to_string()))
+                .collect::<Vec<_>>()
+        });
+        Ok(captures.into_value())
+    });
+    interpreter.define_native("reFindAll", 2, |_, arguments| {
+        let regex = compile(&arguments[0])?;
+        let text = String::from_value(&arguments[1])?;
+        let matches: Vec<String> = regex
+            .find_iter(&text)
+            .map(|found| found.as_str().to_string())
+            .collect();
+        Ok(matches.into_value())
+    });
+    // Replaces every match; `$1` or `${name}` in the replacement insert groups.
+    interpreter.define_native("reReplace", 3, |_, arguments| {
+        let regex = compile(&arguments[0])?;
+        let text = String::from_value(&arguments[1])?;
+        let replacement = String::from_value(&arguments[2])?;
+        Ok(Value::String(
+            regex
+                .replace_all(&text, replacement.as_str())
+                .into_owned()
+                .into(),
+        ))
+    });
+}
+
+fn compile(pattern: &Value) -> Result<Regex, RuntimeError> {
+    let pattern = String::from_value(pattern)?;
+    Regex::new(&pattern).map_err(|e| RuntimeError::new(format!("Invalid pattern: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn matches_and_groups() {
+        let source = r#"print reMatch("(\w+)@(\w+)?", "me@ x"); print reMatch("\d", "none");
+                        print reFindAll("\d+", "a1 b22 c333");"#;
+        assert_eq!(
+            run(source),
+            "[\"me@\", \"me\", nil]\nnil\n[\"1\", \"22\", \"333\"]\n"
+        );
+    }
+
+    #[test]
+    fn replace_inserts_groups() {
+        let source = r#"print reReplace("(\w+)=(\w+)", "a=1, b=2", "$2=$1");"#;
+        assert_eq!(run(source), "1=a, 2=b\n");
+    }
+
+    #[test]
+    fn invalid_patterns_are_runtime_errors() {
+        let output = run(r#"reMatch("(", "");"#);
+        assert!(output.starts_with("[line 1] runtime error: Invalid pattern: "), "{output}");
+    }
+}
@@ -0,0 +1,146 @@
+use super::{bind_method, Native};
+use crate::bind::{FromValue, IntoValue};
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{List, LoxString, Value};
+
+/// Natives taking a string first. Each is a global, `upper(s)`, and a
+/// method of strings, `s.upper()`.
+const METHODS: [(&str, usize, Native); 9] = [
+    ("substring", 3, substring),
+    ("upper", 1, upper),
+    ("lower", 1, lower),
+    ("split", 2, split),
+    ("trim", 1, trim),
+    ("startsWith", 2, starts_with),
+    ("endsWith", 2, ends_with),
+    ("replace", 3, replace),
+    ("ord", 1, ord),
+];
+
+/// String natives. A character is a Unicode scalar value: lengths, indices
+/// and reversal count characters, never bytes, so text in any script is
+/// sliced between whole characters. A character someone reading sees can
+/// still be several, like a letter and a combining accent or an emoji with
+/// a skin tone; `graphemes`, with the `graphemes` feature, splits text into
+/// those. `len`, `get`, `slice`, `reverse`, `indexOf` and `contains` also
+/// take lists, so they live with the list natives.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    for (name, arity, function) in METHODS {
+        interpreter.define_native(name, arity, function);
+    }
+    // Items are converted the way print shows them.
+    interpreter.define_native("join", 2, |_, arguments| {
+        let list = List::from_value(&arguments[0])?;
+        let separator = String::from_value(&arguments[1])?;
+        let parts: Vec<String> = list.borrow().iter().map(Value::to_string).collect();
+        Ok(Value::String(parts.join(&separator).into()))
+    });
+    // The extended grapheme clusters of Unicode Standard Annex #29.
+    #[cfg(feature = "graphemes")]
+    interpreter.define_native("graphemes", 1, |_, arguments| {
+        use unicode_segmentation::UnicodeSegmentation;
+        let s = String::from_value(&arguments[0])?;
+        let graphemes: Vec<String> = s.graphemes(true).map(String::from).collect();
+        Ok(graphemes.into_value())
+    });
+    interpreter.define_native("chr", 1, |_, arguments| {
+        let code = f64::from_value(&arguments[0])?;
+        let c = Some(code)
+            .filter(|code| code.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(code))
+            .and_then(|code| char::from_u32(code as u32))
+            .ok_or_else(|| RuntimeError::new(format!("{code} is not a Unicode scalar value.")))?;
+        Ok(Value::String(c.to_string().into()))
+    });
+}
+
+/// `s.name` as a function with the string bound as its first argument.
+/// The natives taking lists or strings are methods of strings too.
+pub(super) fn string_method(s: &LoxString, name: &str) -> Option<Value> {
+    let receiver = Value::String(s.clone());
+    match METHODS.into_iter().find(|method| method.0 == name) {
+        Some((name, arity, function)) => Some(bind_method(receiver, name, arity, function)),
+        None => super::list::sequence_method(receiver, name),
+    }
+}
+
+fn substring(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let start = super::index(&arguments[1])?;
+    let end = super::index(&arguments[2])?;
+    let length = s.chars().count();
+    if start > end || end > length {
+        return Err(RuntimeError::new(format!(
+            "Substring range {start}..{end} out of bounds for length {length}."
+        )));
+    }
+    Ok(Value::String(
+        s.chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>()
+            .into(),
+    ))
+}
+
+fn upper(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(
+        String::from_value(&arguments[0])?.to_uppercase().into(),
+    ))
+}
+
+fn lower(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(
+        String::from_value(&arguments[0])?.to_lowercase().into(),
+    ))
+}
+
+/// An empty separator splits into characters.
+fn split(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let separator = String::from_value(&arguments[1])?;
+    let parts: Vec<String> = if separator.is_empty() {
+        s.chars().map(String::from).collect()
+    } else {
+        s.split(&separator).map(String::from).collect()
+    };
+    Ok(parts.into_value())
+}
+
+fn trim(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    Ok(Value::String(s.trim().into()))
+}
+
+fn starts_with(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let prefix = String::from_value(&arguments[1])?;
+    Ok(Value::Boolean(s.starts_with(&prefix)))
+}
+
+fn ends_with(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let suffix = String::from_value(&arguments[1])?;
+    Ok(Value::Boolean(s.ends_with(&suffix)))
+}
+
+/// Replaces every occurrence.
+fn replace(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let from = String::from_value(&arguments[1])?;
+    let to = String::from_value(&arguments[2])?;
+    if from.is_empty() {
+        return Err(RuntimeError::new("Can't replace an empty string."));
+    }
+    Ok(Value::String(s.replace(&from, &to).into()))
+}
+
+fn ord(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let s = String::from_value(&arguments[0])?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Number(f64::from(u32::from(c)))),
+        _ => Err(RuntimeError::new(format!(
+            "ord expects a single character but got {s:?}."
+        ))),
+    }
+}
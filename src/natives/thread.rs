@@ -0,0 +1,465 @@
+use super::{bind_method, Native};
+use crate::interpreter::{CancelToken, Interpreter, LoxFunction, RuntimeError};
+use crate::messages;
+use crate::parser::{Bytes, Foreign, Key, List, Map, Value, MAX_NESTING};
+use crate::scanner::{Scanner, TokenType};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often a `recv` waiting for a value checks whether the script was
+/// cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// `spawn(function, arguments...)` calls a function on a new thread, in an
+/// interpreter of its own, and returns a handle to it. `handle.join()`
+/// waits for the call and returns its result, or raises its error.
+/// Arguments and results are copied between the threads, so only nil,
+/// booleans, numbers, strings, bytes, lists and maps of them and channel
+/// endpoints can be passed, and nothing mutable is shared. For the same
+/// reason a function declared in Lox is declared again on the new thread
+/// from its source, with the functions it uses, and with copies of the
+/// other variables they use as globals. Methods can't be spawned.
+///
+/// `channel()` returns `{"send": sender, "recv": receiver}`. `send(sender,
+/// value)` queues a copy of the value and `recv(receiver)` waits for the
+/// oldest one, or returns nil once the queue is empty and every copy of
+/// the sender is gone. Threads given an endpoint talk through it.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_variadic_native("spawn", 1, |interpreter, arguments| {
+        let function = match &arguments[0] {
+            Value::NativeFunction(function) => Spawned::Native(function.name.clone()),
+            Value::Function(function) => Spawned::Function(SentFunction::new(function)?),
+            value => {
+                return Err(RuntimeError::new(format!(
+                    "Can only spawn functions, not {}.",
+                    value.type_name()
+                )))
+            }
+        };
+        let name = function.name().to_string();
+        let arguments = arguments[1..]
+            .iter()
+            .map(|argument| Sendable::from_value(argument, 0))
+            .collect::<Result<Vec<_>, _>>()?;
+        let new_interpreter = interpreter.thread_interpreter();
+        let handle = thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                let mut interpreter = new_interpreter();
+                let result = function.find(&mut interpreter).and_then(|function| {
+                    let arguments: Vec<Value> =
+                        arguments.into_iter().map(Sendable::into_value).collect();
+                    interpreter.call(&function, &arguments)
+                });
+                // Spans are in the source parsed on this thread, lines are
+                // the same as in the script.
+                let result = result.map_err(|mut error| {
+                    error.span = None;
+                    error
+                });
+                Sendable::from_value(&result?, 0)
+            })
+            .map_err(|e| RuntimeError::new(format!("Cant start a thread: {e}")))?;
+        Ok(Value::foreign(Thread(RefCell::new(Some(handle)))))
+    });
+    interpreter.define_native("channel", 0, |_, _| {
+        let channel = Arc::new(Channel::default());
+        Ok(Value::Map(Map::from_iter([
+            ("send", Value::foreign(Sender::new(channel.clone()))),
+            ("recv", Value::foreign(Receiver(channel))),
+        ])))
+    });
+    interpreter.define_native("send", 2, |_, arguments| {
+        let sender = expect::<Sender>(&arguments[0], "a channel sender")?;
+        let value = Sendable::from_value(&arguments[1], 0)?;
+        sender.0.state().queue.push_back(value);
+        sender.0.ready.notify_one();
+        Ok(Value::Nil)
+    });
+    interpreter.define_native("recv", 1, |interpreter, arguments| {
+        let receiver = expect::<Receiver>(&arguments[0], "a channel receiver")?;
+        receiver.0.recv(&interpreter.cancel_token())
+    });
+}
+
+/// The host object of type `T` inside `value`, described as `expected` in
+/// the error otherwise.
+fn expect<'a, T: Any>(value: &'a Value, expected: &str) -> Result<&'a T, RuntimeError> {
+    value.downcast_ref().ok_or_else(|| {
+        RuntimeError::new(format!(
+            "Expected {expected} but got {}.",
+            value.type_name()
+        ))
+    })
+}
+
+/// A thread started by `spawn`, until it is joined.
+struct Thread(RefCell<Option<JoinHandle<Result<Sendable, RuntimeError>>>>);
+
+pub(super) fn method(foreign: &Foreign, name: &str) -> Option<Value> {
+    foreign.downcast_ref::<Thread>()?;
+    let function: Native = match name {
+        "join" => join,
+        _ => return None,
+    };
+    Some(bind_method(
+        Value::Foreign(foreign.clone()),
+        name,
+        1,
+        function,
+    ))
+}
+
+fn join(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let thread = expect::<Thread>(&arguments[0], "a thread")?;
+    let handle = thread
+        .0
+        .borrow_mut()
+        .take()
+        .ok_or_else(|| RuntimeError::new("Thread already joined."))?;
+    match handle.join() {
+        Ok(result) => result.map(Sendable::into_value),
+        Err(_) => Err(RuntimeError::new("Thread panicked.")),
+    }
+}
+
+/// Values sent and not received yet, shared by the endpoints of a channel
+/// on every thread.
+#[derive(Default)]
+struct Channel {
+    state: Mutex<ChannelState>,
+    /// Notified when a value is sent or the last sender goes away.
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    queue: VecDeque<Sendable>,
+    /// Copies of the sender still around.
+    senders: usize,
+}
+
+impl Channel {
+    /// A panic on another thread while it held the lock can't leave the
+    /// queue half changed, so the lock is taken anyway.
+    fn state(&self) -> MutexGuard<'_, ChannelState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn recv(&self, cancel: &CancelToken) -> Result<Value, RuntimeError> {
+        let mut state = self.state();
+        loop {
+            if let Some(value) = state.queue.pop_front() {
+                return Ok(value.into_value());
+            }
+            if state.senders == 0 {
+                return Ok(Value::Nil);
+            }
+            if cancel.is_cancelled() {
+                return Err(RuntimeError::text(messages::CANCELLED.text()));
+            }
+            state = self
+                .ready
+                .wait_timeout(state, CANCEL_POLL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+/// The sending end of a channel. The channel counts its copies, so
+/// receivers stop waiting once none are left.
+struct Sender(Arc<Channel>);
+
+impl Sender {
+    fn new(channel: Arc<Channel>) -> Self {
+        channel.state().senders += 1;
+        Sender(channel)
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        Sender::new(self.0.clone())
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.0.state().senders -= 1;
+        self.0.ready.notify_all();
+    }
+}
+
+struct Receiver(Arc<Channel>);
+
+/// A function to call on a spawned thread.
+enum Spawned {
+    /// A native, by the name it has there.
+    Native(String),
+    Function(SentFunction),
+}
+
+impl Spawned {
+    fn name(&self) -> &str {
+        match self {
+            Spawned::Native(name) => name,
+            Spawned::Function(function) => &function.name,
+        }
+    }
+
+    /// The function in `interpreter`, the interpreter of the spawned thread.
+    fn find(self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        match self {
+            Spawned::Native(name) => find_function(interpreter, &name),
+            Spawned::Function(function) => function.declare(interpreter),
+        }
+    }
+}
+
+/// The native called `name` in the interpreter of a spawned thread, a
+/// global or, for names like `math.sqrt`, a member of a module.
+fn find_function(interpreter: &Interpreter, name: &str) -> Result<Value, RuntimeError> {
+    let function = match name.split_once('.') {
+        Some((module, member)) => interpreter
+            .module(module)
+            .and_then(|module| module.get(member)),
+        None => interpreter.global(name),
+    };
+    function.ok_or_else(|| not_defined(name))
+}
+
+fn not_defined(name: &str) -> RuntimeError {
+    RuntimeError::new(format!("{name} isn't defined in spawned threads."))
+}
+
+/// A function declared in Lox, as it is sent to another thread: its source
+/// and that of the functions it uses, which are declared again there, and
+/// copies of the other variables they use.
+struct SentFunction {
+    name: String,
+    /// The function first.
+    sources: Vec<String>,
+    values: Vec<(String, Sendable)>,
+    /// Native modules used, which the other thread has too.
+    modules: Vec<String>,
+}
+
+impl SentFunction {
+    /// Finds the variables `function` uses by the names in its source.
+    /// Names that are fields or its own variables count too, when they
+    /// are also the names of variables it can see.
+    fn new(function: &Rc<LoxFunction>) -> Result<Self, RuntimeError> {
+        if function.captured("this").is_some() {
+            return Err(RuntimeError::new("Can't spawn methods."));
+        }
+        let mut sent = SentFunction {
+            name: function.name().to_string(),
+            sources: Vec::new(),
+            values: Vec::new(),
+            modules: Vec::new(),
+        };
+        let mut seen = HashSet::from([sent.name.clone()]);
+        let mut functions = vec![function.clone()];
+        while let Some(function) = functions.pop() {
+            sent.sources.push(function.source().to_string());
+            let mut scanner = Scanner::new(function.source());
+            scanner.scan_tokens();
+            let names = scanner
+                .tokens
+                .into_iter()
+                .filter(|token| token.token_type == TokenType::Identifier);
+            for name in names.map(|token| token.lexeme) {
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+                match function.captured(&name) {
+                    None | Some(Value::NativeFunction(_)) => {}
+                    Some(Value::Function(function)) => functions.push(function),
+                    Some(Value::Module(module)) => sent.modules.push(module.name.clone()),
+                    Some(value) => sent.values.push((name, Sendable::from_value(&value, 0)?)),
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Declares the function in `interpreter`, on the thread it was sent to.
+    fn declare(self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        for (name, value) in self.values {
+            interpreter.define_global(name, value.into_value());
+        }
+        for name in self.modules {
+            let module = interpreter.module(&name).ok_or_else(|| not_defined(&name))?;
+            interpreter.define_global(name, Value::Module(module));
+        }
+        // The function itself last, in case a function it uses has its name.
+        for source in self.sources.iter().rev() {
+            let result = interpreter.run_captured(source);
+            if let Some(error) = result.diagnostics.into_iter().find(|d| d.is_error()) {
+                let mut runtime_error = RuntimeError::new(error.message);
+                runtime_error.line = error.line;
+                return Err(runtime_error);
+            }
+        }
+        find_function(interpreter, &self.name)
+    }
+}
+
+/// A copy of a value that can be sent to another thread.
+enum Sendable {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    #[cfg(feature = "decimal")]
+    Decimal(crate::decimal::Decimal),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Sendable>),
+    Map(Vec<(Key, Sendable)>),
+    Sender(Sender),
+    Receiver(Arc<Channel>),
+}
+
+impl Sendable {
+    /// Copies `value`, nested `depth` lists and maps deep. Lists and maps
+    /// nested too deeply, like ones holding themselves, can't be copied.
+    fn from_value(value: &Value, depth: usize) -> Result<Self, RuntimeError> {
+        if depth > MAX_NESTING {
+            return Err(RuntimeError::new(
+                "Can't pass lists or maps nested this deeply between threads.",
+            ));
+        }
+        Ok(match value {
+            Value::Nil => Sendable::Nil,
+            Value::Boolean(b) => Sendable::Boolean(*b),
+            Value::Number(n) => Sendable::Number(*n),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => Sendable::Decimal(*d),
+            Value::String(s) => Sendable::String(s.to_string()),
+            Value::Bytes(bytes) => Sendable::Bytes(bytes.as_slice().to_vec()),
+            Value::List(list) => Sendable::List(
+                list.borrow()
+                    .iter()
+                    .map(|item| Sendable::from_value(item, depth + 1))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Map(map) => Sendable::Map(
+                map.borrow()
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), Sendable::from_value(value, depth + 1)?)))
+                    .collect::<Result<_, RuntimeError>>()?,
+            ),
+            Value::Foreign(foreign) => {
+                if let Some(sender) = foreign.downcast_ref::<Sender>() {
+                    Sendable::Sender(sender.clone())
+                } else if let Some(receiver) = foreign.downcast_ref::<Receiver>() {
+                    Sendable::Receiver(receiver.0.clone())
+                } else {
+                    return Err(cant_pass(value));
+                }
+            }
+            other => return Err(cant_pass(other)),
+        })
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Sendable::Nil => Value::Nil,
+            Sendable::Boolean(b) => Value::Boolean(b),
+            Sendable::Number(n) => Value::Number(n),
+            #[cfg(feature = "decimal")]
+            Sendable::Decimal(d) => Value::Decimal(d),
+            Sendable::String(s) => Value::String(s.into()),
+            Sendable::Bytes(bytes) => Value::Bytes(Bytes::new(bytes)),
+            Sendable::List(items) => Value::List(List::new(
+                items.into_iter().map(Sendable::into_value).collect(),
+            )),
+            Sendable::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_value()))
+                    .collect(),
+            ),
+            Sendable::Sender(sender) => Value::foreign(sender),
+            Sendable::Receiver(channel) => Value::foreign(Receiver(channel)),
+        }
+    }
+}
+
+fn cant_pass(value: &Value) -> RuntimeError {
+    RuntimeError::new(format!("Can't pass {} between threads.", value.type_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn spawn_calls_natives() {
+        assert_eq!(run("print spawn(len, list(1, 2)).join();"), "2\n");
+    }
+
+    #[test]
+    fn spawn_declares_lox_functions_again() {
+        let source = "var scale = 10; fun square(x) { return x * x * scale; }
+                      fun sum(n) { var t = 0; for (var i = 1; i <= n; i = i + 1) t = t + square(i);
+                                   return t; }
+                      print spawn(sum, 3).join();";
+        assert_eq!(run(source), "140\n");
+    }
+
+    #[test]
+    fn spawned_closures_get_copies_of_their_variables() {
+        let source = "fun counter() { var n = 5; fun next() { n = n + 1; return n; } return next; }
+                      var next = counter(); print spawn(next).join(); print next();";
+        assert_eq!(run(source), "6\n6\n");
+    }
+
+    #[test]
+    fn spawned_errors_keep_their_line() {
+        let source = "fun fails() {\n  return nil + 1;\n}\nspawn(fails).join();";
+        assert_eq!(
+            run(source),
+            "[line 2] runtime error: To add operands must be two numbers or two strings\n"
+        );
+    }
+
+    #[test]
+    fn methods_and_instances_cant_be_spawned() {
+        let source = "class A { m() {} } spawn(A().m);";
+        assert_eq!(run(source), "[line 1] runtime error: Can't spawn methods.\n");
+        let source = "class A {} var a = A(); fun f() { return a; } spawn(f);";
+        assert_eq!(
+            run(source),
+            "[line 1] runtime error: Can't pass instance between threads.\n"
+        );
+    }
+
+    #[test]
+    fn channels_carry_copies_between_threads() {
+        let source = r#"var c = channel();
+                        fun produce(out) { for (var i = 1; i <= 3; i = i + 1) send(out, i); }
+                        var t = spawn(produce, c.send);
+                        t.join(); print recv(c.recv); print recv(c.recv); print recv(c.recv);"#;
+        assert_eq!(run(source), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn recv_returns_nil_once_every_sender_is_gone() {
+        let source = r#"var c = channel(); var r = c.recv;
+                        send(c.send, list(1, 2)); c = nil;
+                        print recv(r); print recv(r);"#;
+        assert_eq!(run(source), "[1, 2]\nnil\n");
+    }
+}
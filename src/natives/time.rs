@@ -0,0 +1,124 @@
+use crate::interpreter::Interpreter;
+use crate::parser::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Timestamps are seconds since the Unix epoch, as numbers.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("clock", 0, |_, _| Ok(Value::Number(clock())));
+    interpreter.define_native("now", 0, |_, _| Ok(Value::Number(clock())));
+    #[cfg(feature = "time")]
+    formatting::define(interpreter);
+}
+
+/// Seconds since the Unix epoch, with sub-millisecond precision. There is
+/// no clock on wasm32-unknown-unknown, so it is always zero there.
+fn clock() -> f64 {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return 0.0;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// `formatTime`, `parseTime` and `localOffset`, using strftime-style
+/// formats. Times are in UTC; add `localOffset()` for local time.
+#[cfg(feature = "time")]
+mod formatting {
+    use crate::bind::FromValue;
+    use crate::interpreter::{Interpreter, RuntimeError};
+    use crate::parser::Value;
+    use chrono::format::{Item, StrftimeItems};
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Offset};
+
+    pub(super) fn define(interpreter: &mut Interpreter) {
+        interpreter.define_native("formatTime", 2, |_, arguments| {
+            let timestamp = f64::from_value(&arguments[0])?;
+            let format = String::from_value(&arguments[1])?;
+            let items = strftime_items(&format)?;
+            let time = DateTime::from_timestamp(
+                timestamp.floor() as i64,
+                ((timestamp - timestamp.floor()) * 1e9) as u32,
+            )
+            .filter(|_| timestamp.is_finite())
+            .ok_or_else(|| RuntimeError::new(format!("Timestamp {timestamp} out of range.")))?;
+            Ok(Value::String(
+                time.format_with_items(items.into_iter()).to_string().into(),
+            ))
+        });
+        // Returns nil when the text doesn't match the format. Without a
+        // `%z` in the format the time is taken as UTC.
+        interpreter.define_native("parseTime", 2, |_, arguments| {
+            let text = String::from_value(&arguments[0])?;
+            let format = String::from_value(&arguments[1])?;
+            strftime_items(&format)?;
+            let timestamp = DateTime::parse_from_str(&text, &format)
+                .map(|time| time.timestamp_micros())
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(&text, &format)
+                        .map(|time| time.and_utc().timestamp_micros())
+                })
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(&text, &format).map(|date| {
+                        date.and_time(Default::default())
+                            .and_utc()
+                            .timestamp_micros()
+                    })
+                });
+            Ok(match timestamp {
+                Ok(micros) => Value::Number(micros as f64 / 1e6),
+                Err(_) => Value::Nil,
+            })
+        });
+        interpreter.define_native("localOffset", 0, |_, _| {
+            let offset = Local::now().offset().fix().local_minus_utc();
+            Ok(Value::Number(f64::from(offset)))
+        });
+    }
+
+    /// Checks the format up front, since chrono panics on bad ones while
+    /// formatting.
+    fn strftime_items(format: &str) -> Result<Vec<Item<'_>>, RuntimeError> {
+        let items: Vec<_> = StrftimeItems::new(format).collect();
+        if items.contains(&Item::Error) {
+            return Err(RuntimeError::new(format!(
+                "Invalid time format {format:?}."
+            )));
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn clock_and_now_are_seconds_since_the_epoch() {
+        assert_eq!(run("print now() > 1600000000; print now() - clock() < 1;"), "true\ntrue\n");
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn format_and_parse_utc_times() {
+        let source = r#"print formatTime(86400.5, "%Y-%m-%d %H:%M:%S%.3f");
+                        print parseTime("1970-01-02 00:00", "%Y-%m-%d %H:%M");
+                        print parseTime("1970-01-02", "%Y-%m-%d");
+                        print parseTime("not a date", "%Y-%m-%d");"#;
+        assert_eq!(run(source), "1970-01-02 00:00:00.500\n86400\n86400\nnil\n");
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn invalid_formats_are_runtime_errors() {
+        assert_eq!(
+            run(r#"formatTime(0, "%Q");"#),
+            "[line 1] runtime error: Invalid time format \"%Q\".\n"
+        );
+    }
+}
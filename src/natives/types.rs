@@ -0,0 +1,87 @@
+use super::{bind_method, Native};
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::Value;
+
+/// Natives taking any value, which are methods of numbers, strings and
+/// booleans too, like `true.str()`.
+const METHODS: [(&str, usize, Native); 2] = [("type", 1, type_of), ("str", 1, str)];
+
+pub(super) fn define(interpreter: &mut Interpreter) {
+    for (name, arity, function) in METHODS {
+        interpreter.define_native(name, arity, function);
+    }
+    interpreter.define_native("number", 1, |interpreter, arguments| {
+        let text = match &arguments[0] {
+            Value::Number(n) => return Ok(Value::Number(*n)),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => return Ok(Value::Decimal(*d)),
+            Value::String(text) => text.as_str(),
+            other => {
+                return Err(RuntimeError::new(format!(
+                    "Can't convert {} to a number.",
+                    other.type_name()
+                )))
+            }
+        };
+        #[cfg(feature = "decimal")]
+        if interpreter.dialect().decimal {
+            if let Ok(d) = text.trim().parse() {
+                return Ok(Value::Decimal(d));
+            }
+        }
+        match text.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+            _ if interpreter.strict_conversions() => Err(RuntimeError::new(format!(
+                "Can't convert {text:?} to a number."
+            ))),
+            _ => Ok(Value::Nil),
+        }
+    });
+    interpreter.define_native("deepEquals", 2, |_, arguments| {
+        Ok(Value::Boolean(arguments[0].deep_equals(&arguments[1])))
+    });
+}
+
+/// `value.name` as a function with the value bound as its first argument.
+pub(super) fn method(value: &Value, name: &str) -> Option<Value> {
+    let (name, arity, function) = METHODS.into_iter().find(|method| method.0 == name)?;
+    Some(bind_method(value.clone(), name, arity, function))
+}
+
+fn type_of(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(arguments[0].type_name().into()))
+}
+
+/// The value the way print shows it.
+fn str(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(arguments[0].to_string().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::Runner;
+    use crate::testing::transcript;
+
+    fn run(source: &str) -> String {
+        transcript(&mut Runner::new(), source)
+    }
+
+    #[test]
+    fn deep_equals_compares_structure() {
+        let source = r#"print deepEquals(list(1, mapOf("a", 2)), list(1, mapOf("a", 2)));
+                        print deepEquals(list(1, 2), list(2, 1));
+                        var l = list(1); l.push(l); var m = list(1); m.push(m);
+                        print deepEquals(l, m);"#;
+        assert_eq!(run(source), "true\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn deep_equals_compares_deeply_nested_lists() {
+        let source = "var a = list(); var b = list(); var c = list(1);
+                      for (var i = 0; i < 200000; i = i + 1) {
+                        a = list(a); b = list(b); c = list(c);
+                      }
+                      print deepEquals(a, b); print deepEquals(a, c);";
+        assert_eq!(run(source), "true\nfalse\n");
+    }
+}
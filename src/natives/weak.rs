@@ -0,0 +1,41 @@
+use super::{bind_method, Native};
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{Foreign, Value, WeakValue};
+
+/// `weakRef(object)` makes a reference to a list, map or foreign object
+/// that doesn't keep it alive, for caches and observers. Its `get()`
+/// method returns the object, or nil once nothing else holds it.
+pub(super) fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("weakRef", 1, |_, arguments| {
+        let weak = arguments[0].downgrade().ok_or_else(|| {
+            RuntimeError::new(format!(
+                "Only lists, maps and foreign objects have weak references, not {}.",
+                arguments[0].type_name()
+            ))
+        })?;
+        Ok(Value::foreign(WeakRef(weak)))
+    });
+}
+
+struct WeakRef(WeakValue);
+
+pub(super) fn method(foreign: &Foreign, name: &str) -> Option<Value> {
+    foreign.downcast_ref::<WeakRef>()?;
+    let function: Native = match name {
+        "get" => get,
+        _ => return None,
+    };
+    Some(bind_method(
+        Value::Foreign(foreign.clone()),
+        name,
+        1,
+        function,
+    ))
+}
+
+fn get(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let weak = arguments[0]
+        .downcast_ref::<WeakRef>()
+        .ok_or_else(|| RuntimeError::new("Expected a weak reference."))?;
+    Ok(weak.0.upgrade().unwrap_or(Value::Nil))
+}
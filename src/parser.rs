@@ -1,14 +1,43 @@
-use crate::scanner::{TokenInfo, TokenType};
-use crate::util::format_number;
+use crate::interpreter::{LoxClass, LoxFunction, LoxInstance, NativeFunction, NativeModule};
+use crate::messages::{self, Message, Text};
+use crate::scanner::{Span, TokenInfo, TokenType, KEYWORDS};
+use crate::stats::{self, AllocationKind};
+use crate::util::{closest_match, format_number};
+use indexmap::IndexMap;
+use std::any::Any;
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::rc::{Rc, Weak};
+
+/// Most arguments a call can pass, and parameters a function can take.
+const MAX_ARGUMENTS: usize = 255;
+
+/// Deepest the syntax tree can get, so nested input can't overflow the
+/// stack of the parser or of the passes walking the tree after it.
+pub const MAX_NESTING: usize = 256;
 
 #[derive(Clone, PartialEq)]
 pub enum Value {
-    String(String),
+    String(LoxString),
     Number(f64),
+    /// An exact number, what number literals give in the `decimal`
+    /// dialect. Its type is still `number`.
+    #[cfg(feature = "decimal")]
+    Decimal(crate::decimal::Decimal),
     Boolean(bool),
     Nil,
+    NativeFunction(Rc<NativeFunction>),
+    /// A function declared with `fun`, or a method.
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    Foreign(Foreign),
+    Module(Rc<NativeModule>),
+    List(List),
+    Map(Map),
+    Bytes(Bytes),
 }
 
 impl Value {
@@ -19,6 +48,546 @@ impl Value {
             _ => true,
         }
     }
+
+    /// Name of the value's runtime type, as shown to users and returned by
+    /// the `type` native.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::NativeFunction(_) | Value::Function(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Foreign(_) => "foreign",
+            Value::Module(_) => "module",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Bytes(_) => "bytes",
+        }
+    }
+
+    /// Structural equality, used by the `deepEquals` native. Lists are equal
+    /// when they have equal items in the same order, maps when they have the
+    /// same keys with equal values, in any order. Other values compare like
+    /// `==`, so `NaN` is never equal to itself. A pair of lists or maps met
+    /// again counts as equal, so cyclic structures compare without looping.
+    pub fn deep_equals(&self, other: &Value) -> bool {
+        deep_equals(self, other)
+    }
+
+    /// Wraps a host object so natives can hand it to scripts.
+    pub fn foreign<T: Any>(value: T) -> Value {
+        Value::Foreign(Foreign::new(value))
+    }
+
+    /// A reference to the value that doesn't keep it alive, for lists, maps
+    /// and foreign objects. `None` for other values.
+    pub fn downgrade(&self) -> Option<WeakValue> {
+        match self {
+            Value::List(list) => Some(WeakValue::List(Rc::downgrade(&list.0))),
+            Value::Map(map) => Some(WeakValue::Map(Rc::downgrade(&map.0))),
+            Value::Foreign(foreign) => Some(WeakValue::Foreign(
+                foreign.type_name,
+                Rc::downgrade(&foreign.value),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The host object inside a `Value::Foreign`, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Foreign(foreign) => foreign.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Compares pairs from a worklist rather than recursing, so lists and maps
+/// nested however deeply can't overflow the stack.
+fn deep_equals(left: &Value, right: &Value) -> bool {
+    let mut pairs = vec![(left.clone(), right.clone())];
+    let mut seen = HashSet::new();
+    while let Some((left, right)) = pairs.pop() {
+        let pair = match (&left, &right) {
+            (Value::List(a), Value::List(b)) => (a.address(), b.address()),
+            (Value::Map(a), Value::Map(b)) => (a.address(), b.address()),
+            _ if left == right => continue,
+            _ => return false,
+        };
+        if pair.0 == pair.1 || !seen.insert(pair) {
+            continue;
+        }
+        match (&left, &right) {
+            (Value::List(a), Value::List(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                pairs.extend(a.iter().cloned().zip(b.iter().cloned()));
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return false;
+                }
+                for (key, a) in a.iter() {
+                    match b.get(key) {
+                        Some(b) => pairs.push((a.clone(), b.clone())),
+                        None => return false,
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    true
+}
+
+/// An opaque Rust object owned by a script, like a file handle or a
+/// connection. Scripts can only pass it around; natives get it back with
+/// a typed downcast. Wrap the object in a `RefCell` to mutate it.
+#[derive(Clone)]
+pub struct Foreign {
+    type_name: &'static str,
+    value: Rc<dyn Any>,
+}
+
+impl Foreign {
+    pub fn new<T: Any>(value: T) -> Self {
+        Foreign::named(value, std::any::type_name::<T>())
+    }
+
+    /// Like `new`, but shown to scripts as `type_name` rather than the
+    /// type of `value`, for wrappers like `RefCell<T>`.
+    pub fn named<T: Any>(value: T, type_name: &'static str) -> Self {
+        stats::count(AllocationKind::Foreign);
+        Foreign {
+            type_name,
+            value: Rc::new(value),
+        }
+    }
+
+    /// Full Rust type name of the wrapped object.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Shares ownership of the wrapped object if it is a `T`.
+    pub fn downcast<T: Any>(&self) -> Option<Rc<T>> {
+        self.value.clone().downcast().ok()
+    }
+
+    /// Type name without module paths, as shown to scripts.
+    fn short_type_name(&self) -> String {
+        let mut short = String::new();
+        let mut segment = String::new();
+        for c in self.type_name.chars() {
+            if c.is_alphanumeric() || c == '_' || c == ':' {
+                segment.push(c);
+            } else {
+                short.push_str(segment.rsplit("::").next().unwrap_or_default());
+                segment.clear();
+                short.push(c);
+            }
+        }
+        short.push_str(segment.rsplit("::").next().unwrap_or_default());
+        short
+    }
+}
+
+/// Foreign values are equal only when they wrap the same object.
+impl PartialEq for Foreign {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Rc::as_ptr(&self.value), Rc::as_ptr(&other.value))
+    }
+}
+
+impl Debug for Foreign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<foreign {}>", self.short_type_name())
+    }
+}
+
+/// A value made with [`Value::downgrade`]. Values are freed once nothing
+/// else holds them, except lists and maps holding themselves, which are
+/// never freed.
+#[derive(Clone)]
+pub enum WeakValue {
+    List(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<IndexMap<Key, Value>>>),
+    Foreign(&'static str, Weak<dyn Any>),
+}
+
+impl WeakValue {
+    /// The value, if it wasn't freed.
+    pub fn upgrade(&self) -> Option<Value> {
+        match self {
+            WeakValue::List(list) => list.upgrade().map(|list| Value::List(List(list))),
+            WeakValue::Map(map) => map.upgrade().map(|map| Value::Map(Map(map))),
+            WeakValue::Foreign(type_name, value) => value.upgrade().map(|value| {
+                Value::Foreign(Foreign {
+                    type_name,
+                    value,
+                })
+            }),
+        }
+    }
+}
+
+/// A mutable list shared by every value holding it. There is no literal
+/// syntax yet, lists are made by natives.
+#[derive(Clone, Default)]
+pub struct List(Rc<RefCell<Vec<Value>>>);
+
+impl List {
+    pub fn new(items: Vec<Value>) -> Self {
+        stats::count(AllocationKind::List);
+        List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Vec<Value>> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, Vec<Value>> {
+        self.0.borrow_mut()
+    }
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+}
+
+/// Dropping the last reference to a list drops its items from a worklist,
+/// so lists and maps nested however deeply can't overflow the stack.
+impl Drop for List {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.0) == 1 {
+            if let Ok(mut items) = self.0.try_borrow_mut() {
+                drop_nested(std::mem::take(&mut *items));
+            }
+        }
+    }
+}
+
+/// Drops `values`, taking the items out of lists and maps only they
+/// reference first, so those drop empty and nothing recurses.
+fn drop_nested(mut values: Vec<Value>) {
+    while let Some(value) = values.pop() {
+        match &value {
+            Value::List(list) if Rc::strong_count(&list.0) == 1 => {
+                if let Ok(mut items) = list.0.try_borrow_mut() {
+                    values.append(&mut items);
+                }
+            }
+            Value::Map(map) if Rc::strong_count(&map.0) == 1 => {
+                if let Ok(mut entries) = map.0.try_borrow_mut() {
+                    values.extend(std::mem::take(&mut *entries).into_values());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Lists are equal only when they are the same list.
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        format_nested(f, Value::List(self.clone()))
+    }
+}
+
+/// What is left to write of a list or map being formatted.
+enum Piece {
+    Text(&'static str),
+    Key(Key),
+    Value(Value),
+    /// The end of the list or map at the address.
+    End(usize, &'static str),
+}
+
+/// Formats `value`, which holds lists or maps, from a worklist rather than
+/// recursively, so ones nested however deeply can't overflow the stack. A
+/// list or map met again inside itself is written as `[...]` or `{...}`,
+/// so one holding itself, directly or through others, prints without
+/// looping.
+fn format_nested(f: &mut std::fmt::Formatter<'_>, value: Value) -> std::fmt::Result {
+    let mut pieces = vec![Piece::Value(value)];
+    let mut formatting = HashSet::new();
+    while let Some(piece) = pieces.pop() {
+        match piece {
+            Piece::Text(text) => f.write_str(text)?,
+            Piece::Key(key) => write!(f, "{key:?}: ")?,
+            Piece::End(address, text) => {
+                formatting.remove(&address);
+                f.write_str(text)?;
+            }
+            Piece::Value(Value::List(list)) => {
+                if !formatting.insert(list.address()) {
+                    f.write_str("[...]")?;
+                    continue;
+                }
+                f.write_str("[")?;
+                pieces.push(Piece::End(list.address(), "]"));
+                for (i, item) in list.borrow().iter().enumerate().rev() {
+                    pieces.push(Piece::Value(item.clone()));
+                    if i > 0 {
+                        pieces.push(Piece::Text(", "));
+                    }
+                }
+            }
+            Piece::Value(Value::Map(map)) => {
+                if !formatting.insert(map.address()) {
+                    f.write_str("{...}")?;
+                    continue;
+                }
+                f.write_str("{")?;
+                pieces.push(Piece::End(map.address(), "}"));
+                for (i, (key, value)) in map.borrow().iter().enumerate().rev() {
+                    pieces.push(Piece::Value(value.clone()));
+                    pieces.push(Piece::Key(key.clone()));
+                    if i > 0 {
+                        pieces.push(Piece::Text(", "));
+                    }
+                }
+            }
+            Piece::Value(value) => write!(f, "{value:?}")?,
+        }
+    }
+    Ok(())
+}
+
+/// An immutable string. Joining strings with `+` appends to the buffer of
+/// the left one unless something was appended to it already, so building
+/// a string piece by piece takes time in proportion to its length rather
+/// than to its square. Strings sharing a buffer each see their own prefix
+/// of it, which stays alive as long as any of them.
+#[derive(Clone, Default)]
+pub struct LoxString {
+    buffer: Rc<RefCell<String>>,
+    len: usize,
+}
+
+impl LoxString {
+    pub fn new(text: impl Into<String>) -> Self {
+        stats::count(AllocationKind::String);
+        let text = text.into();
+        LoxString {
+            len: text.len(),
+            buffer: Rc::new(RefCell::new(text)),
+        }
+    }
+
+    pub fn as_str(&self) -> Ref<'_, str> {
+        Ref::map(self.buffer.borrow(), |buffer| &buffer[..self.len])
+    }
+
+    /// This string followed by `other`.
+    pub fn concat(&self, other: &LoxString) -> LoxString {
+        if Rc::ptr_eq(&self.buffer, &other.buffer) {
+            let other = other.to_string();
+            return self.append(&other);
+        }
+        self.append(&other.as_str())
+    }
+
+    fn append(&self, other: &str) -> LoxString {
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.len() == self.len {
+            stats::count(AllocationKind::String);
+            buffer.push_str(other);
+            return LoxString {
+                buffer: self.buffer.clone(),
+                len: buffer.len(),
+            };
+        }
+        let mut text = String::with_capacity(self.len + other.len());
+        text.push_str(&buffer[..self.len]);
+        text.push_str(other);
+        LoxString::new(text)
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(text: String) -> Self {
+        LoxString::new(text)
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> Self {
+        LoxString::new(text)
+    }
+}
+
+/// Strings are compared by content.
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        *self.as_str() == *other.as_str()
+    }
+}
+
+impl PartialOrd for LoxString {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.as_str().cmp(&other.as_str()))
+    }
+}
+
+impl Display for LoxString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.as_str())
+    }
+}
+
+impl Debug for LoxString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", &*self.as_str())
+    }
+}
+
+/// An immutable byte string, for file and network data that isn't valid
+/// UTF-8. Made by natives like `bytes` and `fromHex`. Compared by content,
+/// since it can't change.
+#[derive(Clone, Default, PartialEq)]
+pub struct Bytes(Rc<[u8]>);
+
+impl Bytes {
+    pub fn new(data: impl Into<Rc<[u8]>>) -> Self {
+        stats::count(AllocationKind::Bytes);
+        Bytes(data.into())
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Printed like a Rust byte string literal, `b"GIF89a\x01"`.
+impl Debug for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b\"{}\"", self.0.escape_ascii())
+    }
+}
+
+/// A mutable map that keeps insertion order. Shared like lists, and also
+/// made only by natives for now. Scripts read string entries as properties,
+/// `map.key`.
+#[derive(Clone, Default)]
+pub struct Map(Rc<RefCell<IndexMap<Key, Value>>>);
+
+impl Map {
+    pub fn new(entries: IndexMap<Key, Value>) -> Self {
+        stats::count(AllocationKind::Map);
+        Map(Rc::new(RefCell::new(entries)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, IndexMap<Key, Value>> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, IndexMap<Key, Value>> {
+        self.0.borrow_mut()
+    }
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+}
+
+impl<K: Into<Key>> FromIterator<(K, Value)> for Map {
+    fn from_iter<I: IntoIterator<Item = (K, Value)>>(entries: I) -> Self {
+        Map::new(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+}
+
+/// Like lists, maps drop their values from a worklist.
+impl Drop for Map {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.0) == 1 {
+            if let Ok(mut entries) = self.0.try_borrow_mut() {
+                drop_nested(std::mem::take(&mut *entries).into_values().collect());
+            }
+        }
+    }
+}
+
+/// Maps are equal only when they are the same map.
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        format_nested(f, Value::Map(self.clone()))
+    }
+}
+
+/// A map key. Only values that can't change make keys, so an entry can't
+/// get lost in its map when its key is mutated: nil, booleans, strings and
+/// numbers other than `NaN`, which isn't equal to itself. `-0` and `0` are
+/// the same key. Converted with `FromValue` and `From<Key> for Value`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Boolean(bool),
+    /// Bits of a number that isn't `NaN` or `-0`.
+    Number(u64),
+    String(String),
+}
+
+impl Key {
+    /// `None` for `NaN`.
+    pub fn number(n: f64) -> Option<Key> {
+        if n.is_nan() {
+            return None;
+        }
+        // Adding zero turns -0 into 0 and leaves everything else alone.
+        Some(Key::Number((n + 0.0).to_bits()))
+    }
+}
+
+impl From<&str> for Key {
+    fn from(key: &str) -> Self {
+        Key::String(key.to_string())
+    }
+}
+
+impl From<String> for Key {
+    fn from(key: String) -> Self {
+        Key::String(key)
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Nil => Value::Nil,
+            Key::Boolean(b) => Value::Boolean(b),
+            Key::Number(bits) => Value::Number(f64::from_bits(bits)),
+            Key::String(s) => Value::String(s.into()),
+        }
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", Value::from(self.clone()))
+    }
 }
 
 #[derive(Debug,Clone)]
@@ -33,18 +602,43 @@ pub enum Expr {
         right: Box<Expr>,
     },
     Grouping(Box<Expr>),
-    Literal(Value),
-    Variable(TokenInfo),
+    Literal {
+        value: Value,
+        token: TokenInfo,
+    },
+    Variable {
+        name: TokenInfo,
+        distance: Distance,
+    },
 
     Assign {
         name: TokenInfo,
         value: Box<Expr>,
+        distance: Distance,
     },
     Logical {
         left: Box<Expr>,
         operator: TokenInfo,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: TokenInfo,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: TokenInfo,
+    },
+    Set {
+        object: Box<Expr>,
+        name: TokenInfo,
+        value: Box<Expr>,
+    },
+    This {
+        keyword: TokenInfo,
+        distance: Distance,
+    },
 }
 
 #[derive(Debug)]
@@ -65,6 +659,75 @@ pub enum Stmt {
         condition: Expr,
         body: Box<Stmt>,
     },
+    Import(TokenInfo),
+    Function(Rc<FunctionDecl>),
+    Class {
+        name: TokenInfo,
+        methods: Vec<Rc<FunctionDecl>>,
+    },
+    Return {
+        keyword: TokenInfo,
+        value: Option<Expr>,
+    },
+}
+
+/// How many scopes out from the innermost one a variable is looked up
+/// from, set by the resolver: the scope declaring it for local variables,
+/// past every scope the resolver saw otherwise. Zero until then, so code
+/// that wasn't resolved looks the name up in every scope.
+#[derive(Debug, Clone, Default)]
+pub struct Distance(Cell<usize>);
+
+impl Distance {
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+    pub(crate) fn set(&self, distance: usize) {
+        self.0.set(distance);
+    }
+}
+
+/// A `fun` declaration. Shared with the function values it creates, which
+/// run its body.
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: TokenInfo,
+    pub params: Vec<TokenInfo>,
+    pub body: Vec<Stmt>,
+    /// The declaration as source, from `fun` on and starting on the line it
+    /// was on, so it can be parsed again on spawned threads.
+    pub source: String,
+}
+
+impl Expr {
+    /// Line the expression starts on.
+    pub fn line(&self) -> usize {
+        match self {
+            Expr::Binary { left, .. } | Expr::Logical { left, .. } => left.line(),
+            Expr::Unary { operator, .. } => operator.line,
+            Expr::Grouping(e) => e.line(),
+            Expr::Literal { token, .. } => token.line,
+            Expr::Variable { name, .. } | Expr::Assign { name, .. } => name.line,
+            Expr::Call { callee, .. } => callee.line(),
+            Expr::Get { object, .. } | Expr::Set { object, .. } => object.line(),
+            Expr::This { keyword, .. } => keyword.line,
+        }
+    }
+}
+
+impl Stmt {
+    /// Line the statement starts on, `None` for an empty block.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Expression(e) | Stmt::Print(e) => Some(e.line()),
+            Stmt::Var { name, .. } | Stmt::Import(name) => Some(name.line),
+            Stmt::Function(function) => Some(function.name.line),
+            Stmt::Class { name, .. } => Some(name.line),
+            Stmt::Return { keyword, .. } => Some(keyword.line),
+            Stmt::Block(statments) => statments.first().and_then(Stmt::line),
+            Stmt::If { condition, .. } | Stmt::While { condition, .. } => Some(condition.line()),
+        }
+    }
 }
 
 impl Display for Value {
@@ -72,8 +735,19 @@ impl Display for Value {
         match self {
             Value::String(str) => write!(f, "{str}"),
             Value::Number(n) => write!(f, "{}", format_number(n)),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => write!(f, "{}", d.normalize()),
             Value::Boolean(b) => write!(f, "{b}"),
             Value::Nil => write!(f, "nil"),
+            Value::NativeFunction(function) => write!(f, "<native fn {}>", function.name),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::Class(class) => write!(f, "{}", class.name()),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class().name()),
+            Value::Foreign(foreign) => write!(f, "{foreign:?}"),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+            Value::List(list) => write!(f, "{list:?}"),
+            Value::Map(map) => write!(f, "{map:?}"),
+            Value::Bytes(bytes) => write!(f, "{bytes:?}"),
         }
     }
 }
@@ -83,8 +757,19 @@ impl Debug for Value {
         match self {
             Value::String(str) => write!(f, "{str:?}"),
             Value::Number(n) => write!(f, "{}", format_number(n)),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(d) => write!(f, "{}", d.normalize()),
             Value::Boolean(b) => write!(f, "{b}"),
             Value::Nil => write!(f, "nil"),
+            Value::NativeFunction(function) => write!(f, "<native fn {}>", function.name),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::Class(class) => write!(f, "{}", class.name()),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class().name()),
+            Value::Foreign(foreign) => write!(f, "{foreign:?}"),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+            Value::List(list) => write!(f, "{list:?}"),
+            Value::Map(map) => write!(f, "{map:?}"),
+            Value::Bytes(bytes) => write!(f, "{bytes:?}"),
         }
     }
 }
@@ -101,9 +786,9 @@ impl Display for Expr {
                 parenthesize(f, operator.lexeme.clone(), &[right.as_ref()])
             }
             Expr::Grouping(expr) => parenthesize(f, "group".to_string(), &[expr.as_ref()]),
-            Expr::Literal(value) => write!(f, "{value:?}"),
-            Expr::Variable(name_token) => write!(f, "{}", name_token.lexeme),
-            Expr::Assign { name, value } => {
+            Expr::Literal { value, .. } => write!(f, "{value:?}"),
+            Expr::Variable { name, .. } => write!(f, "{}", name.lexeme),
+            Expr::Assign { name, value, .. } => {
                 parenthesize(f, format!("assign {} to", name.lexeme), &[value.as_ref()])
             }
             Expr::Logical {
@@ -111,10 +796,113 @@ impl Display for Expr {
                 operator,
                 right,
             } => parenthesize(f, operator.lexeme.clone(), &[left.as_ref(), right.as_ref()]),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs = vec![callee.as_ref()];
+                exprs.extend(arguments.iter());
+                parenthesize(f, "call".to_string(), &exprs)
+            }
+            Expr::Get { object, name } => {
+                parenthesize(f, format!("get {} from", name.lexeme), &[object.as_ref()])
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => parenthesize(
+                f,
+                format!("set {} of", name.lexeme),
+                &[object.as_ref(), value.as_ref()],
+            ),
+            Expr::This { .. } => write!(f, "this"),
         }
     }
 }
 
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stmt::Expression(expr) => write!(f, "(expression {expr})"),
+            Stmt::Print(expr) => write!(f, "(print {expr})"),
+            Stmt::Var {
+                name,
+                initializer: Some(initializer),
+            } => write!(f, "(var {} {initializer})", name.lexeme),
+            Stmt::Var {
+                name,
+                initializer: None,
+            } => write!(f, "(var {})", name.lexeme),
+            Stmt::Block(statments) => {
+                write!(f, "(block")?;
+                for stmt in statments.iter() {
+                    write!(f, " {stmt}")?;
+                }
+                write!(f, ")")
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write!(f, "(if {condition} {then_branch}")?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {else_branch}")?;
+                }
+                write!(f, ")")
+            }
+            Stmt::While { condition, body } => write!(f, "(while {condition} {body})"),
+            Stmt::Import(name) => write!(f, "(import {})", name.lexeme),
+            Stmt::Function(function) => write!(f, "{function}"),
+            Stmt::Class { name, methods } => {
+                write!(f, "(class {}", name.lexeme)?;
+                for method in methods.iter() {
+                    write!(f, " {method}")?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Return {
+                value: Some(value), ..
+            } => write!(f, "(return {value})"),
+            Stmt::Return { value: None, .. } => write!(f, "(return)"),
+        }
+    }
+}
+
+impl Display for FunctionDecl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<&str> = self.params.iter().map(|p| p.lexeme.as_str()).collect();
+        write!(f, "(fun {} ({})", self.name.lexeme, params.join(" "))?;
+        for stmt in self.body.iter() {
+            write!(f, " {stmt}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Source of the function declaration made of `tokens`, from its name on.
+/// Tokens are written on the lines they were on.
+fn declaration_source(tokens: &[TokenInfo]) -> String {
+    let mut line = tokens.first().map_or(1, |token| token.line);
+    let mut source = "\n".repeat(line - 1) + "fun";
+    for token in tokens {
+        let lexeme = match token.token_type {
+            TokenType::String => format!("\"{}\"", token.lexeme),
+            _ => token.lexeme.clone(),
+        };
+        // Strings are on the line they end on.
+        let start = token.line - lexeme.matches('\n').count();
+        if start > line {
+            source.push_str(&"\n".repeat(start - line));
+        } else {
+            source.push(' ');
+        }
+        source.push_str(&lexeme);
+        line = token.line;
+    }
+    source
+}
+
 fn parenthesize(
     f: &mut std::fmt::Formatter<'_>,
     name: String,
@@ -130,11 +918,18 @@ fn parenthesize(
 pub struct Parser {
     tokens: Vec<TokenInfo>,
     current: usize,
+    /// Nesting of what's being parsed. Left as it was by errors, so it's
+    /// reset where parsing recovers.
+    depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<TokenInfo>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            depth: 0,
+        }
     }
 
     fn get_matched_token(&mut self, token_types: &[TokenType]) -> Option<TokenInfo> {
@@ -180,40 +975,44 @@ impl Parser {
         self.peak().token_type == TokenType::EOF
     }
 
-    fn new_error(
-        &self,
-        error_type: ParsingErrorType,
-        message: impl Display,
-        expression: Option<Expr>,
-    ) -> ParsingError {
-        self.new_error_on_line(error_type, message, self.previous().line, expression)
+    fn new_error(&self, error_type: ParsingErrorType, message: Text) -> ParsingError {
+        self.new_error_at(error_type, message, self.previous())
     }
 
-    fn new_error_on_line(
+    fn new_error_at(
         &self,
         error_type: ParsingErrorType,
-        message: impl Display,
-        line: usize,
-        expression: Option<Expr>,
+        message: Text,
+        token: &TokenInfo,
     ) -> ParsingError {
         ParsingError {
             error_type,
-            message: message.to_string(),
-            line,
-            expression,
+            code: message.code,
+            message: message.text,
+            hint: None,
+            line: token.line,
+            span: token.span,
+            found_line: self.peak().line,
+            found_span: self.peak().span,
         }
     }
-    fn new_expr_stmt_error(&self, message: impl Display, expr: Expr) -> ParsingError {
-        self.new_error(ParsingErrorType::Stmt, message, Some(expr))
+    fn new_stmt_error(&self, message: Text) -> ParsingError {
+        self.new_error(ParsingErrorType::Stmt, message)
     }
-    fn new_stmt_error(&self, message: impl Display) -> ParsingError {
-        self.new_error(ParsingErrorType::Stmt, message, None)
+    fn new_expr_error(&self, message: Text) -> ParsingError {
+        self.new_error(ParsingErrorType::Expr, message)
     }
-    fn new_expr_error(&self, message: impl Display) -> ParsingError {
-        self.new_error(ParsingErrorType::Expr, message, None)
+    fn new_expr_error_at(&self, message: Text, token: &TokenInfo) -> ParsingError {
+        self.new_error_at(ParsingErrorType::Expr, message, token)
     }
-    fn new_expr_error_on_line(&self, message: impl Display, line: usize) -> ParsingError {
-        self.new_error_on_line(ParsingErrorType::Expr, message, line, None)
+
+    /// Goes one level deeper, failing past `MAX_NESTING`.
+    fn enter(&mut self) -> Result<(), ParsingError> {
+        if self.depth >= MAX_NESTING {
+            return Err(self.new_expr_error_at(messages::TOO_DEEPLY_NESTED.text(), self.peak()));
+        }
+        self.depth += 1;
+        Ok(())
     }
 
     pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParsingError>> {
@@ -222,18 +1021,62 @@ impl Parser {
         while !self.is_at_end() {
             match self.declaration() {
                 Ok(declaration) => statments.push(declaration),
-                Err(e) => errors.extend(e),
+                Err(e) => {
+                    errors.extend(e);
+                    self.depth = 0;
+                    self.synchronize();
+                }
             }
         }
-        return if errors.len() == 0 {
+        if errors.is_empty() {
             Ok(statments)
         } else {
             Err(errors)
-        };
+        }
+    }
+
+    /// Skip tokens until the start of the next statement, so one mistake
+    /// doesn't produce a cascade of errors.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+            match self.peak().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::Import
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::RightBrace => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    /// Parses input that must consist of exactly one expression, like a bare
+    /// `1 + 2` typed at the REPL prompt.
+    pub fn parse_expression(&mut self) -> Result<Expr, ParsingError> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            let message = messages::EXPECT_END_OF_EXPRESSION.text();
+            return Err(self.new_expr_error_at(message, self.peak()));
+        }
+        Ok(expr)
     }
 
     pub fn expression(&mut self) -> Result<Expr, ParsingError> {
-        self.assigment()
+        self.enter()?;
+        let expr = self.assigment()?;
+        self.depth -= 1;
+        Ok(expr)
     }
 
     fn assigment(&mut self) -> Result<Expr, ParsingError> {
@@ -241,15 +1084,24 @@ impl Parser {
 
         if self.match_tokens(&[TokenType::Equal]) {
             let equals_token = self.previous().clone();
+            self.enter()?;
             let value = self.assigment()?;
+            self.depth -= 1;
 
             return match expr {
-                Expr::Variable(name) => Ok(Expr::Assign {
+                Expr::Variable { name, distance } => Ok(Expr::Assign {
+                    name,
+                    value: Box::new(value),
+                    distance,
+                }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
                     name,
                     value: Box::new(value),
                 }),
                 _ => {
-                    Err(self.new_expr_error_on_line("Invalid assigment target", equals_token.line))
+                    let message = messages::INVALID_ASSIGNMENT_TARGET.text();
+                    Err(self.new_expr_error_at(message, &equals_token))
                 }
             };
         }
@@ -257,8 +1109,10 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.comparison()?;
         while self.match_tokens(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.comparison()?;
             expr = Expr::Binary {
@@ -267,10 +1121,12 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
 
     fn comparison(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.term()?;
         while self.match_tokens(&[
             TokenType::Less,
@@ -278,6 +1134,7 @@ impl Parser {
             TokenType::Greater,
             TokenType::GreaterEqual,
         ]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.term()?;
             expr = Expr::Binary {
@@ -286,12 +1143,15 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
 
     fn term(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.factor()?;
         while self.match_tokens(&[TokenType::Minus, TokenType::Plus]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.factor()?;
             expr = Expr::Binary {
@@ -300,11 +1160,14 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
     fn factor(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.unary()?;
         while self.match_tokens(&[TokenType::Star, TokenType::Slash]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.unary()?;
             expr = Expr::Binary {
@@ -313,83 +1176,182 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
 
     fn unary(&mut self) -> Result<Expr, ParsingError> {
         if self.match_tokens(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous().clone();
+            self.enter()?;
             let right = self.unary()?;
+            self.depth -= 1;
             return Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
             });
         }
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            self.enter()?;
+            if self.match_tokens(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_tokens(&[TokenType::Dot]) {
+                let name = self
+                    .get_matched_token(&[TokenType::Identifier])
+                    .ok_or_else(|| {
+                        self.new_expr_error_at(messages::EXPECT_PROPERTY_NAME.text(), self.peak())
+                    })?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParsingError> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(self.new_expr_error_at(
+                        messages::TOO_MANY_ARGUMENTS.with(&[("max", &MAX_ARGUMENTS)]),
+                        self.peak(),
+                    ));
+                }
+                arguments.push(self.expression()?);
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        if !self.match_tokens(&[TokenType::RightParen]) {
+            let message = messages::EXPECT_PAREN_AFTER_ARGUMENTS.text();
+            return Err(self.new_expr_error_at(message, self.peak()));
+        }
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren: self.previous().clone(),
+            arguments,
+        })
     }
 
     fn primary(&mut self) -> Result<Expr, ParsingError> {
+        let literal = |value, token: &TokenInfo| Expr::Literal {
+            value,
+            token: token.clone(),
+        };
         if self.match_tokens(&[TokenType::True]) {
-            return Ok(Expr::Literal(Value::Boolean(true)));
+            return Ok(literal(Value::Boolean(true), self.previous()));
         }
         if self.match_tokens(&[TokenType::False]) {
-            return Ok(Expr::Literal(Value::Boolean(false)));
+            return Ok(literal(Value::Boolean(false), self.previous()));
         }
         if self.match_tokens(&[TokenType::Nil]) {
-            return Ok(Expr::Literal(Value::Nil));
+            return Ok(literal(Value::Nil, self.previous()));
         }
         if self.match_tokens(&[TokenType::String]) {
-            return Ok(Expr::Literal(Value::String(self.previous().lexeme.clone())));
+            let value = Value::String(self.previous().lexeme.as_str().into());
+            return Ok(literal(value, self.previous()));
         }
         if self.match_tokens(&[TokenType::Number]) {
-            return Ok(Expr::Literal(Value::Number(
-                self.previous().number.unwrap(),
-            )));
+            let value = Value::Number(self.previous().number.unwrap());
+            return Ok(literal(value, self.previous()));
+        }
+        if self.match_tokens(&[TokenType::This]) {
+            return Ok(Expr::This {
+                keyword: self.previous().clone(),
+                distance: Distance::default(),
+            });
         }
         if self.match_tokens(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous().clone()));
+            return Ok(Expr::Variable {
+                name: self.previous().clone(),
+                distance: Distance::default(),
+            });
         }
 
-        self.match_tokens(&[TokenType::LeftParen]);
+        if !self.match_tokens(&[TokenType::LeftParen]) {
+            return Err(self.new_expr_error_at(messages::EXPECT_EXPRESSION.text(), self.peak()));
+        }
 
         let expr = self.expression()?;
         if !self.match_tokens(&[TokenType::RightParen]) {
-            return Err(self.new_expr_error("Unterminated parenthesize"));
+            return Err(self.new_expr_error(messages::EXPECT_PAREN_AFTER_EXPRESSION.text()));
         }
-        return Ok(Expr::Grouping(Box::new(expr)));
+        Ok(Expr::Grouping(Box::new(expr)))
     }
 
     fn statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
-        if self.match_tokens(&[TokenType::For]) {
-            return self.for_statment();
-        }
-        if self.match_tokens(&[TokenType::While]) {
-            return self.while_statment();
-        }
-        if self.match_tokens(&[TokenType::Print]) {
-            return self.print_statment();
-        }
-        if self.match_tokens(&[TokenType::LeftBrace]) {
-            return self.block_statment();
+        self.enter().map_err(|e| vec![e])?;
+        let statment = if self.match_tokens(&[TokenType::For]) {
+            self.for_statment()
+        } else if self.match_tokens(&[TokenType::While]) {
+            self.while_statment()
+        } else if self.match_tokens(&[TokenType::Print]) {
+            self.print_statment()
+        } else if self.match_tokens(&[TokenType::LeftBrace]) {
+            self.block_statment()
+        } else if self.match_tokens(&[TokenType::If]) {
+            self.if_statment()
+        } else if self.match_tokens(&[TokenType::Return]) {
+            self.return_statment()
+        } else {
+            self.expression_statment_or_typo()
+        }?;
+        self.depth -= 1;
+        Ok(statment)
+    }
+
+    fn expression_statment_or_typo(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let first = self.peak().clone();
+        self.expression_statment().map_err(|mut errors| {
+            // A statement starting with a misspelled keyword is parsed as an
+            // expression, which then fails.
+            let keyword = Some(&first)
+                .filter(|token| token.token_type == TokenType::Identifier)
+                .and_then(|token| closest_match(&token.lexeme, KEYWORDS));
+            if let (Some(keyword), Some(error)) = (keyword, errors.first_mut()) {
+                let hint = messages::DID_YOU_MEAN_KEYWORD.with(&[("keyword", &keyword)]);
+                error.hint = Some(hint.text);
+            }
+            errors
+        })
+    }
+    /// `return` outside a function parses, the resolver reports it.
+    fn return_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let keyword = self.previous().clone();
+        let mut value = None;
+        if !self.check(&TokenType::Semicolon) {
+            value = Some(self.expression().map_err(|e| vec![e])?);
         }
-        if self.match_tokens(&[TokenType::If]) {
-            return self.if_statment();
+        if !self.match_tokens(&[TokenType::Semicolon]) {
+            return Err(vec![self.new_stmt_error(messages::EXPECT_SEMICOLON_AFTER_RETURN.text())]);
         }
-        self.expression_statment()
+        Ok(Stmt::Return { keyword, value })
     }
     fn print_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         let expr = self.expression().map_err(|e| vec![e])?;
         if !self.match_tokens(&[TokenType::Semicolon]) {
-            return Err(vec![self.new_stmt_error("Expect ';' after value")]);
+            return Err(vec![self.new_stmt_error(messages::EXPECT_SEMICOLON_AFTER_VALUE.text())]);
         }
         Ok(Stmt::Print(expr))
     }
     fn expression_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         let expr = self.expression().map_err(|e| vec![e])?;
         if !self.match_tokens(&[TokenType::Semicolon]) {
-            return Err(vec![
-                self.new_expr_stmt_error("Expect ';' after expression", expr)
-            ]);
+            let message = messages::EXPECT_SEMICOLON_AFTER_EXPRESSION.text();
+            return Err(vec![self.new_stmt_error(message)]);
         }
         Ok(Stmt::Expression(expr))
     }
@@ -397,41 +1359,140 @@ impl Parser {
     fn declaration(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         if self.match_tokens(&[TokenType::Var]) {
             self.var_declaration()
+        } else if self.match_tokens(&[TokenType::Fun]) {
+            self.function_declaration()
+        } else if self.match_tokens(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_tokens(&[TokenType::Import]) {
+            self.import_declaration()
         } else {
             self.statment()
         }
     }
 
+    fn import_declaration(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let name = self
+            .get_matched_token(&[TokenType::Identifier])
+            .ok_or_else(|| vec![self.new_stmt_error(messages::EXPECT_MODULE_NAME.text())])?;
+        if !self.match_tokens(&[TokenType::Semicolon]) {
+            return Err(vec![self.new_stmt_error(messages::EXPECT_SEMICOLON_AFTER_IMPORT.text())]);
+        }
+        Ok(Stmt::Import(name))
+    }
+
+    fn function_declaration(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let function = self.function(messages::EXPECT_FUNCTION_NAME)?;
+        Ok(Stmt::Function(Rc::new(function)))
+    }
+
+    /// A function or method, from its name on. `missing_name` is the error
+    /// when there's no name.
+    fn function(&mut self, missing_name: Message) -> Result<FunctionDecl, Vec<ParsingError>> {
+        let start = self.current;
+        let name = self
+            .get_matched_token(&[TokenType::Identifier])
+            .ok_or_else(|| vec![self.new_stmt_error(missing_name.text())])?;
+        if !self.match_tokens(&[TokenType::LeftParen]) {
+            let message = messages::EXPECT_PAREN_AFTER_FUNCTION_NAME.text();
+            return Err(vec![self.new_stmt_error(message)]);
+        }
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let message = messages::TOO_MANY_PARAMETERS.with(&[("max", &MAX_ARGUMENTS)]);
+                    return Err(vec![self.new_expr_error_at(message, self.peak())]);
+                }
+                let param = self.get_matched_token(&[TokenType::Identifier]);
+                let param = param.ok_or_else(|| {
+                    vec![self.new_stmt_error(messages::EXPECT_PARAMETER_NAME.text())]
+                })?;
+                params.push(param);
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        if !self.match_tokens(&[TokenType::RightParen]) {
+            let message = messages::EXPECT_PAREN_AFTER_PARAMETERS.text();
+            return Err(vec![self.new_stmt_error(message)]);
+        }
+        if !self.match_tokens(&[TokenType::LeftBrace]) {
+            let message = messages::EXPECT_BRACE_BEFORE_BODY.text();
+            return Err(vec![self.new_stmt_error(message)]);
+        }
+        self.enter().map_err(|e| vec![e])?;
+        let body = self.block();
+        self.depth -= 1;
+        let body = body?;
+        let source = declaration_source(&self.tokens[start..self.current]);
+        Ok(FunctionDecl {
+            name,
+            params,
+            body,
+            source,
+        })
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let name = self
+            .get_matched_token(&[TokenType::Identifier])
+            .ok_or_else(|| vec![self.new_stmt_error(messages::EXPECT_CLASS_NAME.text())])?;
+        if !self.match_tokens(&[TokenType::LeftBrace]) {
+            let message = messages::EXPECT_BRACE_BEFORE_CLASS_BODY.text();
+            return Err(vec![self.new_stmt_error(message)]);
+        }
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(Rc::new(self.function(messages::EXPECT_METHOD_NAME)?));
+        }
+        if !self.match_tokens(&[TokenType::RightBrace]) {
+            let message = messages::EXPECT_BRACE_AFTER_CLASS_BODY.text();
+            return Err(vec![self.new_stmt_error(message)]);
+        }
+        Ok(Stmt::Class { name, methods })
+    }
+
     fn var_declaration(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         let name = self
             .get_matched_token(&[TokenType::Identifier])
-            .ok_or_else(|| vec![self.new_stmt_error("Expect variable name.")])?;
+            .ok_or_else(|| vec![self.new_stmt_error(messages::EXPECT_VARIABLE_NAME.text())])?;
         let mut initializer: Option<Expr> = None;
         if self.match_tokens(&[TokenType::Equal]) {
             initializer = Some(self.expression().map_err(|e| vec![e])?)
         }
         if !self.match_tokens(&[TokenType::Semicolon]) {
             return Err(vec![
-                self.new_stmt_error("Expect ';' after variable declaration.")
+                self.new_stmt_error(messages::EXPECT_SEMICOLON_AFTER_VARIABLE.text())
             ]);
         }
         Ok(Stmt::Var { name, initializer })
     }
 
     fn block_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        self.block().map(Stmt::Block)
+    }
+
+    /// The statements of a block, after its `{`.
+    fn block(&mut self) -> Result<Vec<Stmt>, Vec<ParsingError>> {
         let mut statments = Vec::new();
         let mut errors = Vec::new();
+        let depth = self.depth;
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
             match self.declaration() {
                 Ok(s) => statments.push(s),
-                Err(e) => errors.extend(e),
+                Err(e) => {
+                    errors.extend(e);
+                    self.depth = depth;
+                    self.synchronize();
+                }
             }
         }
         if !self.match_tokens(&[TokenType::RightBrace]) {
-            errors.push(self.new_stmt_error("Expect '}' after block"))
+            errors.push(self.new_stmt_error(messages::EXPECT_BRACE_AFTER_BLOCK.text()))
         };
-        if errors.len() == 0 {
-            Ok(Stmt::Block(statments))
+        if errors.is_empty() {
+            Ok(statments)
         } else {
             Err(errors)
         }
@@ -440,7 +1501,7 @@ impl Parser {
     fn if_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         let mut errors = Vec::new();
         if !self.match_tokens(&[TokenType::LeftParen]) {
-            errors.push(self.new_stmt_error("Expect '(' after if ."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_IF.text()));
             return Err(errors);
         }
         let condition_parse_result = self.expression();
@@ -450,7 +1511,7 @@ impl Parser {
             Ok(expr) => condition = Some(expr),
         }
         if !self.match_tokens(&[TokenType::RightParen]) {
-            errors.push(self.new_stmt_error("Expect ')' after if condition."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_IF_CONDITION.text()));
         }
         let then_branch_parse_result = self.statment();
         let mut then_branch = None;
@@ -465,7 +1526,7 @@ impl Parser {
                 Err(e) => errors.extend(e),
             }
         };
-        if errors.len() == 0 {
+        if errors.is_empty() {
             Ok(Stmt::If {
                 condition: condition.unwrap(),
                 then_branch: Box::new(then_branch.unwrap()),
@@ -477,8 +1538,10 @@ impl Parser {
     }
 
     fn or(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.and()?;
         while self.match_tokens(&[TokenType::Or]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.and()?;
             expr = Expr::Logical {
@@ -487,12 +1550,15 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
 
     fn and(&mut self) -> Result<Expr, ParsingError> {
+        let depth = self.depth;
         let mut expr = self.equality()?;
         while self.match_tokens(&[TokenType::And]) {
+            self.enter()?;
             let operator = self.previous().clone();
             let right = self.equality()?;
             expr = Expr::Logical {
@@ -501,13 +1567,14 @@ impl Parser {
                 right: Box::new(right),
             }
         }
+        self.depth = depth;
         Ok(expr)
     }
 
     fn while_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
         let mut errors = Vec::new();
         if !self.match_tokens(&[TokenType::LeftParen]) {
-            errors.push(self.new_stmt_error("Expect '(' after 'while'."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_WHILE.text()));
             return Err(errors);
         }
         let condition_parse_result = self.expression();
@@ -517,7 +1584,7 @@ impl Parser {
             Ok(expr) => condition = Some(expr),
         }
         if !self.match_tokens(&[TokenType::RightParen]) {
-            errors.push(self.new_stmt_error("Expect ')' after condition."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_CONDITION.text()));
         }
         let body_parse_result = self.statment();
         let mut body = None;
@@ -525,7 +1592,7 @@ impl Parser {
             Err(e) => errors.extend(e),
             Ok(stmt) => body = Some(stmt),
         };
-        if errors.len() == 0 {
+        if errors.is_empty() {
             Ok(Stmt::While {
                 condition: condition.unwrap(),
                 body: Box::new(body.unwrap()),
@@ -536,9 +1603,10 @@ impl Parser {
     }
 
     fn for_statment(&mut self) -> Result<Stmt, Vec<ParsingError>> {
+        let for_token = self.previous().clone();
         let mut errors = Vec::new();
         if !self.match_tokens(&[TokenType::LeftParen]) {
-            errors.push(self.new_stmt_error("Expect '(' after 'for'."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_FOR.text()));
             return Err(errors);
         };
         let mut initializer = None;
@@ -556,7 +1624,8 @@ impl Parser {
             }
         };
         if !self.match_tokens(&[TokenType::Semicolon]) {
-            errors.push(self.new_stmt_error("Expect ';' after loop condition."));
+            let message = messages::EXPECT_SEMICOLON_AFTER_LOOP_CONDITION.text();
+            errors.push(self.new_stmt_error(message));
             return Err(errors);
         };
         let mut increment = None;
@@ -567,18 +1636,21 @@ impl Parser {
             }
         };
         if !self.match_tokens(&[TokenType::RightParen]) {
-            errors.push(self.new_stmt_error("Expect ')' after for clauses."));
+            errors.push(self.new_stmt_error(messages::EXPECT_PAREN_AFTER_FOR_CLAUSES.text()));
         }
-        let mut body = self.statment().or_else(|e| {
+        let mut body = self.statment().map_err(|e| {
             errors.extend(e);
-            Err(errors.clone())
+            errors.clone()
         })?;
-        if errors.len() == 0 {
+        if errors.is_empty() {
             if let Some(increment) = increment {
                 body = Stmt::Block(vec![body, Stmt::Expression(increment)]);
             }
 
-            let condition = condition.unwrap_or_else(|| Expr::Literal(Value::Boolean(true)));
+            let condition = condition.unwrap_or(Expr::Literal {
+                value: Value::Boolean(true),
+                token: for_token,
+            });
             body = Stmt::While {
                 condition,
                 body: Box::new(body),
@@ -610,7 +1682,28 @@ impl Display for ParsingErrorType {
 #[derive(Debug,Clone)]
 pub struct ParsingError {
     pub error_type: ParsingErrorType,
+    /// Code of the message, see [`messages`].
+    pub code: &'static str,
     pub message: String,
+    /// Advice added to the message, like a keyword the statement may have
+    /// meant.
+    pub hint: Option<String>,
     pub line: usize,
-    pub expression: Option<Expr>,
+    pub span: Span,
+    /// Where the token the parser stopped at is, which jlox reports the
+    /// error at.
+    pub found_line: usize,
+    pub found_span: Span,
 }
+
+impl Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] {}", self.line, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, " {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParsingError {}
@@ -0,0 +1,125 @@
+use crate::interpreter::Interpreter;
+use libloading::Library;
+use std::fmt::Display;
+use std::path::Path;
+
+/// Bumped whenever [`PluginDeclaration`] or the way plugins are called
+/// changes. Plugins built against another version are refused.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Version of the rlox crate doing the loading. Plugins get `&mut
+/// Interpreter`, whose layout isn't stable, so they also have to be built
+/// against the same rlox release (and compiler).
+pub const RLOX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Name of the static [`declare_plugin!`](crate::declare_plugin) exports.
+const DECLARATION_SYMBOL: &[u8] = b"rlox_plugin_declaration\0";
+
+/// What a plugin exports. `abi_version` comes first so it can be read even
+/// when the rest of the layout changed.
+#[repr(C)]
+pub struct PluginDeclaration {
+    pub abi_version: u32,
+    pub rlox_version: &'static str,
+    pub register: fn(&mut Interpreter),
+}
+
+/// Exports a plugin registration function from a `cdylib` crate, so it can
+/// be loaded with `rlox --plugin=<path>` or [`Interpreter::load_plugin`].
+///
+/// ```ignore
+/// fn register(interpreter: &mut rlox::interpreter::Interpreter) {
+///     interpreter.define_native("double", 1, |_, arguments| { /* ... */ });
+/// }
+///
+/// rlox::declare_plugin!(register);
+/// ```
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static rlox_plugin_declaration: $crate::plugin::PluginDeclaration =
+            $crate::plugin::PluginDeclaration {
+                abi_version: $crate::plugin::PLUGIN_ABI_VERSION,
+                rlox_version: $crate::plugin::RLOX_VERSION,
+                register: $register,
+            };
+    };
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    Load(libloading::Error),
+    /// The library doesn't export a plugin declaration.
+    NotAPlugin,
+    AbiMismatch {
+        expected: u32,
+        found: u32,
+    },
+    VersionMismatch {
+        expected: &'static str,
+        found: String,
+    },
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginError::Load(e) => write!(f, "{e}"),
+            PluginError::NotAPlugin => write!(f, "Not an rlox plugin, use declare_plugin!"),
+            PluginError::AbiMismatch { expected, found } => write!(
+                f,
+                "Plugin uses ABI version {found} but this rlox needs {expected}"
+            ),
+            PluginError::VersionMismatch { expected, found } => write!(
+                f,
+                "Plugin was built for rlox {found} but this is rlox {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PluginError::Load(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Loads a shared library made with [`declare_plugin!`](crate::declare_plugin)
+    /// and lets it define its natives. The library stays loaded until the
+    /// process exits, since the natives point into it.
+    pub fn load_plugin(&mut self, path: impl AsRef<Path>) -> Result<(), PluginError> {
+        // SAFETY: loading runs the library's initializers; the plugin is
+        // trusted as much as the script that asked for it.
+        let library = unsafe { Library::new(path.as_ref()) }.map_err(PluginError::Load)?;
+        // SAFETY: the symbol is a static of this type if the plugin was made
+        // with `declare_plugin!`, which the versions are checked against
+        // before anything but `abi_version` is read.
+        let declaration = unsafe {
+            let symbol = library
+                .get::<*const PluginDeclaration>(DECLARATION_SYMBOL)
+                .map_err(|_| PluginError::NotAPlugin)?;
+            &**symbol
+        };
+        if declaration.abi_version != PLUGIN_ABI_VERSION {
+            return Err(PluginError::AbiMismatch {
+                expected: PLUGIN_ABI_VERSION,
+                found: declaration.abi_version,
+            });
+        }
+        if declaration.rlox_version != RLOX_VERSION {
+            return Err(PluginError::VersionMismatch {
+                expected: RLOX_VERSION,
+                found: declaration.rlox_version.to_string(),
+            });
+        }
+        (declaration.register)(self);
+        std::mem::forget(library);
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! `--dump-on-error`: shows the variables and calls in progress where an
+//! uncaught runtime error was raised.
+
+use rlox::hook::Hook;
+use rlox::interpreter::{Interpreter, RuntimeError};
+use rlox::parser::Value;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// The hook taking the dump. Clones share it, so one can be given to the
+/// interpreter and the other print the dump once the error is reported.
+#[derive(Debug, Clone, Default)]
+pub struct PostMortem(Rc<RefCell<Option<String>>>);
+
+impl PostMortem {
+    pub fn new() -> Self {
+        PostMortem::default()
+    }
+
+    /// The dump of the last error, if the script failed.
+    pub fn dump(&self) -> Option<String> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Hook for PostMortem {
+    fn on_error(&mut self, interpreter: &mut Interpreter, _error: &RuntimeError) {
+        let mut dump = String::from("Call stack, innermost first:\n");
+        for frame in interpreter.call_stack().iter().rev() {
+            let _ = writeln!(dump, "  {} called at line {}", frame.function, frame.line);
+        }
+        dump.push_str("  <script>\n");
+        for (depth, scope) in interpreter.locals().enumerate() {
+            let _ = writeln!(dump, "Scope {depth}:");
+            if scope.is_empty() {
+                dump.push_str("  (empty)\n");
+            }
+            for binding in scope.sorted() {
+                let _ = writeln!(dump, "  {} = {:?}", binding.name, binding.value);
+            }
+        }
+        dump.push_str("Globals, without natives and modules:\n");
+        for binding in interpreter.globals().sorted() {
+            if !matches!(binding.value, Value::NativeFunction(_) | Value::Module(_)) {
+                let _ = writeln!(dump, "  {} = {:?}", binding.name, binding.value);
+            }
+        }
+        *self.0.borrow_mut() = Some(dump);
+    }
+}
@@ -0,0 +1,155 @@
+//! `--profile`: times each line and function of a script as it runs.
+
+use rlox::hook::{CallFrame, Hook};
+use rlox::interpreter::{Interpreter, RuntimeError};
+use rlox::parser::Stmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Lines listed in the report, slowest first.
+const REPORT_LINES: usize = 20;
+
+/// Root of every folded stack.
+const SCRIPT_FRAME: &str = "<script>";
+
+/// What a profiled run spent its time on. Each moment between two events,
+/// a statement starting or a call starting or ending, is charged to the
+/// line and the call stack it was spent in.
+#[derive(Debug)]
+struct Profile {
+    line: Option<usize>,
+    since: Instant,
+    /// Self time and number of runs of each line.
+    lines: HashMap<usize, (Duration, u64)>,
+    /// Total time and number of calls of each function.
+    functions: HashMap<String, (Duration, u64)>,
+    /// Self time of each call stack, joined with `;`.
+    stacks: HashMap<String, Duration>,
+    /// Start of each call in progress.
+    calls: Vec<Instant>,
+}
+
+impl Profile {
+    /// Charges the time since the last event. Time before the first
+    /// statement, spent parsing, isn't charged.
+    fn charge(&mut self, interpreter: &Interpreter) {
+        let now = Instant::now();
+        let elapsed = now - self.since;
+        self.since = now;
+        let Some(line) = self.line else {
+            return;
+        };
+        self.lines.entry(line).or_default().0 += elapsed;
+        let mut stack = SCRIPT_FRAME.to_string();
+        for frame in interpreter.call_stack() {
+            stack.push(';');
+            stack.push_str(&frame.function);
+        }
+        *self.stacks.entry(stack).or_default() += elapsed;
+    }
+}
+
+/// The hook collecting a profile. Clones share it, so one can be given to
+/// the interpreter and the other report on the run.
+#[derive(Debug, Clone)]
+pub struct Profiler(Rc<RefCell<Profile>>);
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler(Rc::new(RefCell::new(Profile {
+            line: None,
+            since: Instant::now(),
+            lines: HashMap::new(),
+            functions: HashMap::new(),
+            stacks: HashMap::new(),
+            calls: Vec::new(),
+        })))
+    }
+
+    /// Charges the time since the last statement, once the script is done.
+    pub fn finish(&self, interpreter: &Interpreter) {
+        let mut profile = self.0.borrow_mut();
+        profile.charge(interpreter);
+        profile.line = None;
+    }
+
+    /// Functions by total time and the slowest lines by self time.
+    pub fn report(&self, origin: &str) -> String {
+        let profile = self.0.borrow();
+        let mut report = String::new();
+        let mut functions: Vec<_> = profile.functions.iter().collect();
+        functions.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        if !functions.is_empty() {
+            let _ = writeln!(report, "{:<24} {:>8} {:>12}", "function", "calls", "total");
+        }
+        for (name, (total, calls)) in functions {
+            let _ = writeln!(
+                report,
+                "{name:<24} {calls:>8} {:>12}",
+                format!("{total:.2?}")
+            );
+        }
+        let mut lines: Vec<_> = profile.lines.iter().collect();
+        lines.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        if !report.is_empty() {
+            report.push('\n');
+        }
+        let _ = writeln!(report, "{:<24} {:>8} {:>12}", "line", "runs", "self");
+        for (line, (time, runs)) in lines.into_iter().take(REPORT_LINES) {
+            let location = format!("{origin}:{line}");
+            let _ = writeln!(
+                report,
+                "{location:<24} {runs:>8} {:>12}",
+                format!("{time:.2?}")
+            );
+        }
+        report
+    }
+
+    /// Self time of each call stack in microseconds, one `a;b;c 123` line
+    /// per stack, the input format of flamegraph tools.
+    pub fn folded_stacks(&self) -> String {
+        let profile = self.0.borrow();
+        let mut stacks: Vec<_> = profile.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{stack} {}\n", time.as_micros()))
+            .collect()
+    }
+}
+
+impl Hook for Profiler {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        _stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        let mut profile = self.0.borrow_mut();
+        profile.charge(interpreter);
+        profile.line = Some(line);
+        profile.lines.entry(line).or_default().1 += 1;
+        Ok(())
+    }
+
+    fn enter_call(&mut self, interpreter: &mut Interpreter, _frame: &CallFrame) {
+        let mut profile = self.0.borrow_mut();
+        profile.charge(interpreter);
+        profile.calls.push(Instant::now());
+    }
+
+    fn exit_call(&mut self, interpreter: &mut Interpreter, frame: &CallFrame) {
+        let mut profile = self.0.borrow_mut();
+        profile.charge(interpreter);
+        let Some(start) = profile.calls.pop() else {
+            return;
+        };
+        let function = profile.functions.entry(frame.function.clone()).or_default();
+        function.0 += start.elapsed();
+        function.1 += 1;
+    }
+}
@@ -0,0 +1,139 @@
+use rlox::scanner::{Scanner, TokenType};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, Helper};
+use std::borrow::Cow;
+use std::env;
+use std::path::PathBuf;
+
+const HISTORY_FILE_ENV: &str = "RLOX_HISTORY";
+const HISTORY_FILE_NAME: &str = ".rlox_history";
+const HISTORY_SIZE_ENV: &str = "RLOX_HISTORY_SIZE";
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+const RC_FILE_NAME: &str = ".rloxrc";
+
+const KEYWORD_COLOR: &str = "\x1b[1;35m";
+const STRING_COLOR: &str = "\x1b[32m";
+const NUMBER_COLOR: &str = "\x1b[33m";
+const COMMENT_COLOR: &str = "\x1b[90m";
+const HINT_COLOR: &str = "\x1b[90m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Location of the prompt history file: `$RLOX_HISTORY` if set,
+/// otherwise `~/.rlox_history`.
+pub fn history_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(HISTORY_FILE_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    home_dir().map(|home| home.join(HISTORY_FILE_NAME))
+}
+
+/// Startup file run before the first prompt, `~/.rloxrc`.
+pub fn rc_path() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(RC_FILE_NAME))
+}
+
+/// Line editor settings. Ctrl-R searches the history backwards and Ctrl-S
+/// forwards; `$RLOX_HISTORY_SIZE` caps how many entries are kept.
+pub fn editor_config() -> Config {
+    let history_size = env::var(HISTORY_SIZE_ENV)
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE);
+    // With bracketed paste a multi-line paste lands in the buffer as a whole
+    // instead of being submitted at every newline, so it runs as one unit.
+    Config::builder()
+        .max_history_size(history_size)
+        .expect("history size is valid")
+        .history_ignore_dups(true)
+        .expect("history_ignore_dups never fails")
+        .history_ignore_space(true)
+        .bracketed_paste(true)
+        .build()
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Highlights input and previews the latest history entry starting with it.
+#[derive(Default)]
+pub struct LoxHelper {
+    hinter: HistoryHinter,
+}
+
+impl LoxHelper {
+    pub fn new() -> Self {
+        LoxHelper::default()
+    }
+
+    fn token_color(token_type: &TokenType) -> Option<&'static str> {
+        match token_type {
+            TokenType::String => Some(STRING_COLOR),
+            TokenType::Number => Some(NUMBER_COLOR),
+            TokenType::Comment => Some(COMMENT_COLOR),
+            t if t.is_keyword() => Some(KEYWORD_COLOR),
+            _ => None,
+        }
+    }
+}
+
+impl Highlighter for LoxHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let mut scanner = Scanner::new_lenient(line);
+        scanner.scan_tokens();
+        let colored_spans: Vec<_> = scanner
+            .tokens
+            .iter()
+            .filter_map(|t| Self::token_color(&t.token_type).map(|color| (t.span, color)))
+            .collect();
+        if colored_spans.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        let mut highlighted = String::with_capacity(line.len() * 2);
+        let mut spans = colored_spans.iter().peekable();
+        for (i, c) in line.chars().enumerate() {
+            if let Some((span, color)) = spans.peek() {
+                if span.start == i {
+                    highlighted.push_str(color);
+                }
+            }
+            highlighted.push(c);
+            if let Some((span, _)) = spans.peek() {
+                if span.end == i + 1 {
+                    highlighted.push_str(RESET_COLOR);
+                    spans.next();
+                }
+            }
+        }
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{HINT_COLOR}{hint}{RESET_COLOR}"))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
@@ -0,0 +1,460 @@
+use crate::diagnostic::{Diagnostic, Warning};
+use crate::messages;
+use crate::parser::{Expr, FunctionDecl, Stmt, Value};
+use crate::scanner::TokenInfo;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct LocalVariable {
+    name: TokenInfo,
+    used: bool,
+    /// Whether the variable was declared with a value or may have been
+    /// assigned since.
+    assigned: bool,
+    /// Where the variable was last given a value that hasn't been read
+    /// yet, if that is known for sure.
+    unread_assignment: Option<TokenInfo>,
+    /// Assignments whose value was overwritten before being read, reported
+    /// at the end of the scope unless the variable is unused altogether.
+    overwritten: Vec<TokenInfo>,
+    /// How many branches or loop bodies the declaration is inside.
+    branch_depth: usize,
+}
+
+/// Static pass over the parsed program that runs before the interpreter and
+/// reports problems visible without executing anything.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    scopes: Vec<HashMap<String, LocalVariable>>,
+    diagnostics: Vec<Diagnostic>,
+    /// How many branches or loop bodies the code being resolved is inside.
+    /// Assignments in a branch the variable wasn't declared in may not run,
+    /// or run again, so they aren't checked for being read.
+    branch_depth: usize,
+    /// How many function bodies the code being resolved is inside.
+    function_depth: usize,
+    /// How many class bodies the code being resolved is inside.
+    class_depth: usize,
+    /// Whether the innermost function being resolved is an `init` method.
+    in_initializer: bool,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    pub fn resolve(mut self, statments: &[Stmt]) -> Vec<Diagnostic> {
+        for stmt in statments {
+            self.resolve_stmt(stmt);
+        }
+        self.diagnostics
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(e) | Stmt::Print(e) => self.resolve_expr(e),
+            Stmt::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.declare(name, initializer.is_some());
+                if initializer.is_some() {
+                    self.assign(name);
+                }
+            }
+            Stmt::Block(statments) => {
+                self.begin_scope();
+                for stmt in statments {
+                    self.resolve_stmt(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                if let Some((value, token)) = literal(condition) {
+                    self.diagnostics.push(Diagnostic::warning(
+                        Warning::ConstantCondition,
+                        token,
+                        format!("Condition is always {}.", value.is_truthy()),
+                    ));
+                }
+                self.branch_depth += 1;
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+                self.branch_depth -= 1;
+            }
+            Stmt::While { condition, body } => {
+                self.resolve_expr(condition);
+                if let Some((value, token)) = literal(condition).filter(|(v, _)| !v.is_truthy()) {
+                    self.diagnostics.push(Diagnostic::warning(
+                        Warning::ConstantCondition,
+                        token,
+                        format!("Condition is always {value}, the loop never runs."),
+                    ));
+                }
+                self.branch_depth += 1;
+                self.resolve_stmt(body);
+                self.branch_depth -= 1;
+            }
+            Stmt::Import(name) => self.declare(name, true),
+            Stmt::Function(function) => {
+                self.declare(&function.name, true);
+                self.resolve_function(function, false);
+            }
+            Stmt::Class { name, methods } => {
+                self.declare(name, true);
+                // Methods are bound in a scope of their own holding `this`.
+                self.begin_scope();
+                self.declare_this(name);
+                self.class_depth += 1;
+                for method in methods {
+                    self.resolve_function(method, method.name.lexeme == "init");
+                }
+                self.class_depth -= 1;
+                self.end_scope();
+            }
+            Stmt::Return { keyword, value } => {
+                if self.function_depth == 0 {
+                    let message = messages::RETURN_AT_TOP_LEVEL.text();
+                    self.diagnostics.push(Diagnostic::resolve_error(keyword, message));
+                }
+                if let Some(value) = value {
+                    if self.in_initializer {
+                        let message = messages::RETURN_FROM_INITIALIZER.text();
+                        self.diagnostics.push(Diagnostic::resolve_error(keyword, message));
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+        }
+    }
+
+    /// The body runs whenever the function is called, maybe never or many
+    /// times, so it counts as a branch.
+    fn resolve_function(&mut self, function: &FunctionDecl, initializer: bool) {
+        self.begin_scope();
+        self.branch_depth += 1;
+        self.function_depth += 1;
+        let in_initializer = std::mem::replace(&mut self.in_initializer, initializer);
+        for param in &function.params {
+            self.declare(param, true);
+            // Callers decide the parameters, so unused ones aren't reported.
+            self.mark_used(param);
+        }
+        for stmt in &function.body {
+            self.resolve_stmt(stmt);
+        }
+        self.in_initializer = in_initializer;
+        self.function_depth -= 1;
+        self.branch_depth -= 1;
+        self.end_scope();
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.branch_depth += 1;
+                self.resolve_expr(right);
+                self.branch_depth -= 1;
+            }
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Grouping(e) => self.resolve_expr(e),
+            Expr::Literal { .. } => {}
+            Expr::Variable { name, distance } => {
+                distance.set(self.distance(name));
+                self.mark_used(name);
+            }
+            Expr::Assign {
+                name,
+                value,
+                distance,
+            } => {
+                self.resolve_expr(value);
+                distance.set(self.distance(name));
+                if matches!(
+                    value.as_ref(),
+                    Expr::Variable { name: source, .. } if source.lexeme == name.lexeme
+                ) {
+                    self.diagnostics.push(Diagnostic::warning(
+                        Warning::SelfAssignment,
+                        name,
+                        format!("'{}' is assigned to itself.", name.lexeme),
+                    )
+                    .with_help("Remove the assignment, it has no effect."));
+                }
+                self.assign(name);
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::This { keyword, distance } => {
+                if self.class_depth == 0 {
+                    let message = messages::THIS_OUTSIDE_CLASS.text();
+                    self.diagnostics.push(Diagnostic::resolve_error(keyword, message));
+                }
+                distance.set(self.distance(keyword));
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let mut variables: Vec<_> = scope.into_values().collect();
+        variables.sort_by_key(|v| v.name.span.start);
+        for variable in variables {
+            if !variable.used {
+                self.diagnostics.push(Diagnostic::warning(
+                    Warning::Unused,
+                    &variable.name,
+                    format!("Unused variable '{}'.", variable.name.lexeme),
+                ));
+                continue;
+            }
+            let unread = variable.overwritten.iter().chain(&variable.unread_assignment);
+            for assignment in unread {
+                let mut warning = Diagnostic::warning(
+                    Warning::UnusedAssignment,
+                    assignment,
+                    format!("Value assigned to '{}' is never read.", assignment.lexeme),
+                );
+                if assignment.span != variable.name.span {
+                    warning = warning.with_label(variable.name.span, "declared here");
+                }
+                self.diagnostics.push(warning);
+            }
+        }
+    }
+
+    /// Globals are not tracked: later code (or REPL input) may still use them.
+    fn declare(&mut self, name: &TokenInfo, assigned: bool) {
+        let branch_depth = self.branch_depth;
+        let Some((scope, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        if let Some(previous) = scope.get(&name.lexeme) {
+            self.diagnostics.push(
+                Diagnostic::resolve_error(name, messages::ALREADY_DECLARED.text())
+                    .with_label(previous.name.span, "first declared here"),
+            );
+        } else if let Some(shadowed) = enclosing.iter().rev().find_map(|s| s.get(&name.lexeme)) {
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    Warning::Shadowing,
+                    name,
+                    format!("'{}' shadows a variable of an enclosing scope.", name.lexeme),
+                )
+                .with_label(shadowed.name.span, "shadowed variable declared here"),
+            );
+        }
+        scope.insert(
+            name.lexeme.clone(),
+            LocalVariable {
+                name: name.clone(),
+                used: false,
+                assigned,
+                unread_assignment: None,
+                overwritten: Vec::new(),
+                branch_depth,
+            },
+        );
+    }
+
+    /// Declares `this` in the innermost scope, for the methods of the class
+    /// called `class`. It is never reported as unused.
+    fn declare_this(&mut self, class: &TokenInfo) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        let name = TokenInfo {
+            lexeme: "this".to_string(),
+            ..class.clone()
+        };
+        scope.insert(
+            name.lexeme.clone(),
+            LocalVariable {
+                name,
+                used: true,
+                assigned: true,
+                unread_assignment: None,
+                overwritten: Vec::new(),
+                branch_depth: self.branch_depth,
+            },
+        );
+    }
+
+    fn mark_used(&mut self, name: &TokenInfo) {
+        let Some(variable) = self.lookup(name) else {
+            return;
+        };
+        variable.used = true;
+        variable.unread_assignment = None;
+        if variable.assigned {
+            return;
+        }
+        let declaration = variable.name.span;
+        self.diagnostics.push(
+            Diagnostic::warning(
+                Warning::Uninitialized,
+                name,
+                format!("Variable '{}' used before being assigned.", name.lexeme),
+            )
+            .with_label(declaration, "declared without a value")
+            .with_help("Give it a value where it is declared."),
+        );
+    }
+
+    /// Records a value given to a variable, keeping the previous one if it
+    /// was never read.
+    fn assign(&mut self, name: &TokenInfo) {
+        let branch_depth = self.branch_depth;
+        let Some(variable) = self.lookup(name) else {
+            return;
+        };
+        variable.assigned = true;
+        let known = variable.branch_depth == branch_depth;
+        let previous = std::mem::replace(
+            &mut variable.unread_assignment,
+            known.then(|| name.clone()),
+        );
+        if let Some(previous) = previous.filter(|_| known) {
+            variable.overwritten.push(previous);
+        }
+    }
+
+    /// How many scopes out from the innermost one the variable `name` is
+    /// declared in, or the number of scopes for a global or a variable of
+    /// an enclosing module, which are found by name past them.
+    fn distance(&self, name: &TokenInfo) -> usize {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme))
+            .unwrap_or(self.scopes.len())
+    }
+
+    fn lookup(&mut self, name: &TokenInfo) -> Option<&mut LocalVariable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name.lexeme))
+    }
+}
+
+/// The value of a condition that is a literal, maybe in parentheses.
+fn literal(expr: &Expr) -> Option<(&Value, &TokenInfo)> {
+    match expr {
+        Expr::Literal { value, token } => Some((value, token)),
+        Expr::Grouping(e) => literal(e),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn messages(source: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let statments = Parser::new(scanner.tokens).parse().unwrap();
+        let diagnostics = Resolver::new().resolve(&statments);
+        diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn unused_locals_but_not_globals() {
+        assert_eq!(messages("var a = 1; { var b = 2; }"), ["Unused variable 'b'."]);
+    }
+
+    #[test]
+    fn unused_parameters_are_fine() {
+        assert!(messages("fun f(a) { return 1; }").is_empty());
+    }
+
+    #[test]
+    fn overwritten_assignments() {
+        assert_eq!(
+            messages("{ var a = 1; a = 2; print a; }"),
+            ["Value assigned to 'a' is never read."]
+        );
+    }
+
+    #[test]
+    fn constant_conditions() {
+        assert_eq!(messages("if (true) print 1;"), ["Condition is always true."]);
+        assert!(messages("while (true) print 1;").is_empty());
+    }
+
+    #[test]
+    fn shadowing_and_redeclaring() {
+        assert_eq!(
+            messages("{ var a = 1; { var a = 2; print a; } print a; }"),
+            ["'a' shadows a variable of an enclosing scope."]
+        );
+        assert_eq!(
+            messages("{ var a = 1; var a = 2; print a; }"),
+            ["Already a variable with this name in this scope."]
+        );
+    }
+
+    #[test]
+    fn closures_see_the_scope_they_were_declared_in() {
+        // The `a` declared after `show` is a different variable from the
+        // one it prints.
+        let source = r#"var a = "global"; { fun show() { print a; } show(); var a = "block"; }"#;
+        assert_eq!(messages(source), ["Unused variable 'a'."]);
+    }
+
+    #[test]
+    fn return_outside_functions() {
+        assert_eq!(messages("return 1;"), ["Can't return from top-level code."]);
+        assert_eq!(
+            messages("var c = true; { if (c) return; } while (c) return;"),
+            [
+                "Can't return from top-level code.",
+                "Can't return from top-level code."
+            ]
+        );
+        assert!(messages("fun f() { { return 1; } }").is_empty());
+    }
+
+    #[test]
+    fn misplaced_this_and_initializer_return() {
+        assert_eq!(messages("print this;"), ["Can't use 'this' outside of a class."]);
+        assert_eq!(
+            messages("class A { init() { return 1; } }"),
+            ["Can't return a value from an initializer."]
+        );
+        assert!(messages("class A { init() { return; } get() { return this; } }").is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+use crate::dialect::Dialect;
+use crate::diagnostic::{Diagnostic, WarningConfig};
+use crate::interpreter::{CapturedOutput, Interpreter, RuntimeError};
+use crate::parser::{Expr, Parser, ParsingError, Stmt, Value};
+use crate::resolver::Resolver;
+use crate::scanner::{Scanner, TokenInfo};
+use std::collections::HashMap;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What running a piece of source produced.
+#[derive(Debug, Default)]
+pub struct RunResult {
+    /// Errors and warnings from every phase, in the order they were found.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Value of the input when it was a single expression.
+    pub value: Option<Value>,
+    /// What `print` wrote, when run with `run_captured`.
+    pub stdout: String,
+    /// Wall-clock duration of each phase that ran.
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+impl RunResult {
+    pub fn success(&self) -> bool {
+        !self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    fn measure<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("phase", phase).entered();
+        let stopwatch = Stopwatch::start();
+        let result = f();
+        self.timings.push((phase, stopwatch.elapsed()));
+        result
+    }
+}
+
+/// Times a phase. `Instant::now` panics on wasm32-unknown-unknown, which has
+/// no clock, so phases there take no time.
+struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    fn start() -> Self {
+        if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+            Stopwatch(None)
+        } else {
+            Stopwatch(Some(Instant::now()))
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+/// The standard library written in Lox, as file names and sources, in the
+/// order every [`Runner::new`] runs them.
+pub const PRELUDE: &[(&str, &str)] = &[
+    ("core.lox", include_str!("stdlib/core.lox")),
+    ("list.lox", include_str!("stdlib/list.lox")),
+    ("functional.lox", include_str!("stdlib/functional.lox")),
+    ("assert.lox", include_str!("stdlib/assert.lox")),
+];
+
+/// Runs source through scanning, parsing, resolving and interpreting,
+/// collecting diagnostics instead of printing them. The interpreter is kept
+/// between runs so later sources see earlier definitions.
+#[derive(Debug)]
+pub struct Runner {
+    interpreter: Interpreter,
+    warnings: WarningConfig,
+    /// Whether a runtime error only stops the top-level statement raising
+    /// it.
+    keep_going: bool,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner::new()
+    }
+}
+
+impl Runner {
+    /// A runner whose globals start with the [`PRELUDE`] definitions.
+    pub fn new() -> Self {
+        let mut runner = Runner::without_prelude();
+        for (name, source) in PRELUDE {
+            let result = runner.load_prelude(source);
+            debug_assert!(result.success(), "{name}: {:?}", result.diagnostics);
+        }
+        runner
+    }
+
+    /// A runner with only the builtin natives defined.
+    pub fn without_prelude() -> Self {
+        Runner::with_interpreter(Interpreter::new())
+    }
+
+    /// A runner behaving like jlox: the [`Dialect::jlox`] dialect, `clock`
+    /// as the only global and no warnings.
+    pub fn jlox() -> Self {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_dialect(Dialect::jlox());
+        interpreter.retain_globals(|name| name == "clock");
+        let mut runner = Runner::with_interpreter(interpreter);
+        runner.warnings.disable_all();
+        runner
+    }
+
+    /// Runs host-supplied prelude code in the global scope. Its definitions
+    /// replace earlier ones with the same name, so this can override
+    /// helpers from [`PRELUDE`]. Warnings aren't reported for it.
+    pub fn load_prelude(&mut self, source: &str) -> RunResult {
+        let warnings = std::mem::take(&mut self.warnings);
+        let result = self.run(source);
+        self.warnings = warnings;
+        result
+    }
+
+    /// Runs code in an existing interpreter, keeping its globals and
+    /// natives.
+    pub fn with_interpreter(interpreter: Interpreter) -> Self {
+        Runner {
+            interpreter,
+            warnings: WarningConfig::default(),
+            keep_going: false,
+        }
+    }
+
+    pub fn into_interpreter(self) -> Interpreter {
+        self.interpreter
+    }
+
+    pub fn set_warnings(&mut self, warnings: WarningConfig) {
+        self.warnings = warnings;
+    }
+
+    /// With `keep_going`, a runtime error stops only the top-level statement
+    /// raising it: runs report it and carry on with the next statement, see
+    /// [`Interpreter::interpret_keep_going`].
+    pub fn set_keep_going(&mut self, keep_going: bool) {
+        self.keep_going = keep_going;
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    pub fn run(&mut self, source: &str) -> RunResult {
+        let mut result = RunResult::default();
+        if let Some(tokens) = self.scan(source, &mut result) {
+            self.execute(tokens, &mut result);
+        }
+        result
+    }
+
+    /// Like `run`, awaiting async natives.
+    #[cfg(feature = "async")]
+    pub async fn run_async(&mut self, source: &str) -> RunResult {
+        let mut result = RunResult::default();
+        let statments =
+            self.scan(source, &mut result).and_then(|tokens| self.analyze(tokens, &mut result));
+        let Some(statments) = statments else {
+            return result;
+        };
+        let stopwatch = Stopwatch::start();
+        let outcome = self.interpreter.interpret_async(statments).await;
+        result.timings.push(("interpret", stopwatch.elapsed()));
+        if let Err(e) = outcome {
+            result.diagnostics.push(runtime_diagnostic(&e));
+        }
+        result
+    }
+
+    /// Like `run`, but input that is a single expression is evaluated and
+    /// its value returned, the way a REPL shows results.
+    pub fn run_interactive(&mut self, source: &str) -> RunResult {
+        let mut result = RunResult::default();
+        let Some(tokens) = self.scan(source, &mut result) else {
+            return result;
+        };
+        match Parser::new(tokens.clone()).parse_expression() {
+            Ok(expr) => self.evaluate_into(&expr, &mut result),
+            Err(_) => self.execute(tokens, &mut result),
+        }
+        result
+    }
+
+    /// Like `run_interactive`, with `print` output collected into
+    /// `RunResult::stdout` instead of written out.
+    pub fn run_captured(&mut self, source: &str) -> RunResult {
+        let captured = CapturedOutput::default();
+        let output = self.interpreter.replace_output(Box::new(captured.clone()));
+        let mut result = self.run_interactive(source);
+        self.interpreter.replace_output(output);
+        result.stdout = captured.contents();
+        result
+    }
+
+    /// Evaluates source that must be a single expression.
+    pub fn evaluate(&mut self, source: &str) -> RunResult {
+        let mut result = RunResult::default();
+        let Some(tokens) = self.scan(source, &mut result) else {
+            return result;
+        };
+        match result.measure("parse", || Parser::new(tokens).parse_expression()) {
+            Ok(expr) => self.evaluate_into(&expr, &mut result),
+            Err(e) => result.diagnostics.push(Diagnostic::from(&e)),
+        }
+        result
+    }
+
+    /// Like `evaluate`, with `bindings` as variables of the expression. See
+    /// [`Interpreter::evaluate_with`].
+    pub fn evaluate_with(&mut self, source: &str, bindings: &HashMap<String, Value>) -> RunResult {
+        let mut result = RunResult::default();
+        let Some(tokens) = self.scan(source, &mut result) else {
+            return result;
+        };
+        let expr = match result.measure("parse", || Parser::new(tokens).parse_expression()) {
+            Ok(expr) => expr,
+            Err(e) => {
+                result.diagnostics.push(Diagnostic::from(&e));
+                return result;
+            }
+        };
+        let interpreter = &mut self.interpreter;
+        match result.measure("interpret", || interpreter.evaluate_with(&expr, bindings)) {
+            Ok(value) => result.value = Some(value),
+            Err(e) => result.diagnostics.push(runtime_diagnostic(&e)),
+        }
+        result
+    }
+
+    /// Scans, parses and resolves without running anything.
+    pub fn check(&self, source: &str) -> RunResult {
+        self.parse(source).1
+    }
+
+    /// Returns the program, or `None` if it has errors, along with the
+    /// diagnostics found on the way.
+    pub fn parse(&self, source: &str) -> (Option<Vec<Stmt>>, RunResult) {
+        let mut result = RunResult::default();
+        let statments =
+            self.scan(source, &mut result).and_then(|tokens| self.analyze(tokens, &mut result));
+        (statments, result)
+    }
+
+    fn execute(&mut self, tokens: Vec<TokenInfo>, result: &mut RunResult) {
+        let Some(statments) = self.analyze(tokens, result) else {
+            return;
+        };
+        let interpreter = &mut self.interpreter;
+        if self.keep_going {
+            let errors = result.measure("interpret", || interpreter.interpret_keep_going(statments));
+            result.diagnostics.extend(errors.iter().map(runtime_diagnostic));
+        } else if let Err(e) = result.measure("interpret", || interpreter.interpret(statments)) {
+            result.diagnostics.push(runtime_diagnostic(&e));
+        }
+    }
+
+    fn evaluate_into(&mut self, expr: &Expr, result: &mut RunResult) {
+        let interpreter = &mut self.interpreter;
+        match result.measure("interpret", || interpreter.evaluate(expr)) {
+            Ok(value) => result.value = Some(value),
+            Err(e) => result.diagnostics.push(runtime_diagnostic(&e)),
+        }
+    }
+
+    /// Parses and resolves the tokens. Returns the program if it is fit to run.
+    fn analyze(&self, tokens: Vec<TokenInfo>, result: &mut RunResult) -> Option<Vec<Stmt>> {
+        let mut parser = Parser::new(tokens);
+        let statments = match result.measure("parse", || parser.parse()) {
+            Ok(statments) => statments,
+            Err(errors) => {
+                result
+                    .diagnostics
+                    .extend(errors.iter().map(|e| self.parse_diagnostic(e)));
+                return None;
+            }
+        };
+        let diagnostics = result.measure("resolve", || Resolver::new().resolve(&statments));
+        let diagnostics = self.warnings.apply(diagnostics);
+        let has_errors = diagnostics.iter().any(Diagnostic::is_error);
+        result.diagnostics.extend(diagnostics);
+        if has_errors {
+            None
+        } else {
+            Some(statments)
+        }
+    }
+
+    /// Returns the tokens of `source`, or `None` if it has scan errors. Scanning
+    /// always consumes the whole source, so a failed scan still gets its
+    /// remaining tokens parsed for error reporting.
+    fn scan(&self, source: &str, result: &mut RunResult) -> Option<Vec<TokenInfo>> {
+        let mut scanner = Scanner::with_dialect(source, self.interpreter.dialect());
+        result.measure("scan", || scanner.scan_tokens());
+        if scanner.errors.is_empty() {
+            return Some(scanner.tokens);
+        }
+        result
+            .diagnostics
+            .extend(scanner.errors.iter().map(Diagnostic::from));
+        if let Err(errors) = Parser::new(scanner.tokens).parse() {
+            result
+                .diagnostics
+                .extend(errors.iter().map(|e| self.parse_diagnostic(e)));
+        }
+        None
+    }
+
+    /// jlox reports parse errors at the token it stopped at, not after the
+    /// last one that fit.
+    fn parse_diagnostic(&self, error: &ParsingError) -> Diagnostic {
+        let mut diagnostic = Diagnostic::from(error);
+        if self.interpreter.dialect().jlox {
+            diagnostic.line = Some(error.found_line);
+            diagnostic.span = Some(error.found_span);
+            // Nor does it hint at misspelled keywords.
+            diagnostic.message = error.message.clone();
+        }
+        diagnostic
+    }
+}
+
+/// The diagnostic for a runtime error that stopped a run, which is also a
+/// `tracing` event with the `tracing` feature.
+fn runtime_diagnostic(error: &RuntimeError) -> Diagnostic {
+    #[cfg(feature = "tracing")]
+    tracing::info!(code = error.code, line = error.line, "runtime error: {}", error.message);
+    Diagnostic::from(error)
+}
+
+/// Scans and runs `source` in a fresh interpreter.
+pub fn run(source: &str) -> RunResult {
+    Runner::new().run(source)
+}
+
+/// Calls `f` on a new thread with a stack of `stack_size` bytes and returns
+/// what it returns. A runner can't move between threads, so `f` makes its
+/// own, and lets calls nest deeper with
+/// [`max_call_depth_for`](crate::interpreter::max_call_depth_for) of the
+/// same size. A panic in `f` carries on on the calling thread.
+pub fn with_stack_size<T: Send>(stack_size: usize, f: impl FnOnce() -> T + Send) -> io::Result<T> {
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, f)?;
+        Ok(handle
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+    })
+}
+
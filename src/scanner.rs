@@ -1,5 +1,13 @@
+use crate::dialect::Dialect;
+use crate::messages::{self, Text};
 use std::collections::HashMap;
 
+/// Every reserved word, in alphabetical order.
+pub const KEYWORDS: [&str; 17] = [
+    "and", "class", "else", "false", "for", "fun", "if", "import", "nil", "or", "print", "return",
+    "super", "this", "true", "var", "while",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Single-character tokens
@@ -38,6 +46,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
@@ -48,29 +57,84 @@ pub enum TokenType {
     Var,
     While,
 
+    // Only produced by a lenient scanner.
+    Comment,
+
     EOF,
 }
 
+impl TokenType {
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::And
+                | TokenType::Class
+                | TokenType::Else
+                | TokenType::False
+                | TokenType::Fun
+                | TokenType::For
+                | TokenType::If
+                | TokenType::Import
+                | TokenType::Nil
+                | TokenType::Or
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Super
+                | TokenType::This
+                | TokenType::True
+                | TokenType::Var
+                | TokenType::While
+        )
+    }
+}
+
+/// Range of characters in the source that a token was scanned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug,Clone)]
 pub struct TokenInfo {
     pub token_type: TokenType,
     pub line: usize,
     pub lexeme: String,
     pub number: Option<f64>,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    /// Code of the message, see [`messages`].
+    pub code: &'static str,
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScanError {}
+
 pub struct Scanner {
     source: Vec<char>,
     pub tokens: Vec<TokenInfo>,
+    pub errors: Vec<ScanError>,
     start: usize,
     current: usize,
     line: usize,
     reserved_words: HashMap<String, TokenType>,
+    lenient: bool,
 }
 
 impl Scanner {
-    pub fn new(source: &String) -> Self {
-        let mut reserved_words = HashMap::with_capacity(16);
+    pub fn new(source: &str) -> Self {
+        let mut reserved_words = HashMap::with_capacity(KEYWORDS.len());
         reserved_words.insert("and".to_string(), TokenType::And);
         reserved_words.insert("class".to_string(), TokenType::Class);
         reserved_words.insert("else".to_string(), TokenType::Else);
@@ -78,6 +142,7 @@ impl Scanner {
         reserved_words.insert("fun".to_string(), TokenType::Fun);
         reserved_words.insert("for".to_string(), TokenType::For);
         reserved_words.insert("if".to_string(), TokenType::If);
+        reserved_words.insert("import".to_string(), TokenType::Import);
         reserved_words.insert("nil".to_string(), TokenType::Nil);
         reserved_words.insert("or".to_string(), TokenType::Or);
         reserved_words.insert("print".to_string(), TokenType::Print);
@@ -90,10 +155,33 @@ impl Scanner {
         Scanner {
             source: source.chars().collect(),
             tokens: Vec::default(),
+            errors: Vec::default(),
             start: 0,
             current: 0,
             line: 1,
             reserved_words,
+            lenient: false,
+        }
+    }
+
+    /// Scanner for a script of `dialect`. Under jlox, `import`, which rlox
+    /// reserves on top of Lox, is an identifier.
+    pub fn with_dialect(source: &str, dialect: Dialect) -> Self {
+        let mut scanner = Scanner::new(source);
+        if dialect.jlox {
+            scanner
+                .reserved_words
+                .retain(|_, token| *token != TokenType::Import);
+        }
+        scanner
+    }
+
+    /// Scanner for incomplete input (e.g. a line being typed at the prompt):
+    /// it never reports errors and keeps comments as tokens.
+    pub fn new_lenient(source: &str) -> Self {
+        Scanner {
+            lenient: true,
+            ..Scanner::new(source)
         }
     }
 
@@ -102,6 +190,7 @@ impl Scanner {
             self.start = self.current;
             self.scan_token();
         }
+        self.start = self.current;
         self.add_token(TokenType::EOF,"");
     }
 
@@ -149,28 +238,41 @@ impl Scanner {
             }
             '/' => {
                 if self.match_char('/') {
-                    while self.peek() != Some('\n') {
+                    while self.peek().is_some_and(|c| c != '\n') {
                         self.advance();
                     }
+                    if self.lenient {
+                        let comment: String =
+                            self.source[self.start..self.current].iter().collect();
+                        self.add_token(TokenType::Comment, comment);
+                    }
                 } else {
                     self.add_token(TokenType::Slash,'/')
                 }
             }
             '"' => self.string(),
             c if Self::is_digit(c) => self.number(),
-            c if c.is_ascii_alphabetic() => self.identifier(),
-            c => println!("Unexpected character {:?}", c),
+            c if Self::is_alpha(c) => self.identifier(),
+            _ if self.lenient => {}
+            c => {
+                let character = format!("{c:?}");
+                self.add_error(messages::UNEXPECTED_CHARACTER.with(&[("character", &character)]))
+            }
         }
     }
 
     fn is_digit(c: char) -> bool {
-        ('0'..='9').contains(&c)
+        c.is_ascii_digit()
+    }
+
+    fn is_alpha(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn identifier(&mut self) {
         loop {
             let is_alphanumeric = if let Some(c) = self.peek() {
-                c.is_ascii_alphanumeric()
+                Self::is_alpha(c) || Self::is_digit(c)
             } else {
                 false
             };
@@ -180,7 +282,7 @@ impl Scanner {
                 break;
             }
         }
-        let identifier: String = self.source[self.start..self.current].into_iter().collect();
+        let identifier: String = self.source[self.start..self.current].iter().collect();
         let token = self
             .reserved_words
             .get(&identifier)
@@ -205,7 +307,7 @@ impl Scanner {
                 None | Some(_) => break,
             };
         }
-        let number_str: String = self.source[self.start..self.current].into_iter().collect();
+        let number_str: String = self.source[self.start..self.current].iter().collect();
         let number: f64 = number_str.parse().unwrap();
         self.add_number_token(number_str,number);
     }
@@ -214,11 +316,13 @@ impl Scanner {
         let mut s = String::new();
         loop {
             match self.peek() {
+                None if self.lenient => {
+                    self.add_token(TokenType::String, s);
+                    break;
+                }
                 None => {
-                    panic!(
-                        "Unterminated string with value {:?} at line {}",
-                        s, self.line
-                    );
+                    self.add_error(messages::UNTERMINATED_STRING.text());
+                    break;
                 }
                 Some('"') => {
                     self.advance();
@@ -235,6 +339,7 @@ impl Scanner {
             line: self.line,
             lexeme,
             number: Some(number),
+            span: self.current_span(),
         });
     }
     fn add_token(&mut self, token: TokenType, lexeme: impl std::fmt::Display) {
@@ -243,8 +348,23 @@ impl Scanner {
             line: self.line,
             lexeme: lexeme.to_string(),
             number: None,
+            span: self.current_span(),
+        });
+    }
+    fn add_error(&mut self, message: Text) {
+        self.errors.push(ScanError {
+            code: message.code,
+            message: message.text,
+            line: self.line,
+            span: self.current_span(),
         });
     }
+    fn current_span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
+        }
+    }
     fn current_char(&self) -> char {
         self.source[self.current]
     }
@@ -289,3 +409,34 @@ impl Scanner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(mut scanner: Scanner) -> Vec<TokenType> {
+        scanner.scan_tokens();
+        scanner.tokens.into_iter().map(|token| token.token_type).collect()
+    }
+
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(
+            token_types(Scanner::new("var import = nil;")),
+            [
+                TokenType::Var,
+                TokenType::Import,
+                TokenType::Equal,
+                TokenType::Nil,
+                TokenType::Semicolon,
+                TokenType::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn jlox_only_reserves_lox_keywords() {
+        let scanner = Scanner::with_dialect("import", Dialect::jlox());
+        assert_eq!(token_types(scanner), [TokenType::Identifier, TokenType::EOF]);
+    }
+}
@@ -0,0 +1,139 @@
+//! Workspaces saved by `:save-session` and restored by `:load-session`. A
+//! session is a Lox script declaring each global that can be written as
+//! Lox, so loading one is running it. Numbers, strings, booleans, nil,
+//! bytes and the lists and maps holding them are saved; natives, modules,
+//! functions and foreign objects aren't. A list or map in two globals is saved twice,
+//! and comes back as two.
+
+use rlox::interpreter::Interpreter;
+use rlox::parser::Value;
+use std::fmt::Write;
+
+const HEADER: &str = "// rlox session, saved with :save-session. Load it with :load-session.\n";
+
+/// Lists and maps nested deeper are taken for one holding itself, which
+/// can't be saved.
+const MAX_DEPTH: usize = 64;
+
+/// The session script for the globals of `interpreter`, and the names of
+/// those that couldn't be saved. Natives and modules, which every
+/// interpreter has, are left out of both.
+pub fn save(interpreter: &Interpreter) -> (String, Vec<String>) {
+    let mut script = String::from(HEADER);
+    let mut skipped = Vec::new();
+    for binding in interpreter.globals().sorted() {
+        if matches!(binding.value, Value::NativeFunction(_) | Value::Module(_)) {
+            continue;
+        }
+        match literal(&binding.value, 0) {
+            Some(literal) => {
+                let _ = writeln!(script, "var {} = {literal};", binding.name);
+            }
+            None => skipped.push(binding.name),
+        }
+    }
+    if !skipped.is_empty() {
+        let _ = writeln!(script, "// Not saved: {}", skipped.join(", "));
+    }
+    (script, skipped)
+}
+
+/// `value` as a Lox expression giving an equal value, `None` if it can't
+/// be written as one.
+fn literal(value: &Value, depth: usize) -> Option<String> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    Some(match value {
+        Value::Nil => "nil".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Number(n) if n.is_finite() => number(*n),
+        Value::String(s) => string(&s.as_str()),
+        Value::Bytes(bytes) => {
+            let hex: String = bytes
+                .as_slice()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            format!("fromHex(\"{hex}\")")
+        }
+        Value::List(list) => {
+            let items = list
+                .borrow()
+                .iter()
+                .map(|item| literal(item, depth + 1))
+                .collect::<Option<Vec<_>>>()?;
+            format!("list({})", items.join(", "))
+        }
+        Value::Map(map) => {
+            let mut arguments = Vec::new();
+            for (key, value) in map.borrow().iter() {
+                arguments.push(literal(&Value::from(key.clone()), depth + 1)?);
+                arguments.push(literal(value, depth + 1)?);
+            }
+            format!("mapOf({})", arguments.join(", "))
+        }
+        _ => return None,
+    })
+}
+
+/// Lox has no negative number literals, so those are negated positive
+/// ones.
+fn number(n: f64) -> String {
+    if n.is_sign_negative() {
+        format!("-{}", -n)
+    } else {
+        n.to_string()
+    }
+}
+
+/// Lox strings have no escapes, so double quotes are joined in with `chr`.
+fn string(s: &str) -> String {
+    let mut pieces = Vec::new();
+    for (i, part) in s.split('"').enumerate() {
+        if i > 0 {
+            pieces.push("chr(34)".to_string());
+        }
+        if !part.is_empty() {
+            pieces.push(format!("\"{part}\""));
+        }
+    }
+    match pieces.len() {
+        0 => "\"\"".to_string(),
+        1 => pieces.remove(0),
+        _ => format!("({})", pieces.join(" + ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox::Runner;
+
+    #[test]
+    fn saved_globals_come_back() {
+        let mut runner = Runner::new();
+        let source = r#"var n = -1.5; var s = "say " + chr(34) + "hi" + chr(34);
+                        var l = list(1, nil, mapOf("k", true)); var b = fromHex("00ff");
+                        fun f() {}"#;
+        runner.run(source);
+        let (script, skipped) = save(runner.interpreter());
+        assert_eq!(skipped, ["f"]);
+        let mut restored = Runner::new();
+        assert!(restored.run(&script).success());
+        for name in ["n", "s", "l", "b"] {
+            let saved = runner.interpreter().global(name).unwrap();
+            let loaded = restored.interpreter().global(name).unwrap();
+            assert!(saved.deep_equals(&loaded), "{name}: {saved:?} != {loaded:?}");
+        }
+    }
+
+    #[test]
+    fn lists_holding_themselves_are_skipped() {
+        let mut runner = Runner::new();
+        runner.run("var l = list(); l.push(l); var ok = 1;");
+        let (script, skipped) = save(runner.interpreter());
+        assert_eq!(skipped, ["l"]);
+        assert!(script.contains("var ok = 1;\n// Not saved: l\n"), "{script}");
+    }
+}
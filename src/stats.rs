@@ -0,0 +1,92 @@
+use std::cell::Cell;
+use std::fmt::Display;
+
+/// What the interpreter allocates, counted on each thread as it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AllocationKind {
+    /// A string value, made by `+` or a native.
+    String,
+    List,
+    Map,
+    Bytes,
+    /// A host object wrapped for scripts.
+    Foreign,
+    /// A scope of variables, pushed by a block or a call.
+    Scope,
+    /// An instance of a class declared in Lox.
+    Instance,
+}
+
+impl AllocationKind {
+    pub const ALL: [AllocationKind; 7] = [
+        AllocationKind::String,
+        AllocationKind::List,
+        AllocationKind::Map,
+        AllocationKind::Bytes,
+        AllocationKind::Foreign,
+        AllocationKind::Scope,
+        AllocationKind::Instance,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AllocationKind::String => "string",
+            AllocationKind::List => "list",
+            AllocationKind::Map => "map",
+            AllocationKind::Bytes => "bytes",
+            AllocationKind::Foreign => "foreign",
+            AllocationKind::Scope => "scope",
+            AllocationKind::Instance => "instance",
+        }
+    }
+}
+
+impl Display for AllocationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Allocations of each kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Allocations([u64; AllocationKind::ALL.len()]);
+
+impl Allocations {
+    pub fn get(&self, kind: AllocationKind) -> u64 {
+        self.0[kind as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    /// The allocations made since `earlier` was taken.
+    pub fn since(&self, earlier: &Allocations) -> Allocations {
+        Allocations(std::array::from_fn(|i| self.0[i] - earlier.0[i]))
+    }
+
+    /// Adds `other` to these.
+    pub fn add(&mut self, other: &Allocations) {
+        for (count, other) in self.0.iter_mut().zip(other.0) {
+            *count += other;
+        }
+    }
+}
+
+thread_local! {
+    static ALLOCATIONS: Cell<Allocations> = const { Cell::new(Allocations([0; AllocationKind::ALL.len()])) };
+}
+
+/// Allocations made on this thread so far. Take two and subtract them with
+/// [`Allocations::since`] to count what a piece of code allocates.
+pub fn allocations() -> Allocations {
+    ALLOCATIONS.with(Cell::get)
+}
+
+pub(crate) fn count(kind: AllocationKind) {
+    ALLOCATIONS.with(|allocations| {
+        let mut counts = allocations.get();
+        counts.0[kind as usize] += 1;
+        allocations.set(counts);
+    });
+}
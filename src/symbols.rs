@@ -0,0 +1,192 @@
+use crate::parser::{Expr, FunctionDecl, Stmt};
+use crate::scanner::{Span, TokenInfo};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Module,
+    Function,
+    Class,
+}
+
+/// A name declared by `var`, `import`, `fun` or `class`, or a parameter.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Span of the name in the declaration.
+    pub span: Span,
+    pub line: usize,
+    /// Whether it was declared outside any block.
+    pub global: bool,
+}
+
+/// The declarations of a program and the names referring to them, for
+/// editor tooling like go-to-definition. Names are matched to declarations
+/// with the same scoping rules as the interpreter. Uses of a global before
+/// its declaration refer to its first declaration, since a REPL or later
+/// code may still define it first.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    /// Every declaration, in source order.
+    pub symbols: Vec<Symbol>,
+    /// Span of each name, declarations included, with the index of the
+    /// symbol it refers to.
+    references: Vec<(Span, usize)>,
+    scopes: Vec<HashMap<String, usize>>,
+    unresolved: Vec<TokenInfo>,
+}
+
+impl SymbolIndex {
+    pub fn new(statments: &[Stmt]) -> Self {
+        let mut index = SymbolIndex {
+            scopes: vec![HashMap::new()],
+            ..SymbolIndex::default()
+        };
+        for stmt in statments {
+            index.index_stmt(stmt);
+        }
+        for name in std::mem::take(&mut index.unresolved) {
+            let global = index
+                .symbols
+                .iter()
+                .position(|s| s.global && s.name == name.lexeme);
+            if let Some(symbol) = global {
+                index.references.push((name.span, symbol));
+            }
+        }
+        index.references.sort_by_key(|(span, _)| span.start);
+        index.scopes.clear();
+        index
+    }
+
+    /// The declaration of the name at character `offset`, which may be a
+    /// use of the name or the declaration itself.
+    pub fn definition_at(&self, offset: usize) -> Option<&Symbol> {
+        self.references
+            .iter()
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(_, symbol)| &self.symbols[*symbol])
+    }
+
+    fn index_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(e) | Stmt::Print(e) => self.index_expr(e),
+            Stmt::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.index_expr(initializer);
+                }
+                self.declare(name, SymbolKind::Variable);
+            }
+            Stmt::Block(statments) => {
+                self.scopes.push(HashMap::new());
+                for stmt in statments {
+                    self.index_stmt(stmt);
+                }
+                self.scopes.pop();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.index_expr(condition);
+                self.index_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.index_stmt(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.index_expr(condition);
+                self.index_stmt(body);
+            }
+            Stmt::Import(name) => self.declare(name, SymbolKind::Module),
+            Stmt::Function(function) => {
+                self.declare(&function.name, SymbolKind::Function);
+                self.index_function(function);
+            }
+            Stmt::Class { name, methods } => {
+                self.declare(name, SymbolKind::Class);
+                for method in methods {
+                    self.index_function(method);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.index_expr(value);
+                }
+            }
+        }
+    }
+
+    /// The parameters and body of a function or method.
+    fn index_function(&mut self, function: &FunctionDecl) {
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.declare(param, SymbolKind::Variable);
+        }
+        for stmt in &function.body {
+            self.index_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn index_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.index_expr(left);
+                self.index_expr(right);
+            }
+            Expr::Unary { right, .. } => self.index_expr(right),
+            Expr::Grouping(e) => self.index_expr(e),
+            Expr::Literal { .. } => {}
+            Expr::Variable { name, .. } => self.refer(name),
+            Expr::Assign { name, value, .. } => {
+                self.index_expr(value);
+                self.refer(name);
+            }
+            Expr::Get { object, .. } => self.index_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.index_expr(object);
+                self.index_expr(value);
+            }
+            Expr::This { .. } => {}
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.index_expr(callee);
+                for argument in arguments {
+                    self.index_expr(argument);
+                }
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &TokenInfo, kind: SymbolKind) {
+        let symbol = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: name.lexeme.clone(),
+            kind,
+            span: name.span,
+            line: name.line,
+            global: self.scopes.len() == 1,
+        });
+        self.references.push((name.span, symbol));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), symbol);
+        }
+    }
+
+    fn refer(&mut self, name: &TokenInfo) {
+        let symbol = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme));
+        match symbol {
+            Some(symbol) => self.references.push((name.span, *symbol)),
+            None => self.unresolved.push(name.clone()),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use rlox::diagnostic::{DiagnosticFormat, DiagnosticKind, DiagnosticRenderer};
+use rlox::interpreter::CapturedOutput;
+use rlox::messages;
+use rlox::Runner;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+const EXPECT_OUTPUT: &str = "// expect: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+/// Stack of each thread running tests, the main thread's on Linux, so
+/// tests nest as deeply as when run on their own.
+const WORKER_STACK_SIZE: usize = 8 << 20;
+
+/// Marks files of the upstream suite that aren't tests.
+const UPSTREAM_NONTEST: &str = "// nontest";
+
+/// Directories of the upstream Crafting Interpreters suite holding the
+/// tests each chapter of the book adds. Files at the top of the suite
+/// belong to the first one.
+const CHAPTERS: [(usize, &[&str]); 6] = [
+    (
+        8,
+        &[
+            "assignment",
+            "block",
+            "bool",
+            "comments",
+            "nil",
+            "number",
+            "operator",
+            "print",
+            "string",
+            "variable",
+        ],
+    ),
+    (9, &["for", "if", "logical_operator", "while"]),
+    (10, &["call", "function", "return"]),
+    (11, &["closure", "regression"]),
+    (12, &["class", "constructor", "field", "method", "this"]),
+    (13, &["inheritance", "super"]),
+];
+
+/// Directories of the upstream suite that jlox doesn't run either: they
+/// need a different harness or test limits of clox.
+const NOT_FOR_JLOX: [&str; 4] = ["benchmark", "expressions", "limit", "scanning"];
+
+/// What a test file says should happen, read from its `// expect` comments.
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    output: Vec<String>,
+    errors: Vec<String>,
+    runtime_error: Option<String>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Expectations {
+        let mut expectations = Expectations::default();
+        for (i, line) in source.lines().enumerate() {
+            if let Some(index) = line.find(EXPECT_OUTPUT) {
+                let value = &line[index + EXPECT_OUTPUT.len()..];
+                expectations.output.push(value.to_string());
+            } else if let Some(index) = line.find(EXPECT_ERROR) {
+                let message = &line[index + EXPECT_ERROR.len()..];
+                expectations
+                    .errors
+                    .push(format!("[line {}] {}", i + 1, message));
+            } else if let Some(index) = line.find(EXPECT_RUNTIME_ERROR) {
+                let message = &line[index + EXPECT_RUNTIME_ERROR.len()..];
+                expectations.runtime_error = Some(message.to_string());
+            }
+        }
+        expectations
+    }
+
+    /// Reads the comments of the upstream suite: `// expect: ` output,
+    /// compile errors as jlox prints them, `// Error at 'x': message` on the
+    /// line of the error or `// [line N] Error ...` for another line, and
+    /// `// expect runtime error: ` messages. Errors only clox reports,
+    /// marked `[c line N]`, are left out.
+    fn parse_upstream(source: &str) -> Expectations {
+        let mut expectations = Expectations::default();
+        for (i, line) in source.lines().enumerate() {
+            let Some(index) = line.find("// ") else {
+                continue;
+            };
+            let comment = &line[index + "// ".len()..];
+            if let Some(value) = comment.strip_prefix("expect:") {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                expectations.output.push(value.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some(format!("[line {}] {message}", i + 1));
+            } else if let Some(error) = upstream_error(comment, i + 1) {
+                expectations.errors.push(error);
+            }
+        }
+        expectations
+    }
+
+    /// What a jlox runner does with `source`, in the terms of
+    /// `parse_upstream`.
+    fn actual_upstream(source: &str) -> Expectations {
+        let mut actual = Expectations::default();
+        let output = CapturedOutput::default();
+        let mut runner = Runner::jlox();
+        runner.interpreter_mut().set_output(output.clone());
+        let result = runner.run(source);
+        let mut renderer = DiagnosticRenderer::new(source, "", false);
+        renderer.set_format(DiagnosticFormat::Jlox);
+        for d in result.diagnostics.iter().filter(|d| d.is_error()) {
+            match d.kind {
+                DiagnosticKind::Runtime => {
+                    let message = format!("[line {}] {}", d.line.unwrap_or(0), d.message);
+                    actual.runtime_error = Some(message);
+                }
+                _ => actual.errors.push(renderer.render(d)),
+            }
+        }
+        actual.output = output.contents().lines().map(str::to_string).collect();
+        actual
+    }
+
+    fn actual(source: &str) -> Expectations {
+        let mut actual = Expectations::default();
+        let output = CapturedOutput::default();
+        let mut runner = Runner::new();
+        runner.interpreter_mut().set_output(output.clone());
+        let result = runner.run(source);
+        for d in result.diagnostics.iter().filter(|d| d.is_error()) {
+            match d.kind {
+                DiagnosticKind::Runtime => actual.runtime_error = Some(d.message.clone()),
+                _ => actual
+                    .errors
+                    .push(format!("[line {}] {}", d.line.unwrap_or(0), d.message)),
+            }
+        }
+        actual.output = output.contents().lines().map(str::to_string).collect();
+        actual
+    }
+}
+
+/// The compile error an upstream comment expects, if it expects one jlox
+/// reports.
+fn upstream_error(comment: &str, line: usize) -> Option<String> {
+    let (line, message) = match comment.strip_prefix('[') {
+        Some(rest) => {
+            let (location, message) = rest.split_once("] ")?;
+            let location = location.strip_prefix("java ").unwrap_or(location);
+            (location.strip_prefix("line ")?.parse().ok()?, message)
+        }
+        None => (line, comment),
+    };
+    message
+        .starts_with("Error")
+        .then(|| format!("[line {line}] {message}"))
+}
+
+/// Runs every `.lox` file under `dir` on `jobs` threads and prints a
+/// summary, returning whether all of them passed.
+pub fn run_tests(dir: &Path, jobs: usize) -> bool {
+    let mut files = Vec::new();
+    if let Err(e) = collect_lox_files(dir, &mut files) {
+        eprintln!("Cant read test directory {}: {e}", dir.display());
+        return false;
+    }
+    files.sort();
+    let failed = check_files(&files, Expectations::parse, Expectations::actual, jobs);
+    println!(
+        "\n{} passed, {} failed, {} total",
+        files.len() - failed,
+        failed,
+        files.len()
+    );
+    failed == 0
+}
+
+/// Runs the upstream Crafting Interpreters suite in `dir` in jlox mode,
+/// only the tests of chapters up to `chapter` if given, leaving out
+/// `skips`, paths relative to `dir`, on `jobs` threads. Prints a summary
+/// with the share of tests run that passed, returning whether all of them
+/// did.
+pub fn run_suite(dir: &Path, chapter: Option<usize>, skips: &[String], jobs: usize) -> bool {
+    let mut files = Vec::new();
+    if let Err(e) = collect_lox_files(dir, &mut files) {
+        eprintln!("Cant read test directory {}: {e}", dir.display());
+        return false;
+    }
+    files.sort();
+    let mut skipped = 0;
+    files.retain(|file| {
+        let path = file.strip_prefix(dir).unwrap_or(file);
+        let top = match path.components().count() {
+            1 => None,
+            _ => path.iter().next().and_then(|name| name.to_str()),
+        };
+        if top.is_some_and(|top| NOT_FOR_JLOX.contains(&top)) {
+            return false;
+        }
+        let is_test = fs::read_to_string(file).map_or(true, |s| !s.contains(UPSTREAM_NONTEST));
+        let in_chapter = match (chapter, top) {
+            (None, _) => true,
+            (Some(chapter), None) => CHAPTERS[0].0 <= chapter,
+            (Some(chapter), Some(top)) => CHAPTERS
+                .iter()
+                .any(|(number, dirs)| *number <= chapter && dirs.contains(&top)),
+        };
+        let run = is_test && in_chapter && !skips.iter().any(|skip| path.starts_with(skip));
+        if is_test && in_chapter && !run {
+            skipped += 1;
+        }
+        run
+    });
+    let failed = check_files(
+        &files,
+        Expectations::parse_upstream,
+        Expectations::actual_upstream,
+        jobs,
+    );
+    let passed = files.len() - failed;
+    let conformance = if files.is_empty() {
+        0.0
+    } else {
+        passed as f64 * 100.0 / files.len() as f64
+    };
+    println!(
+        "\n{passed} passed, {failed} failed, {skipped} skipped, {conformance:.1}% conformance"
+    );
+    failed == 0
+}
+
+/// What checking a test file found.
+enum Outcome {
+    Passed,
+    Failed {
+        expected: Expectations,
+        actual: Expectations,
+    },
+    Unreadable(io::Error),
+}
+
+impl Outcome {
+    fn check(
+        file: &Path,
+        expected: fn(&str) -> Expectations,
+        actual: fn(&str) -> Expectations,
+    ) -> Outcome {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => return Outcome::Unreadable(e),
+        };
+        let expected = expected(&source);
+        let actual = actual(&source);
+        if expected == actual {
+            Outcome::Passed
+        } else {
+            Outcome::Failed { expected, actual }
+        }
+    }
+
+    /// Prints whether the test in `file` passed. Returns whether it did.
+    fn print(&self, file: &Path) -> bool {
+        match self {
+            Outcome::Passed => println!("PASS {}", file.display()),
+            Outcome::Failed { expected, actual } => {
+                println!("FAIL {}", file.display());
+                print_diff(expected, actual);
+            }
+            Outcome::Unreadable(e) => println!("FAIL {}: cant read file: {e}", file.display()),
+        }
+        matches!(self, Outcome::Passed)
+    }
+}
+
+/// Checks the files on `jobs` threads, each file with runners of its own,
+/// and prints whether each passed in the order of `files`. Returns how
+/// many failed.
+fn check_files(
+    files: &[PathBuf],
+    expected: fn(&str) -> Expectations,
+    actual: fn(&str) -> Expectations,
+    jobs: usize,
+) -> usize {
+    let next = AtomicUsize::new(0);
+    let catalog = messages::catalog();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            let sender = sender.clone();
+            let (next, catalog) = (&next, &catalog);
+            let worker = move || {
+                messages::set_catalog(catalog.clone());
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else {
+                        break;
+                    };
+                    let outcome = Outcome::check(file, expected, actual);
+                    if sender.send((index, outcome)).is_err() {
+                        break;
+                    }
+                }
+            };
+            thread::Builder::new()
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn_scoped(scope, worker)
+                .expect("Cant start a thread to run tests");
+        }
+        drop(sender);
+        // Tests finish in any order; each is printed once those before it
+        // are.
+        let mut outcomes: Vec<Option<Outcome>> = files.iter().map(|_| None).collect();
+        let mut printed = 0;
+        let mut failed = 0;
+        for (index, outcome) in receiver {
+            outcomes[index] = Some(outcome);
+            while let Some(outcome) = outcomes.get_mut(printed).and_then(Option::take) {
+                if !outcome.print(&files[printed]) {
+                    failed += 1;
+                }
+                printed += 1;
+            }
+        }
+        failed
+    })
+}
+
+fn collect_lox_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_lox_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn print_diff(expected: &Expectations, actual: &Expectations) {
+    print_lines_diff("output", &expected.output, &actual.output);
+    print_lines_diff("errors", &expected.errors, &actual.errors);
+    if expected.runtime_error != actual.runtime_error {
+        println!("  runtime error:");
+        println!(
+            "    - {}",
+            expected.runtime_error.as_deref().unwrap_or("<none>")
+        );
+        println!(
+            "    + {}",
+            actual.runtime_error.as_deref().unwrap_or("<none>")
+        );
+    }
+}
+
+fn print_lines_diff(title: &str, expected: &[String], actual: &[String]) {
+    if expected == actual {
+        return;
+    }
+    println!("  {title}:");
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => println!("      {e}"),
+            (e, a) => {
+                if let Some(e) = e {
+                    println!("    - {e}");
+                }
+                if let Some(a) = a {
+                    println!("    + {a}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expectations_match_what_runs() {
+        let source = "print 1; // expect: 1\nprint \"a\"; // expect: a\nnil(); \
+                      // expect runtime error: Can only call functions and classes.";
+        let expected = Expectations::parse(source);
+        assert_eq!(expected.output, ["1", "a"]);
+        assert_eq!(Expectations::actual(source), expected);
+    }
+
+    #[test]
+    fn compile_errors_expect_their_line() {
+        let source = "print 1;\nprint; // expect error: Expect expression.";
+        let expected = Expectations::parse(source);
+        assert_eq!(expected.errors, ["[line 2] Expect expression."]);
+        assert_eq!(Expectations::actual(source), expected);
+    }
+
+    #[test]
+    fn run_tests_fails_on_a_mismatch() {
+        let dir = std::env::temp_dir().join(format!("rlox-test-runner-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("pass.lox"), "print 1; // expect: 1").unwrap();
+        assert!(run_tests(&dir, 2));
+        fs::write(dir.join("nested/fail.lox"), "print 2; // expect: 3").unwrap();
+        let passed = run_tests(&dir, 2);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!passed);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::diagnostic::Diagnostic;
+use crate::runner::Runner;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of the golden file next to each script.
+pub const GOLDEN_EXTENSION: &str = "out";
+
+/// Environment variable that makes `check_dir` write the actual output as
+/// the golden files instead of comparing, to create or update them.
+pub const BLESS_VAR: &str = "RLOX_BLESS";
+
+/// What running `source` printed, followed by one `[line N] kind error:
+/// message` line per error. Warnings aren't part of it.
+pub fn transcript(runner: &mut Runner, source: &str) -> String {
+    let result = runner.run_captured(source);
+    let mut transcript = result.stdout;
+    for diagnostic in result.diagnostics.iter().filter(|d| d.is_error()) {
+        let _ = writeln!(transcript, "{}", error_line(diagnostic));
+    }
+    transcript
+}
+
+fn error_line(diagnostic: &Diagnostic) -> String {
+    match diagnostic.line {
+        Some(line) => format!(
+            "[line {line}] {} error: {}",
+            diagnostic.kind, diagnostic.message
+        ),
+        None => format!("{} error: {}", diagnostic.kind, diagnostic.message),
+    }
+}
+
+/// Runs `source` in a fresh runner and compares its transcript with
+/// `expected`.
+pub fn check_source(source: &str, expected: &str) -> Result<(), Mismatch> {
+    compare(None, expected, &transcript(&mut Runner::new(), source))
+}
+
+/// Compares two transcripts, ignoring `\r` before line breaks and a
+/// missing line break at the end.
+pub fn compare(path: Option<&Path>, expected: &str, actual: &str) -> Result<(), Mismatch> {
+    let expected = normalize(expected);
+    let actual = normalize(actual);
+    if expected == actual {
+        return Ok(());
+    }
+    Err(Mismatch {
+        path: path.map(Path::to_path_buf),
+        expected,
+        actual,
+    })
+}
+
+fn normalize(text: &str) -> String {
+    let mut normalized = text.replace("\r\n", "\n");
+    if !normalized.is_empty() && !normalized.ends_with('\n') {
+        normalized.push('\n');
+    }
+    normalized
+}
+
+/// Checks every `.lox` file under `dir` against the `.out` golden file
+/// next to it, running each in a runner from `new_runner` so embedders can
+/// register their own natives. Returns how many files were checked.
+///
+/// With `RLOX_BLESS` set, golden files are written instead.
+pub fn check_dir(
+    dir: impl AsRef<Path>,
+    mut new_runner: impl FnMut() -> Runner,
+) -> Result<usize, GoldenError> {
+    let dir = dir.as_ref();
+    let mut files = Vec::new();
+    collect_lox_files(dir, &mut files).map_err(|e| GoldenError::Io(dir.to_path_buf(), e))?;
+    files.sort();
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+    let mut mismatches = Vec::new();
+    for file in files.iter() {
+        let source = fs::read_to_string(file).map_err(|e| GoldenError::Io(file.clone(), e))?;
+        let actual = transcript(&mut new_runner(), &source);
+        let golden = file.with_extension(GOLDEN_EXTENSION);
+        if bless {
+            fs::write(&golden, &actual).map_err(|e| GoldenError::Io(golden.clone(), e))?;
+            continue;
+        }
+        let expected = match fs::read_to_string(&golden) {
+            Ok(expected) => expected,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(GoldenError::Missing(golden));
+            }
+            Err(e) => return Err(GoldenError::Io(golden, e)),
+        };
+        if let Err(mismatch) = compare(Some(file), &expected, &actual) {
+            mismatches.push(mismatch);
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(files.len())
+    } else {
+        Err(GoldenError::Mismatches(mismatches))
+    }
+}
+
+fn collect_lox_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_lox_files(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A transcript that differs from the expected one. Displays as a line
+/// diff, `-` for expected lines missing and `+` for unexpected ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The script, when it came from a file.
+    pub path: Option<PathBuf>,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => writeln!(f, "{}: output differs from the golden file", path.display())?,
+            None => writeln!(f, "output differs from the expected output")?,
+        }
+        let expected: Vec<&str> = self.expected.lines().collect();
+        let actual: Vec<&str> = self.actual.lines().collect();
+        for (marker, line) in diff(&expected, &actual) {
+            writeln!(f, "  {marker} {line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// The lines of both sides in order, each marked ` ` if both have it, `-`
+/// if only `expected` has it and `+` if only `actual` has it, keeping as
+/// many common lines as possible.
+fn diff<'a>(expected: &[&'a str], actual: &[&'a str]) -> Vec<(char, &'a str)> {
+    // common[i][j] is the longest common subsequence of expected[i..] and
+    // actual[j..].
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push((' ', expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(('-', expected[i]));
+            i += 1;
+        } else {
+            lines.push(('+', actual[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    /// Reading a script or golden file, or writing a golden file, failed.
+    Io(PathBuf, io::Error),
+    /// A script has no golden file.
+    Missing(PathBuf),
+    Mismatches(Vec<Mismatch>),
+}
+
+impl Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            GoldenError::Missing(path) => write!(
+                f,
+                "Missing golden file {}, set {BLESS_VAR}=1 to write it",
+                path.display()
+            ),
+            GoldenError::Mismatches(mismatches) => {
+                for mismatch in mismatches {
+                    write!(f, "{mismatch}")?;
+                }
+                write!(
+                    f,
+                    "{} golden file(s) differ, set {BLESS_VAR}=1 to update them",
+                    mismatches.len()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_source_compares_transcripts() {
+        let expected = "1\n[line 2] runtime error: Can only call functions and classes.";
+        assert!(check_source("print 1;\nnil();", expected).is_ok());
+        let mismatch = check_source("print 1; print 2;", "1\r\n3\r\n").unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            "output differs from the expected output\n    1\n  - 3\n  + 2\n"
+        );
+    }
+
+    #[test]
+    fn check_dir_reads_golden_files() {
+        let dir = std::env::temp_dir().join(format!("rlox-golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.lox"), "print 1;").unwrap();
+        let missing = check_dir(&dir, Runner::new);
+        fs::write(dir.join("a.out"), "1\n").unwrap();
+        let checked = check_dir(&dir, Runner::new);
+        fs::write(dir.join("a.out"), "2\n").unwrap();
+        let mismatched = check_dir(&dir, Runner::new);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(missing, Err(GoldenError::Missing(_))));
+        assert_eq!(checked.unwrap(), 1);
+        assert!(matches!(mismatched, Err(GoldenError::Mismatches(m)) if m.len() == 1));
+    }
+}
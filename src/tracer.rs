@@ -0,0 +1,59 @@
+//! `--trace`: prints each statement as it runs, then the value it computed.
+//! `--trace=expressions` also prints every expression evaluated on the way.
+
+use rlox::hook::Hook;
+use rlox::interpreter::{Interpreter, RuntimeError};
+use rlox::parser::{Expr, Stmt, Value};
+use std::collections::HashSet;
+
+pub struct Tracer {
+    source: Vec<String>,
+    expressions: bool,
+    /// The expression each statement seen so far is traced with: what an
+    /// expression or `print` statement evaluates, the initializer of a
+    /// `var` and the condition of an `if` or `while`. Only compared, as
+    /// the syntax tree outlives the run.
+    statement_values: HashSet<*const Expr>,
+}
+
+impl Tracer {
+    pub fn new(source: &str, expressions: bool) -> Self {
+        Tracer {
+            source: source.lines().map(str::to_string).collect(),
+            expressions,
+            statement_values: HashSet::new(),
+        }
+    }
+}
+
+impl Hook for Tracer {
+    fn before_statement(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        let value = match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr) => Some(expr),
+            Stmt::Return { value, .. } => value.as_ref(),
+            Stmt::Var { initializer, .. } => initializer.as_ref(),
+            Stmt::If { condition, .. } | Stmt::While { condition, .. } => Some(condition),
+            Stmt::Block(_) | Stmt::Import(_) | Stmt::Function(_) | Stmt::Class { .. } => None,
+        };
+        if let Some(value) = value {
+            self.statement_values.insert(value as *const Expr);
+        }
+        let text = self.source.get(line - 1).map_or("", |text| text.trim());
+        eprintln!("[line {line}] {text}");
+        Ok(())
+    }
+
+    fn after_expression(&mut self, _interpreter: &mut Interpreter, expr: &Expr, value: &Value) {
+        let line = expr.line();
+        if self.statement_values.contains(&(expr as *const Expr)) {
+            eprintln!("[line {line}] => {value:?}");
+        } else if self.expressions && !matches!(expr, Expr::Literal { .. } | Expr::Grouping(_)) {
+            eprintln!("[line {line}]   {expr} => {value:?}");
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Transcripts of REPL sessions, written by `:record` and run again by
+//! `rlox replay`. Each input is a line starting with `> `, with `| `
+//! before each further line of a multi-line input. With `:record --output`
+//! what an input printed follows it, each line starting with `< `. Other
+//! lines, like `#` comments, are ignored.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+const INPUT_PREFIX: &str = "> ";
+const CONTINUATION_PREFIX: &str = "| ";
+const OUTPUT_PREFIX: &str = "< ";
+
+/// Appends the inputs of a session, and optionally their output, to a
+/// transcript file.
+pub struct Recorder {
+    pub path: String,
+    file: File,
+    /// Output of the current input, when outputs are recorded.
+    output: Option<Rc<RefCell<Vec<u8>>>>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, with_output: bool) -> io::Result<Self> {
+        Ok(Recorder {
+            path: path.to_string(),
+            file: File::create(path)?,
+            output: with_output.then(Rc::default),
+        })
+    }
+
+    /// A writer for `print` output, which goes to stdout and, when outputs
+    /// are recorded, into the transcript.
+    pub fn stdout(&self) -> Tee {
+        Tee(self.output.clone())
+    }
+
+    pub fn record_input(&mut self, input: &str) -> io::Result<()> {
+        self.file.write_all(format_input(input).as_bytes())?;
+        self.file.flush()
+    }
+
+    /// Prints `text` shown by the REPL itself, like the value of an
+    /// expression, recording it with the output of the current input.
+    pub fn show(&mut self, text: &str) {
+        let _ = self.stdout().write_all(text.as_bytes());
+    }
+
+    /// Writes the output of the current input, if outputs are recorded.
+    pub fn finish_input(&mut self) -> io::Result<()> {
+        let Some(output) = &self.output else {
+            return Ok(());
+        };
+        let output = std::mem::take(&mut *output.borrow_mut());
+        let output = String::from_utf8_lossy(&output);
+        for line in output.lines() {
+            writeln!(self.file, "{OUTPUT_PREFIX}{line}")?;
+        }
+        self.file.flush()
+    }
+}
+
+/// `input` as transcript lines.
+pub fn format_input(input: &str) -> String {
+    let mut text = String::new();
+    for (i, line) in input.lines().enumerate() {
+        let prefix = if i == 0 {
+            INPUT_PREFIX
+        } else {
+            CONTINUATION_PREFIX
+        };
+        text.push_str(prefix);
+        text.push_str(line);
+        text.push('\n');
+    }
+    if text.is_empty() {
+        text.push_str(INPUT_PREFIX);
+        text.push('\n');
+    }
+    text
+}
+
+/// The inputs of a transcript, in order.
+pub fn parse(transcript: &str) -> Vec<String> {
+    let mut inputs: Vec<String> = Vec::new();
+    for line in transcript.lines() {
+        if let Some(input) = strip(line, INPUT_PREFIX) {
+            inputs.push(input.to_string());
+        } else if let Some(continuation) = strip(line, CONTINUATION_PREFIX) {
+            if let Some(input) = inputs.last_mut() {
+                input.push('\n');
+                input.push_str(continuation);
+            }
+        }
+    }
+    inputs
+}
+
+/// `line` without `prefix`, also matching the prefix without its trailing
+/// space when that is all the line has, as editors strip it.
+fn strip<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.strip_prefix(prefix)
+        .or_else(|| (line == prefix.trim_end()).then_some(""))
+}
+
+/// Writes to stdout and to the output of the current input.
+pub struct Tee(Option<Rc<RefCell<Vec<u8>>>>);
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = io::stdout().write(buf)?;
+        if let Some(output) = &self.0 {
+            output.borrow_mut().extend_from_slice(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_round_trip() {
+        let inputs = ["print 1;", "fun f() {\n  return 2;\n}", ""];
+        let transcript: String = inputs.iter().map(|input| format_input(input)).collect();
+        assert_eq!(transcript, "> print 1;\n> fun f() {\n|   return 2;\n| }\n> \n");
+        assert_eq!(parse(&transcript), inputs);
+    }
+
+    #[test]
+    fn parse_skips_output_and_comments() {
+        let transcript = "# a session\n> print 1;\n< 1\n>\n> var a\n|\n| = 2;\n";
+        assert_eq!(parse(transcript), ["print 1;", "", "var a\n\n= 2;"]);
+    }
+}
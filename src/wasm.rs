@@ -0,0 +1,38 @@
+use crate::diagnostic::DiagnosticRenderer;
+use crate::Runner;
+use wasm_bindgen::prelude::*;
+
+/// What a script printed and the diagnostics it produced, rendered as text.
+#[wasm_bindgen]
+pub struct RunOutput {
+    output: String,
+    errors: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl RunOutput {
+    #[wasm_bindgen(getter)]
+    pub fn output(&self) -> String {
+        self.output.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.clone()
+    }
+}
+
+/// Runs `source` in a fresh interpreter, for JavaScript callers.
+#[wasm_bindgen]
+pub fn run(source: &str) -> RunOutput {
+    let result = Runner::new().run_captured(source);
+    let renderer = DiagnosticRenderer::new(source, "<input>", false);
+    RunOutput {
+        output: result.stdout,
+        errors: result
+            .diagnostics
+            .iter()
+            .map(|d| renderer.render(d))
+            .collect(),
+    }
+}